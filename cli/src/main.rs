@@ -0,0 +1,359 @@
+#![deny(clippy::all, clippy::pedantic, clippy::nursery)]
+//! `bridge-cli`: drive a bridge flow by hand, with the same encryption and
+//! `request_id` derivation as the reference client.
+//!
+//! ```text
+//! bridge-cli create --data '{"action":"verify"}'      # prints request_id, key, deeplink
+//! bridge-cli fetch <request_id> --key <key>           # the other side reads it...
+//! bridge-cli respond <request_id> --key <key> --data proof
+//! bridge-cli poll <request_id> --key <key>            # ...and the first side waits
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::ExitCode,
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+use world_id_bridge_client::{crypto::Key, Backoff, BridgeClient, RequestStatus};
+
+const DEFAULT_BRIDGE_URL: &str = "https://bridge.worldcoin.org";
+const DEFAULT_VERIFY_URL: &str = "https://world.org/verify";
+
+#[derive(Parser)]
+#[command(version, about = "Command-line client for the World ID bridge")]
+struct Cli {
+    /// Base URL of the bridge to talk to.
+    #[arg(long, global = true, env = "BRIDGE_URL", default_value = DEFAULT_BRIDGE_URL)]
+    bridge_url: String,
+    /// Print machine-readable JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encrypt a payload and create a request for it.
+    Create {
+        #[command(flatten)]
+        input: Input,
+        /// Base64 key to encrypt with. A fresh key is generated if omitted.
+        #[arg(long, env = "BRIDGE_KEY")]
+        key: Option<String>,
+        /// Let the bridge pick the `request_id` instead of deriving it from
+        /// the key.
+        #[arg(long)]
+        random_id: bool,
+        /// Base URL the deeplink points at.
+        #[arg(long, default_value = DEFAULT_VERIFY_URL)]
+        verify_url: String,
+    },
+    /// Fetch and decrypt a request. Requests can only be fetched once.
+    Fetch {
+        request_id: String,
+        #[arg(long, env = "BRIDGE_KEY")]
+        key: String,
+    },
+    /// Encrypt a payload and submit it as the response to a request.
+    Respond {
+        request_id: String,
+        #[arg(long, env = "BRIDGE_KEY")]
+        key: String,
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Wait for the response to a request and decrypt it.
+    Poll {
+        request_id: String,
+        #[arg(long, env = "BRIDGE_KEY")]
+        key: String,
+        /// Give up after this many seconds.
+        #[arg(long, default_value_t = 900)]
+        timeout: u64,
+    },
+    /// Show the status of a request. A completed response is consumed, so
+    /// this needs `--key` to decrypt it, or `--consume` to discard it.
+    Status {
+        request_id: String,
+        #[arg(long, env = "BRIDGE_KEY")]
+        key: Option<String>,
+        /// Check the status without a key, discarding the response if there
+        /// is one.
+        #[arg(long, required_unless_present = "key")]
+        consume: bool,
+    },
+}
+
+/// Where a payload to encrypt comes from. Defaults to stdin.
+#[derive(Args)]
+#[group(multiple = false)]
+struct Input {
+    /// The payload, as a literal string.
+    #[arg(long)]
+    data: Option<String>,
+    /// Read the payload from a file.
+    #[arg(long)]
+    file: Option<PathBuf>,
+}
+
+impl Input {
+    fn read(self) -> Result<Vec<u8>, String> {
+        if let Some(data) = self.data {
+            return Ok(data.into_bytes());
+        }
+        if let Some(path) = self.file {
+            return std::fs::read(&path).map_err(|e| format!("reading {}: {e}", path.display()));
+        }
+
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| format!("reading stdin: {e}"))?;
+        Ok(data)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let json = cli.json;
+
+    match run(cli).await {
+        Ok(output) => {
+            output.print(json);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            if json {
+                println!("{}", json!({ "error": e }));
+            } else {
+                eprintln!("error: {e}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// What a command prints: labelled fields as text, or one JSON object.
+struct Output {
+    fields: Vec<(&'static str, Value)>,
+    /// Decrypted payload, written raw to stdout in text mode.
+    payload: Option<Vec<u8>>,
+}
+
+impl Output {
+    const fn new() -> Self {
+        Self {
+            fields: Vec::new(),
+            payload: None,
+        }
+    }
+
+    fn field(mut self, name: &'static str, value: impl Into<Value>) -> Self {
+        self.fields.push((name, value.into()));
+        self
+    }
+
+    fn payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    fn print(self, json: bool) {
+        if json {
+            let mut object: serde_json::Map<_, _> = self
+                .fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect();
+            if let Some(payload) = self.payload {
+                match String::from_utf8(payload) {
+                    Ok(text) => object.insert("payload".to_string(), text.into()),
+                    Err(e) => object.insert(
+                        "payload_base64".to_string(),
+                        STANDARD.encode(e.into_bytes()).into(),
+                    ),
+                };
+            }
+            println!("{}", Value::Object(object));
+            return;
+        }
+
+        for (name, value) in self.fields {
+            match value {
+                Value::String(text) => eprintln!("{name}: {text}"),
+                other => eprintln!("{name}: {other}"),
+            }
+        }
+        if let Some(payload) = self.payload {
+            let mut stdout = io::stdout().lock();
+            let _ = stdout.write_all(&payload).and_then(|()| stdout.flush());
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<Output, String> {
+    let client = BridgeClient::new(&cli.bridge_url);
+    let parse_key = |key: &str| Key::from_base64(key).map_err(|e| e.to_string());
+
+    match cli.command {
+        Command::Create {
+            input,
+            key,
+            random_id,
+            verify_url,
+        } => {
+            let key = match key {
+                Some(key) => parse_key(&key)?,
+                None => Key::generate().map_err(|e| e.to_string())?,
+            };
+            let payload = key.encrypt(&input.read()?).map_err(|e| e.to_string())?;
+            let request_id = (!random_id).then(|| key.request_id());
+
+            let request_id = client
+                .create_request(&payload, request_id.as_deref())
+                .await
+                .map_err(|e| e.to_string())?;
+            let deeplink = deeplink(&verify_url, &request_id, &key, &cli.bridge_url);
+
+            Ok(Output::new()
+                .field("request_id", request_id)
+                .field("key", key.to_base64())
+                .field("deeplink", deeplink))
+        }
+        Command::Fetch { request_id, key } => {
+            let key = parse_key(&key)?;
+            let payload = client
+                .fetch_request(&request_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let plaintext = key.decrypt(&payload).map_err(|e| e.to_string())?;
+
+            Ok(Output::new()
+                .field("request_id", request_id)
+                .payload(plaintext))
+        }
+        Command::Respond {
+            request_id,
+            key,
+            input,
+        } => {
+            let key = parse_key(&key)?;
+            let payload = key.encrypt(&input.read()?).map_err(|e| e.to_string())?;
+            client
+                .submit_response(&request_id, &payload)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            Ok(Output::new()
+                .field("request_id", request_id)
+                .field("status", RequestStatus::Completed.to_string()))
+        }
+        Command::Poll {
+            request_id,
+            key,
+            timeout,
+        } => {
+            let key = parse_key(&key)?;
+            let payload = client
+                .with_backoff(Backoff {
+                    timeout: Duration::from_secs(timeout),
+                    ..Backoff::default()
+                })
+                .await_response(&request_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let plaintext = key.decrypt(&payload).map_err(|e| e.to_string())?;
+
+            Ok(Output::new()
+                .field("request_id", request_id)
+                .field("status", RequestStatus::Completed.to_string())
+                .payload(plaintext))
+        }
+        Command::Status {
+            request_id, key, ..
+        } => {
+            let key = key.as_deref().map(parse_key).transpose()?;
+            let status = client
+                .response_status(&request_id)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            let output = Output::new()
+                .field("request_id", request_id)
+                .field("status", status.status.to_string());
+            match (status.response, key) {
+                (Some(payload), Some(key)) => {
+                    Ok(output.payload(key.decrypt(&payload).map_err(|e| e.to_string())?))
+                }
+                (Some(_), None) => Ok(output.field("note", "the response was discarded")),
+                (None, _) => Ok(output),
+            }
+        }
+    }
+}
+
+/// The link a wallet opens to pick up the request: `verify_url` with the
+/// `t/i/k/b` parameters the SDK uses. `b` (the bridge URL) is omitted for the
+/// default bridge.
+fn deeplink(verify_url: &str, request_id: &str, key: &Key, bridge_url: &str) -> String {
+    let mut link = format!(
+        "{verify_url}?t=wld&i={}&k={}",
+        percent_encode(request_id),
+        percent_encode(&key.to_base64())
+    );
+    let bridge_url = bridge_url.trim_end_matches('/');
+    if bridge_url != DEFAULT_BRIDGE_URL {
+        link.push_str("&b=");
+        link.push_str(&percent_encode(bridge_url));
+    }
+    link
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut out, b| {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(char::from(b));
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deeplink_encodes_key_and_custom_bridge() {
+        let key = Key::from_bytes([0xfb; 32]);
+        assert_eq!(
+            key.to_base64(),
+            "+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/v7+/s="
+        );
+
+        let link = deeplink(DEFAULT_VERIFY_URL, "abc", &key, DEFAULT_BRIDGE_URL);
+        assert_eq!(
+            link,
+            "https://world.org/verify?t=wld&i=abc&k=%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fs%3D"
+        );
+
+        let staging = deeplink(DEFAULT_VERIFY_URL, "abc", &key, "http://localhost:8000/");
+        assert!(staging.ends_with("&b=http%3A%2F%2Flocalhost%3A8000"));
+    }
+
+    #[test]
+    fn cli_parses() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,288 @@
+//! HTTP client for the request/response flow.
+
+use std::{fmt::Display, time::Duration};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Instant};
+
+use crate::types::{RequestPayload, RequestStatus, Response};
+
+/// How [`BridgeClient::await_response`] spaces out its polls.
+///
+/// Delays start at `initial` and double up to `max`. Transient failures
+/// (connection errors and 5xx responses) are retried on the same schedule.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Give up after this long. Defaults to the bridge's 15 minute TTL, after
+    /// which the request is gone anyway.
+    pub timeout: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(250),
+            max: Duration::from_secs(5),
+            timeout: Duration::from_secs(900),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request couldn't be sent or its response couldn't be read.
+    Http(reqwest::Error),
+    /// The request doesn't exist: it expired, was never created, or was
+    /// already consumed.
+    NotFound,
+    /// A request or response already exists under this ID.
+    Conflict,
+    /// The bridge answered with an unexpected status.
+    Status { status: StatusCode, body: String },
+    /// [`Backoff::timeout`] elapsed before a response arrived.
+    Timeout,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "request to the bridge failed: {e}"),
+            Self::NotFound => write!(f, "request not found"),
+            Self::Conflict => write!(f, "request ID already in use"),
+            Self::Status { status, body } if body.is_empty() => {
+                write!(f, "bridge returned {status}")
+            }
+            Self::Status { status, body } => write!(f, "bridge returned {status}: {body}"),
+            Self::Timeout => write!(f, "timed out waiting for a response"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl Error {
+    /// Whether retrying the same call might succeed.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Http(e) => e.is_connect() || e.is_timeout(),
+            Self::Status { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+/// Body of `POST /request` and `POST /response`.
+#[derive(Serialize)]
+struct CreateBody<'a> {
+    #[serde(flatten)]
+    payload: &'a RequestPayload,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Created {
+    request_id: String,
+}
+
+/// Client for a bridge deployment.
+#[derive(Debug, Clone)]
+pub struct BridgeClient {
+    http: reqwest::Client,
+    base_url: String,
+    backoff: Backoff,
+}
+
+impl BridgeClient {
+    /// Create a client for the bridge at `base_url` (e.g.
+    /// `https://bridge.worldcoin.org`).
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            backoff: Backoff::default(),
+        }
+    }
+
+    /// Use a preconfigured HTTP client (proxies, timeouts, extra headers).
+    #[must_use]
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /// `POST /request`. Returns the `request_id`: `request_id` if given,
+    /// otherwise one generated by the bridge.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Conflict`] if `request_id` is already in use.
+    pub async fn create_request(
+        &self,
+        payload: &RequestPayload,
+        request_id: Option<&str>,
+    ) -> Result<String, Error> {
+        let response = self
+            .http
+            .post(self.url("/request"))
+            .json(&CreateBody {
+                payload,
+                request_id,
+            })
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json::<Created>().await?.request_id)
+    }
+
+    /// `GET /request/:request_id`. Requests can only be fetched once.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the request doesn't exist or was already
+    /// fetched.
+    pub async fn fetch_request(&self, request_id: &str) -> Result<RequestPayload, Error> {
+        let response = self
+            .http
+            .get(self.url(&format!("/request/{request_id}")))
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// `PUT /response/:request_id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Conflict`] if the request already has a response, and
+    /// [`Error::Status`] with `400` if the request doesn't exist.
+    pub async fn submit_response(
+        &self,
+        request_id: &str,
+        payload: &RequestPayload,
+    ) -> Result<(), Error> {
+        let response = self
+            .http
+            .put(self.url(&format!("/response/{request_id}")))
+            .json(payload)
+            .send()
+            .await?;
+
+        check(response).await.map(drop)
+    }
+
+    /// `POST /response`: a standalone response with no prior request. Returns
+    /// the `request_id`: `request_id` if given, otherwise one generated by the
+    /// bridge.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Conflict`] if `request_id` is already in use, or an
+    /// error if the bridge rejects the payload.
+    pub async fn create_response(
+        &self,
+        payload: &RequestPayload,
+        request_id: Option<&str>,
+    ) -> Result<String, Error> {
+        let response = self
+            .http
+            .post(self.url("/response"))
+            .json(&CreateBody {
+                payload,
+                request_id,
+            })
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json::<Created>().await?.request_id)
+    }
+
+    /// `GET /response/:request_id`: the current status, and the response once
+    /// there is one. A returned response is consumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFound`] if the request doesn't exist.
+    pub async fn response_status(&self, request_id: &str) -> Result<Response, Error> {
+        let response = self
+            .http
+            .get(self.url(&format!("/response/{request_id}")))
+            .send()
+            .await?;
+
+        Ok(check(response).await?.json().await?)
+    }
+
+    /// Poll `GET /response/:request_id` with backoff until the response
+    /// arrives.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Timeout`] once [`Backoff::timeout`] elapses, or the
+    /// first non-transient error (e.g. [`Error::NotFound`] if the request
+    /// expires).
+    pub async fn await_response(&self, request_id: &str) -> Result<RequestPayload, Error> {
+        let deadline = Instant::now() + self.backoff.timeout;
+        let mut delay = self.backoff.initial;
+
+        loop {
+            match self.response_status(request_id).await {
+                Ok(Response {
+                    status: RequestStatus::Completed,
+                    response: Some(payload),
+                    ..
+                }) => return Ok(payload),
+                Ok(_) => {}
+                Err(e) if e.is_transient() => {}
+                Err(e) => return Err(e),
+            }
+
+            if Instant::now() + delay > deadline {
+                return Err(Error::Timeout);
+            }
+            sleep(delay).await;
+            delay = (delay * 2).min(self.backoff.max);
+        }
+    }
+}
+
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND => Err(Error::NotFound),
+        StatusCode::CONFLICT => Err(Error::Conflict),
+        status => Err(Error::Status {
+            status,
+            body: response.text().await.unwrap_or_default(),
+        }),
+    }
+}
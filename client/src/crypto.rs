@@ -0,0 +1,238 @@
+//! Reference encryption for bridge payloads.
+//!
+//! The bridge never sees plaintext: both parties share a key off-band (e.g. in
+//! a deeplink) and exchange [`RequestPayload`]s produced here.
+//!
+//! - Keys are 32 random bytes, shared as standard padded base64.
+//! - Payloads are AES-256-GCM with a random 96-bit nonce as the `iv` and the
+//!   ciphertext followed by the 16-byte tag as the `payload`, both base64 on
+//!   the JSON wire. No associated data.
+//! - The `request_id` is the lowercase hex of 32 bytes of HKDF-SHA256 over the
+//!   key (empty salt, info [`REQUEST_ID_INFO`]), so knowing the key is enough
+//!   to find the request and the ID alone reveals nothing about the key.
+
+use std::fmt::{Debug, Display, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::types::RequestPayload;
+
+/// Length of a key in bytes.
+pub const KEY_LEN: usize = 32;
+
+/// HKDF `info` for deriving a `request_id` from a key.
+pub const REQUEST_ID_INFO: &[u8] = b"world-id-bridge request_id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptoError {
+    /// The key isn't 32 bytes of standard base64.
+    InvalidKey,
+    /// The payload's `iv` or `payload` isn't base64, or the IV isn't 12 bytes.
+    Malformed,
+    /// The payload wasn't encrypted under this key or was tampered with.
+    Decrypt,
+    /// The system RNG failed.
+    Rng,
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidKey => write!(f, "key must be 32 bytes of base64"),
+            Self::Malformed => write!(f, "payload is not a base64 AES-GCM ciphertext"),
+            Self::Decrypt => write!(f, "payload failed to decrypt"),
+            Self::Rng => write!(f, "failed to generate random bytes"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// A symmetric key shared by the two parties of a request.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; KEY_LEN]);
+
+/// Keys never show up in logs.
+impl Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+impl Key {
+    /// Generate a fresh random key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CryptoError::Rng`] if the system RNG fails.
+    pub fn generate() -> Result<Self, CryptoError> {
+        let mut key = [0u8; KEY_LEN];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| CryptoError::Rng)?;
+        Ok(Self(key))
+    }
+
+    #[must_use]
+    pub const fn from_bytes(key: [u8; KEY_LEN]) -> Self {
+        Self(key)
+    }
+
+    /// Parse a key from its shared base64 form.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CryptoError::InvalidKey`] unless `key` is standard base64 of
+    /// exactly 32 bytes.
+    pub fn from_base64(key: &str) -> Result<Self, CryptoError> {
+        STANDARD
+            .decode(key.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(Self)
+            .ok_or(CryptoError::InvalidKey)
+    }
+
+    /// The key in its shared base64 form.
+    #[must_use]
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.0)
+    }
+
+    /// The `request_id` derived from this key.
+    ///
+    /// # Panics
+    ///
+    /// Never: 32 bytes is well within HKDF-SHA256's output limit.
+    #[must_use]
+    pub fn request_id(&self) -> String {
+        let mut okm = [0u8; 32];
+        Salt::new(HKDF_SHA256, &[])
+            .extract(&self.0)
+            .expand(&[REQUEST_ID_INFO], HKDF_SHA256)
+            .and_then(|okm_material| okm_material.fill(&mut okm))
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+        okm.iter().fold(String::with_capacity(64), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+    }
+
+    /// Encrypt `plaintext` under a fresh random IV.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CryptoError::Rng`] if the system RNG fails.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<RequestPayload, CryptoError> {
+        let mut iv = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut iv)
+            .map_err(|_| CryptoError::Rng)?;
+        Ok(self.encrypt_with_iv(iv, plaintext))
+    }
+
+    /// Decrypt a payload produced by [`Key::encrypt`] (or any client following
+    /// the same construction).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CryptoError::Malformed`] if the fields aren't base64 or the IV
+    /// has the wrong length, and [`CryptoError::Decrypt`] if authentication
+    /// fails.
+    pub fn decrypt(&self, payload: &RequestPayload) -> Result<Vec<u8>, CryptoError> {
+        let iv = payload.iv().as_bytes().ok_or(CryptoError::Malformed)?;
+        let nonce = Nonce::try_assume_unique_for_key(iv).map_err(|_| CryptoError::Malformed)?;
+        let mut buffer = payload
+            .payload()
+            .as_bytes()
+            .ok_or(CryptoError::Malformed)?
+            .to_vec();
+
+        let plaintext = self
+            .cipher()
+            .open_in_place(nonce, Aad::empty(), &mut buffer)
+            .map_err(|_| CryptoError::Decrypt)?;
+        Ok(plaintext.to_vec())
+    }
+
+    fn encrypt_with_iv(&self, iv: [u8; NONCE_LEN], plaintext: &[u8]) -> RequestPayload {
+        let mut buffer = plaintext.to_vec();
+        self.cipher()
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut buffer)
+            .expect("payload is within AES-GCM's length limit");
+
+        RequestPayload::new(iv.to_vec(), buffer)
+    }
+
+    fn cipher(&self) -> LessSafeKey {
+        LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, &self.0).expect("key has the AES-256 length"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generated independently with Python's `cryptography` package.
+    const VECTOR_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    const VECTOR_IV: &str = "AAECAwQFBgcICQoL";
+    const VECTOR_PLAINTEXT: &[u8] = b"world id bridge";
+    const VECTOR_PAYLOAD: &str = "MG2kd6HFq3+tI+Xi1Y4d2DsKjbNtxjCC9bNTdk4cOA==";
+    const VECTOR_REQUEST_ID: &str =
+        "aacb073f1f45e831d000e4627668b349ee2b122bfc69c1a0ac1247c7c8e63006";
+
+    fn vector_key() -> Key {
+        Key::from_base64(VECTOR_KEY).unwrap()
+    }
+
+    #[test]
+    fn matches_test_vectors() {
+        let key = vector_key();
+        let iv: [u8; NONCE_LEN] = STANDARD.decode(VECTOR_IV).unwrap().try_into().unwrap();
+
+        let payload = key.encrypt_with_iv(iv, VECTOR_PLAINTEXT);
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            serde_json::json!({"iv": VECTOR_IV, "payload": VECTOR_PAYLOAD})
+        );
+        assert_eq!(key.request_id(), VECTOR_REQUEST_ID);
+
+        let wire = RequestPayload::new(VECTOR_IV.to_string(), VECTOR_PAYLOAD.to_string());
+        assert_eq!(key.decrypt(&wire).unwrap(), VECTOR_PLAINTEXT);
+    }
+
+    #[test]
+    fn round_trips_with_fresh_ivs() {
+        let key = Key::generate().unwrap();
+        let first = key.encrypt(b"hello").unwrap();
+        let second = key.encrypt(b"hello").unwrap();
+
+        assert_ne!(first.iv(), second.iv());
+        assert_eq!(key.decrypt(&first).unwrap(), b"hello");
+        assert_eq!(Key::from_base64(&key.to_base64()).unwrap(), key);
+    }
+
+    #[test]
+    fn rejects_wrong_key_and_bad_input() {
+        let payload = vector_key().encrypt(b"secret").unwrap();
+        let other = Key::generate().unwrap();
+        assert_eq!(other.decrypt(&payload), Err(CryptoError::Decrypt));
+
+        let malformed = RequestPayload::new("not base64".to_string(), "AAAA".to_string());
+        assert_eq!(
+            vector_key().decrypt(&malformed),
+            Err(CryptoError::Malformed)
+        );
+
+        assert_eq!(Key::from_base64("c2hvcnQ="), Err(CryptoError::InvalidKey));
+        assert_eq!(format!("{:?}", vector_key()), "Key(..)");
+    }
+}
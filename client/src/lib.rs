@@ -0,0 +1,21 @@
+#![deny(clippy::all, clippy::pedantic, clippy::nursery)]
+//! Client for the World ID bridge.
+//!
+//! [`types`] holds the wire types the server itself uses, so the two can't
+//! drift apart. With the default `client` feature, [`BridgeClient`] wraps the
+//! request/response flow in typed calls, and with `crypto`, [`crypto::Key`]
+//! produces and opens payloads the way every client should. With `webhook`,
+//! [`webhook::verify`] checks the signature on a webhook delivery.
+
+pub mod types;
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+#[cfg(feature = "client")]
+pub use client::{Backoff, BridgeClient, Error};
+pub use types::{PayloadField, RequestPayload, RequestStatus, Response};
@@ -0,0 +1,226 @@
+//! Wire types shared by the bridge server and its clients.
+
+use std::{fmt::Display, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum RequestStatus {
+    /// The request has been initiated by the client
+    Initialized,
+    /// The request has been retrieved
+    Retrieved,
+    /// The request has received a response
+    Completed,
+}
+
+impl Display for RequestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Retrieved => write!(f, "retrieved"),
+            Self::Completed => write!(f, "completed"),
+            Self::Initialized => write!(f, "initialized"),
+        }
+    }
+}
+
+impl FromStr for RequestStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "initialized" => Ok(Self::Initialized),
+            "retrieved" => Ok(Self::Retrieved),
+            "completed" => Ok(Self::Completed),
+            _ => Err(format!("Invalid status: {s}")),
+        }
+    }
+}
+
+/// One field of a [`RequestPayload`].
+///
+/// JSON clients send base64 strings while binary clients send raw bytes.
+/// Canonical base64 is decoded on the way in so the stored form is compact, and
+/// re-encoded to the identical string for JSON clients on the way out. Any
+/// other string (the bridge doesn't require base64 unless validation is on) is
+/// kept verbatim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadField {
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+impl PayloadField {
+    /// The raw bytes, or `None` if the field was sent as a non-base64 string.
+    #[must_use]
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            Self::Text(_) => None,
+        }
+    }
+
+    /// Take the raw bytes, or `None` if the field was sent as a non-base64 string.
+    #[must_use]
+    pub fn into_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            Self::Text(_) => None,
+        }
+    }
+}
+
+impl From<String> for PayloadField {
+    fn from(value: String) -> Self {
+        // The standard engine only accepts canonical padded base64, so a
+        // successful decode always re-encodes to exactly `value`.
+        STANDARD
+            .decode(&value)
+            .map_or(Self::Text(value), Self::Bytes)
+    }
+}
+
+impl From<Vec<u8>> for PayloadField {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl Serialize for PayloadField {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Bytes(bytes) if serializer.is_human_readable() => {
+                serializer.serialize_str(&STANDARD.encode(bytes))
+            }
+            Self::Bytes(bytes) => serializer.serialize_bytes(bytes),
+            Self::Text(text) => serializer.serialize_str(text),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PayloadField {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl Visitor<'_> for FieldVisitor {
+            type Value = PayloadField;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a string or byte string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(PayloadField::from(v.to_string()))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, v: String) -> Result<Self::Value, E> {
+                Ok(PayloadField::from(v))
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(PayloadField::Bytes(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(PayloadField::Bytes(v))
+            }
+        }
+
+        deserializer.deserialize_any(FieldVisitor)
+    }
+}
+
+/// Documented as a plain string: binary encodings are described per route.
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for PayloadField {
+    fn schema_name() -> String {
+        String::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct RequestPayload {
+    /// The initialization vector for the encrypted payload
+    iv: PayloadField,
+    /// The encrypted payload
+    payload: PayloadField,
+}
+
+impl RequestPayload {
+    #[must_use]
+    pub fn new(iv: impl Into<PayloadField>, payload: impl Into<PayloadField>) -> Self {
+        Self {
+            iv: iv.into(),
+            payload: payload.into(),
+        }
+    }
+
+    #[must_use]
+    pub const fn iv(&self) -> &PayloadField {
+        &self.iv
+    }
+
+    #[must_use]
+    pub const fn payload(&self) -> &PayloadField {
+        &self.payload
+    }
+
+    #[must_use]
+    pub fn into_parts(self) -> (PayloadField, PayloadField) {
+        (self.iv, self.payload)
+    }
+}
+
+/// Body of `GET /response/:request_id`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct Response {
+    pub status: RequestStatus,
+    pub response: Option<RequestPayload>,
+    /// For requests created with `max_responses`: the responses that arrived
+    /// since the last poll, in order. Each is returned once.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub responses: Vec<RequestPayload>,
+    /// For requests created with `max_reads`: how many times the request has
+    /// been read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reads: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_fields_keep_their_json_form() {
+        let payload = RequestPayload::new("AAAAAAAAAAAAAAAA".to_string(), "not base64".to_string());
+        assert_eq!(payload.iv().as_bytes(), Some(&[0u8; 12][..]));
+        assert!(payload.payload().as_bytes().is_none());
+
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            serde_json::json!({"iv": "AAAAAAAAAAAAAAAA", "payload": "not base64"})
+        );
+    }
+
+    #[test]
+    fn response_status_round_trips() {
+        let pending: Response =
+            serde_json::from_str(r#"{"status":"retrieved","response":null}"#).unwrap();
+        assert_eq!(pending.status, RequestStatus::Retrieved);
+        assert!(pending.response.is_none());
+        assert_eq!("completed".parse(), Ok(RequestStatus::Completed));
+    }
+}
@@ -0,0 +1,253 @@
+//! Signing and verification of bridge webhooks.
+//!
+//! The bridge signs every webhook delivery in the [`SIGNATURE_HEADER`], as
+//! `t=<unix seconds>,v1=<hex>[,v1=<hex>...]`:
+//!
+//! - Each `v1` is the lowercase hex HMAC-SHA256 of `{t}.{request_id}.{body}`
+//!   under one of the app's secrets, where `request_id` is the value of the
+//!   [`REQUEST_ID_HEADER`] and `body` the raw request body.
+//! - While a secret is being rotated the bridge signs with every configured
+//!   secret, so receivers holding either the old or the new one accept it.
+//! - `t` is when the delivery attempt was signed. Receivers reject signatures
+//!   outside a tolerance window, so a captured delivery can't be replayed
+//!   later. Within the window, a replay is indistinguishable from a retry, so
+//!   receivers should handle a `request_id` at most once.
+//!
+//! RP backends should call [`verify`] with the raw body before parsing it.
+
+use std::{
+    fmt::{Display, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ring::hmac;
+
+/// Carries the signature of a webhook delivery.
+pub const SIGNATURE_HEADER: &str = "bridge-signature";
+/// Carries the `request_id` a webhook delivery belongs to.
+pub const REQUEST_ID_HEADER: &str = "bridge-request-id";
+
+/// How far a signature's timestamp may be from the receiver's clock, in either
+/// direction, unless the receiver picks its own window.
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookError {
+    /// The header isn't `t=...,v1=...`.
+    Malformed,
+    /// The timestamp is outside the tolerance window.
+    Expired,
+    /// No signature matches any of the secrets.
+    Mismatch,
+}
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "webhook signature header is malformed"),
+            Self::Expired => write!(f, "webhook signature is outside the tolerance window"),
+            Self::Mismatch => write!(f, "webhook signature does not match"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+/// Sign a delivery at `timestamp` (unix seconds) with every secret, returning
+/// the [`SIGNATURE_HEADER`] value.
+pub fn sign<S: AsRef<[u8]>>(
+    secrets: &[S],
+    request_id: &str,
+    body: &[u8],
+    timestamp: u64,
+) -> String {
+    secrets
+        .iter()
+        .fold(format!("t={timestamp}"), |mut header, secret| {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref());
+            let tag = hmac::sign(&key, &signed_content(timestamp, request_id, body));
+            header.push_str(",v1=");
+            for b in tag.as_ref() {
+                let _ = write!(header, "{b:02x}");
+            }
+            header
+        })
+}
+
+/// Verify a delivery's [`SIGNATURE_HEADER`] against the receiver's secrets
+/// (several while rotating), rejecting timestamps more than `tolerance` away
+/// from now.
+///
+/// # Errors
+///
+/// See [`WebhookError`].
+pub fn verify<S: AsRef<[u8]>>(
+    header: &str,
+    request_id: &str,
+    body: &[u8],
+    secrets: &[S],
+    tolerance: Duration,
+) -> Result<(), WebhookError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    verify_at(header, request_id, body, secrets, tolerance, now)
+}
+
+/// [`verify`] against an explicit clock (unix seconds).
+///
+/// # Errors
+///
+/// See [`WebhookError`].
+pub fn verify_at<S: AsRef<[u8]>>(
+    header: &str,
+    request_id: &str,
+    body: &[u8],
+    secrets: &[S],
+    tolerance: Duration,
+    now: u64,
+) -> Result<(), WebhookError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => {
+                timestamp = Some(value.parse::<u64>().map_err(|_| WebhookError::Malformed)?);
+            }
+            Some(("v1", value)) => signatures.push(decode_hex(value)?),
+            // Other schemes are ignored, so new ones can be added alongside v1.
+            Some(_) => {}
+            None => return Err(WebhookError::Malformed),
+        }
+    }
+    let timestamp = timestamp.ok_or(WebhookError::Malformed)?;
+    if signatures.is_empty() {
+        return Err(WebhookError::Malformed);
+    }
+
+    if timestamp.abs_diff(now) > tolerance.as_secs() {
+        return Err(WebhookError::Expired);
+    }
+
+    let content = signed_content(timestamp, request_id, body);
+    let matches = secrets.iter().any(|secret| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_ref());
+        signatures
+            .iter()
+            .any(|signature| hmac::verify(&key, &content, signature).is_ok())
+    });
+    if matches {
+        Ok(())
+    } else {
+        Err(WebhookError::Mismatch)
+    }
+}
+
+fn signed_content(timestamp: u64, request_id: &str, body: &[u8]) -> Vec<u8> {
+    let mut content = format!("{timestamp}.{request_id}.").into_bytes();
+    content.extend_from_slice(body);
+    content
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, WebhookError> {
+    if value.len() % 2 != 0 {
+        return Err(WebhookError::Malformed);
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            value
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(WebhookError::Malformed)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const NEW: &[u8] = b"fedcba9876543210fedcba9876543210";
+    const BODY: &[u8] = br#"{"iv":"ri","payload":"rp"}"#;
+
+    #[test]
+    fn signs_with_every_secret() {
+        // HMAC-SHA256(OLD, "1700000000.abc.{}")
+        assert_eq!(
+            sign(&[OLD], "abc", b"{}", 1_700_000_000),
+            "t=1700000000,v1=a77684f3233bbd5fbe8998b9155eb19461cc428d1ac6aa451f35bea7ca9779b9"
+        );
+
+        let header = sign(&[NEW, OLD], "abc", BODY, 1_700_000_000);
+        assert_eq!(header.matches(",v1=").count(), 2);
+        for secret in [OLD, NEW] {
+            assert_eq!(
+                verify_at(
+                    &header,
+                    "abc",
+                    BODY,
+                    &[secret],
+                    DEFAULT_TOLERANCE,
+                    1_700_000_000
+                ),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_tampering_and_replays() {
+        let header = sign(&[OLD], "abc", BODY, 1_700_000_000);
+        let check = |header: &str, request_id: &str, body: &[u8], now: u64| {
+            verify_at(header, request_id, body, &[OLD], DEFAULT_TOLERANCE, now)
+        };
+
+        assert_eq!(check(&header, "abc", BODY, 1_700_000_300), Ok(()));
+        assert_eq!(check(&header, "abc", BODY, 1_699_999_700), Ok(()));
+        assert_eq!(
+            check(&header, "abc", BODY, 1_700_000_301),
+            Err(WebhookError::Expired)
+        );
+        assert_eq!(
+            check(&header, "abd", BODY, 1_700_000_000),
+            Err(WebhookError::Mismatch)
+        );
+        assert_eq!(
+            check(&header, "abc", b"{}", 1_700_000_000),
+            Err(WebhookError::Mismatch)
+        );
+        assert_eq!(
+            verify_at(
+                &header,
+                "abc",
+                BODY,
+                &[NEW],
+                DEFAULT_TOLERANCE,
+                1_700_000_000
+            ),
+            Err(WebhookError::Mismatch)
+        );
+
+        let retimed = header.replace("t=1700000000", "t=1700000100");
+        assert_eq!(
+            check(&retimed, "abc", BODY, 1_700_000_100),
+            Err(WebhookError::Mismatch)
+        );
+
+        for malformed in [
+            "",
+            "v1=00",
+            "t=1700000000",
+            "t=x,v1=00",
+            "t=1700000000,v1=0",
+        ] {
+            assert_eq!(
+                check(malformed, "abc", BODY, 1_700_000_000),
+                Err(WebhookError::Malformed),
+                "{malformed}"
+            );
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Structured audit events for request lifecycle transitions.
+//!
+//! Every status change is also emitted as an [`AuditEvent`] to a configurable
+//! sink, so analytics can measure funnel drop-off without scraping logs. Events
+//! never carry payload content, only its size, and identify requests by a keyed
+//! hash of the `request_id`. The ID itself is the capability to read the
+//! payload, so it must not end up in an analytics store.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    net::IpAddr,
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use redis::aio::ConnectionManager;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::redact;
+use crate::utils::RequestStatus;
+
+/// Entries kept in a Redis stream sink; older ones are trimmed as new ones
+/// arrive.
+const STREAM_MAX_LEN: usize = 100_000;
+
+/// Lines queued for a file sink's writer before new events are dropped.
+const FILE_QUEUE: usize = 10_000;
+
+/// One lifecycle transition of a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// [`redact::request_hash`] of the `request_id`, stable for the lifetime
+    /// of the hash key.
+    pub request_hash: String,
+    /// The previous status, or `None` for a request that didn't exist yet.
+    pub from: Option<RequestStatus>,
+    pub to: RequestStatus,
+    /// Method and matched route, e.g. `PUT /response/:request_id`.
+    pub route: String,
+    /// Unix time in milliseconds.
+    pub timestamp_ms: u64,
+    /// Size of the payload involved in the transition.
+    pub payload_bytes: Option<u64>,
+    pub client_ip: IpClass,
+}
+
+/// The kind of address a request came from, which is all an event records of
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpClass {
+    Loopback,
+    /// RFC 1918, link-local and unique-local addresses.
+    Private,
+    Public,
+    /// No address was available.
+    Unknown,
+}
+
+impl IpClass {
+    #[must_use]
+    pub fn of(ip: Option<IpAddr>) -> Self {
+        let Some(ip) = ip.map(|ip| ip.to_canonical()) else {
+            return Self::Unknown;
+        };
+        if ip.is_loopback() {
+            return Self::Loopback;
+        }
+
+        let private = match ip {
+            IpAddr::V4(ip) => ip.is_private() || ip.is_link_local(),
+            IpAddr::V6(ip) => {
+                let first = ip.segments()[0];
+                // fc00::/7 (unique local) and fe80::/10 (link-local).
+                first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+            }
+        };
+        if private {
+            Self::Private
+        } else {
+            Self::Public
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+enum Sink {
+    #[default]
+    Off,
+    Stdout,
+    /// JSON lines queued for the thread appending them to a file, so a slow
+    /// disk never blocks the runtime.
+    File(SyncSender<String>),
+    /// Entries with a single `event` field holding the JSON.
+    Redis(String),
+}
+
+/// Where audit events go, and the key request IDs are hashed with.
+///
+/// Built once at startup from `AUDIT_SINK` and `AUDIT_HASH_KEY` (see
+/// [`Audit::from_config`]). The default value is off.
+pub struct Audit {
+    sink: Sink,
+    key: hmac::Key,
+}
+
+impl Default for Audit {
+    fn default() -> Self {
+        Self {
+            sink: Sink::Off,
+            key: hmac::Key::new(hmac::HMAC_SHA256, &[]),
+        }
+    }
+}
+
+/// The hash key never shows up in logs.
+impl std::fmt::Debug for Audit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Audit")
+            .field("sink", &self.sink)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Audit {
+    /// Build the sink from its config representation: `stdout`,
+    /// `file:<path>` (appended to as JSON lines) or `redis:<stream key>`.
+    /// `hash_key` is the base64 HMAC key (at least 32 bytes) request IDs are
+    /// hashed with. Without one, a random key is generated, so hashes only
+    /// correlate within one process.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the sink is unknown, the file
+    /// can't be opened, or the key is not valid base64 or too short.
+    pub fn from_config(sink: &str, hash_key: Option<&str>) -> Result<Self, String> {
+        let sink = match sink.trim().split_once(':') {
+            None if sink.trim() == "stdout" => Sink::Stdout,
+            Some(("file", path)) if !path.is_empty() => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(|e| format!("failed to open audit file {path}: {e}"))?;
+                Sink::File(spawn_file_writer(file)?)
+            }
+            Some(("redis", stream)) if !stream.is_empty() => Sink::Redis(stream.to_string()),
+            _ => return Err(format!("unknown audit sink {sink:?}")),
+        };
+
+        Ok(Self {
+            sink,
+            key: redact::hash_key(hash_key)?,
+        })
+    }
+
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        !matches!(self.sink, Sink::Off)
+    }
+
+    /// The [`AuditEvent::request_hash`] of `request_id`.
+    #[must_use]
+    pub fn request_hash(&self, request_id: &str) -> String {
+        redact::request_hash(&self.key, request_id)
+    }
+
+    /// Build the event for a transition happening now.
+    #[must_use]
+    pub fn event(
+        &self,
+        request_id: &str,
+        from: Option<RequestStatus>,
+        to: RequestStatus,
+        route: String,
+        payload_bytes: Option<u64>,
+        client_ip: IpClass,
+    ) -> AuditEvent {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| u64::try_from(now.as_millis()).unwrap_or(u64::MAX));
+
+        AuditEvent {
+            request_hash: self.request_hash(request_id),
+            from,
+            to,
+            route,
+            timestamp_ms,
+            payload_bytes,
+            client_ip,
+        }
+    }
+
+    /// Write `event` to the sink. Best-effort: failures are logged, never
+    /// returned, so auditing can't fail a request.
+    pub async fn emit(&self, redis: &mut ConnectionManager, event: &AuditEvent) {
+        if !self.is_enabled() {
+            return;
+        }
+        let line = match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to encode audit event: {e}");
+                return;
+            }
+        };
+
+        let written = match &self.sink {
+            Sink::Off => Ok(()),
+            Sink::Stdout => {
+                println!("{line}");
+                Ok(())
+            }
+            Sink::File(lines) => lines.try_send(line).map_err(|e| match e {
+                TrySendError::Full(_) => "the file writer is behind".to_string(),
+                TrySendError::Disconnected(_) => "the file writer stopped".to_string(),
+            }),
+            Sink::Redis(stream) => redis::cmd("XADD")
+                .arg(stream)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(STREAM_MAX_LEN)
+                .arg("*")
+                .arg("event")
+                .arg(line)
+                .query_async::<()>(redis)
+                .await
+                .map_err(|e| e.to_string()),
+        };
+        if let Err(e) = written {
+            tracing::warn!("Failed to write audit event: {e}");
+        }
+    }
+}
+
+/// Start the thread that appends queued lines to `file`, one write per line so
+/// events can't interleave. It stops once the sink is dropped.
+fn spawn_file_writer(mut file: File) -> Result<SyncSender<String>, String> {
+    let (lines, queue) = mpsc::sync_channel::<String>(FILE_QUEUE);
+    thread::Builder::new()
+        .name("audit-file".to_string())
+        .spawn(move || {
+            for line in queue {
+                if let Err(e) = file.write_all(format!("{line}\n").as_bytes()) {
+                    tracing::warn!("Failed to write audit event: {e}");
+                }
+            }
+        })
+        .map_err(|e| format!("failed to start the audit file writer: {e}"))?;
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    #[test]
+    fn classifies_client_addresses() {
+        let class = |ip: &str| IpClass::of(Some(ip.parse().unwrap()));
+        assert_eq!(class("127.0.0.1"), IpClass::Loopback);
+        assert_eq!(class("::1"), IpClass::Loopback);
+        assert_eq!(class("10.1.2.3"), IpClass::Private);
+        assert_eq!(class("192.168.0.1"), IpClass::Private);
+        assert_eq!(class("fd00::1"), IpClass::Private);
+        assert_eq!(class("::ffff:172.16.0.1"), IpClass::Private);
+        assert_eq!(class("8.8.8.8"), IpClass::Public);
+        assert_eq!(class("2001:db8::1"), IpClass::Public);
+        assert_eq!(IpClass::of(None), IpClass::Unknown);
+    }
+
+    #[test]
+    fn hashes_request_ids_with_the_configured_key() {
+        let audit = Audit::from_config("stdout", Some(KEY)).unwrap();
+        let hash = audit.request_hash("abc");
+        assert_eq!(
+            hash,
+            Audit::from_config("redis:audit", Some(KEY))
+                .unwrap()
+                .request_hash("abc")
+        );
+        assert_ne!(hash, audit.request_hash("abd"));
+        assert_ne!(
+            hash,
+            Audit::from_config("stdout", None)
+                .unwrap()
+                .request_hash("abc")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        assert!(Audit::from_config("stderr", None).is_err());
+        assert!(Audit::from_config("redis:", None).is_err());
+        assert!(Audit::from_config("stdout", Some("c2hvcnQ=")).is_err());
+        assert!(!Audit::default().is_enabled());
+    }
+}
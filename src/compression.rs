@@ -0,0 +1,72 @@
+//! Negotiated compression for payload-carrying route groups.
+//!
+//! Responses are compressed with gzip, brotli or zstd according to the client's
+//! `Accept-Encoding`, and request bodies with a matching `Content-Encoding` are
+//! decompressed before extraction. Decompression happens inside the
+//! `DefaultBodyLimit` set in [`crate::app`], so the limit applies to the
+//! decompressed size and a small compressed body can't inflate past it.
+
+use std::{collections::HashSet, str::FromStr};
+
+use aide::axum::ApiRouter;
+use tower_http::{
+    compression::{predicate::SizeAbove, CompressionLayer},
+    decompression::RequestDecompressionLayer,
+};
+
+/// Route groups whose middleware can be configured independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    /// `/request` and `/request/:request_id`.
+    Request,
+    /// `/response` and `/response/:request_id`.
+    Response,
+    /// `/upload` and everything under it.
+    Upload,
+}
+
+impl FromStr for RouteGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "request" => Ok(Self::Request),
+            "response" => Ok(Self::Response),
+            "upload" => Ok(Self::Upload),
+            _ => Err(format!("Invalid route group: {s}")),
+        }
+    }
+}
+
+/// Responses smaller than this aren't worth compressing.
+pub const DEFAULT_MIN_COMPRESS_BYTES: u16 = 1024;
+
+/// Which route groups compress responses and accept compressed bodies.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    pub routes: HashSet<RouteGroup>,
+    /// Minimum response size, in bytes, before compression kicks in.
+    pub min_size: u16,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            routes: HashSet::new(),
+            min_size: DEFAULT_MIN_COMPRESS_BYTES,
+        }
+    }
+}
+
+impl Compression {
+    /// Wrap `router` in the compression layers if they're enabled for `group`.
+    pub(crate) fn apply(&self, group: RouteGroup, router: ApiRouter) -> ApiRouter {
+        if !self.routes.contains(&group) {
+            return router;
+        }
+
+        router
+            .layer(CompressionLayer::new().compress_when(SizeAbove::new(self.min_size)))
+            .layer(RequestDecompressionLayer::new())
+    }
+}
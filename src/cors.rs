@@ -0,0 +1,290 @@
+//! Configurable CORS policy for the browser-facing route groups.
+//!
+//! Every group allows any request header. The allowed origins, max-age and
+//! extra exposed headers are shared, while methods are set per group, so a
+//! deployment can e.g. drop `HEAD` from a group its clients don't use.
+//! `PUT /response/:request_id` is only needed by the simulator and is kept
+//! away from browsers in production regardless. CORS only binds browsers;
+//! it is not access control.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+use crate::compression::RouteGroup;
+
+/// An allowed `Origin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginPattern {
+    Exact(String),
+    /// A single `*` standing for one or more characters of the host, as in
+    /// `https://*.example.com`.
+    Wildcard {
+        prefix: String,
+        suffix: String,
+    },
+}
+
+impl OriginPattern {
+    fn parse(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim().to_lowercase();
+        if !raw.contains("://") || raw.ends_with('/') {
+            return Err(format!("invalid CORS origin {raw:?}"));
+        }
+
+        match raw.split_once('*') {
+            None => Ok(Self::Exact(raw)),
+            Some((prefix, suffix)) if !suffix.contains('*') => Ok(Self::Wildcard {
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+            }),
+            Some(_) => Err(format!("CORS origin {raw:?} has more than one wildcard")),
+        }
+    }
+
+    fn matches(&self, origin: &str) -> bool {
+        match self {
+            Self::Exact(exact) => exact.eq_ignore_ascii_case(origin),
+            Self::Wildcard { prefix, suffix } => {
+                let origin = origin.to_lowercase();
+                origin.len() > prefix.len() + suffix.len()
+                    && origin.starts_with(prefix.as_str())
+                    && origin.ends_with(suffix.as_str())
+                    && !origin[prefix.len()..origin.len() - suffix.len()].contains('/')
+            }
+        }
+    }
+}
+
+/// Which origins may call the bridge from a browser.
+#[derive(Debug, Clone, Default)]
+pub enum Origins {
+    #[default]
+    Any,
+    List(Arc<Vec<OriginPattern>>),
+}
+
+impl Origins {
+    fn allow_origin(&self) -> AllowOrigin {
+        match self {
+            Self::Any => AllowOrigin::any(),
+            Self::List(patterns) => {
+                let patterns = patterns.clone();
+                AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+                    origin
+                        .to_str()
+                        .is_ok_and(|origin| patterns.iter().any(|p| p.matches(origin)))
+                })
+            }
+        }
+    }
+}
+
+/// The CORS policy of every route group.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    pub origins: Origins,
+    /// Allowed methods by route group. Groups without an entry allow none.
+    pub methods: HashMap<RouteGroup, Vec<Method>>,
+    /// How long browsers may cache a preflight. `None` leaves it to them.
+    pub max_age: Option<Duration>,
+    /// Exposed on top of the headers each route group exposes itself.
+    pub expose_headers: Vec<HeaderName>,
+}
+
+/// Any origin, and every method each group serves.
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self {
+            origins: Origins::Any,
+            methods: HashMap::from([
+                (
+                    RouteGroup::Request,
+                    vec![Method::GET, Method::POST, Method::HEAD, Method::PUT],
+                ),
+                (
+                    RouteGroup::Response,
+                    vec![Method::GET, Method::PUT, Method::POST],
+                ),
+                (
+                    RouteGroup::Upload,
+                    vec![Method::GET, Method::POST, Method::PUT],
+                ),
+            ]),
+            max_age: None,
+            expose_headers: Vec::new(),
+        }
+    }
+}
+
+impl CorsPolicy {
+    /// Build the policy from its config representation. Each part is
+    /// optional and keeps its default when `None`:
+    ///
+    /// - `origins`: `*`, or a comma-separated list of exact origins and
+    ///   single-wildcard patterns (`https://*.example.com`).
+    /// - `methods`: `;`-separated `group:METHOD,METHOD` entries, e.g.
+    ///   `request:GET,POST,HEAD;response:GET,POST`. Unlisted groups keep
+    ///   their defaults.
+    /// - `max_age`: seconds.
+    /// - `expose_headers`: comma-separated header names.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the first part that doesn't parse.
+    pub fn from_config(
+        origins: Option<&str>,
+        methods: Option<&str>,
+        max_age: Option<&str>,
+        expose_headers: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut policy = Self::default();
+
+        if let Some(origins) = origins.map(str::trim).filter(|o| *o != "*") {
+            let patterns = list(origins)
+                .map(OriginPattern::parse)
+                .collect::<Result<Vec<_>, _>>()?;
+            if patterns.is_empty() {
+                return Err("CORS origins are empty".to_string());
+            }
+            policy.origins = Origins::List(Arc::new(patterns));
+        }
+
+        for entry in methods.unwrap_or_default().split(';').map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            let (group, methods) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("CORS methods entry {entry:?} is not group:METHODS"))?;
+            let group: RouteGroup = group.trim().to_lowercase().parse()?;
+            let methods = list(methods)
+                .map(|m| {
+                    Method::from_bytes(m.to_uppercase().as_bytes())
+                        .map_err(|_| format!("invalid CORS method {m:?}"))
+                })
+                .collect::<Result<_, _>>()?;
+            policy.methods.insert(group, methods);
+        }
+
+        if let Some(max_age) = max_age {
+            let seconds = max_age
+                .trim()
+                .parse()
+                .map_err(|_| format!("CORS max-age {max_age:?} is not a number of seconds"))?;
+            policy.max_age = Some(Duration::from_secs(seconds));
+        }
+
+        policy.expose_headers = list(expose_headers.unwrap_or_default())
+            .map(|h| {
+                HeaderName::from_bytes(h.to_lowercase().as_bytes())
+                    .map_err(|_| format!("invalid CORS exposed header {h:?}"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(policy)
+    }
+
+    /// Remove `method` from the methods allowed for `group`.
+    pub(crate) fn disallow(&mut self, group: RouteGroup, method: &Method) {
+        if let Some(methods) = self.methods.get_mut(&group) {
+            methods.retain(|m| m != method);
+        }
+    }
+
+    /// The layer for `group`, which exposes `expose` along with the
+    /// configured headers.
+    pub(crate) fn layer(&self, group: RouteGroup, expose: &[HeaderName]) -> CorsLayer {
+        let layer = CorsLayer::new()
+            .allow_origin(self.origins.allow_origin())
+            .allow_headers(AllowHeaders::any())
+            .allow_methods(self.methods.get(&group).cloned().unwrap_or_default())
+            .expose_headers(
+                expose
+                    .iter()
+                    .chain(&self.expose_headers)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            );
+
+        match self.max_age {
+            Some(max_age) => layer.max_age(max_age),
+            None => layer,
+        }
+    }
+}
+
+fn list(raw: &str) -> impl Iterator<Item = &str> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_and_wildcard_origins() {
+        let exact = OriginPattern::parse("https://World.org").unwrap();
+        assert!(exact.matches("https://world.org"));
+        assert!(!exact.matches("https://sub.world.org"));
+        assert!(!exact.matches("http://world.org"));
+
+        let wildcard = OriginPattern::parse("https://*.world.org").unwrap();
+        assert!(wildcard.matches("https://app.world.org"));
+        assert!(wildcard.matches("https://a.b.world.org"));
+        assert!(!wildcard.matches("https://.world.org"));
+        assert!(!wildcard.matches("https://world.org"));
+        assert!(!wildcard.matches("https://evil.org/.world.org"));
+        assert!(!wildcard.matches("https://app.world.org.evil.org"));
+    }
+
+    #[test]
+    fn parses_config() {
+        let policy = CorsPolicy::from_config(
+            Some("https://world.org, https://*.world.org"),
+            Some("response: get, post"),
+            Some("600"),
+            Some("X-Trace-Id"),
+        )
+        .unwrap();
+
+        assert!(matches!(&policy.origins, Origins::List(p) if p.len() == 2));
+        assert_eq!(
+            policy.methods[&RouteGroup::Response],
+            [Method::GET, Method::POST]
+        );
+        assert_eq!(
+            policy.methods[&RouteGroup::Request],
+            CorsPolicy::default().methods[&RouteGroup::Request]
+        );
+        assert_eq!(policy.max_age, Some(Duration::from_secs(600)));
+        assert_eq!(policy.expose_headers, ["x-trace-id"]);
+
+        assert!(matches!(
+            CorsPolicy::from_config(Some("*"), None, None, None)
+                .unwrap()
+                .origins,
+            Origins::Any
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        let origins = |o| CorsPolicy::from_config(Some(o), None, None, None);
+        assert!(origins("world.org").is_err());
+        assert!(origins("https://world.org/").is_err());
+        assert!(origins("https://*.*.world.org").is_err());
+        assert!(origins(" , ").is_err());
+
+        let methods = |m| CorsPolicy::from_config(None, Some(m), None, None);
+        assert!(methods("response").is_err());
+        assert!(methods("system:GET").is_err());
+        assert!(methods("response:GET,G T").is_err());
+
+        assert!(CorsPolicy::from_config(None, None, Some("10m"), None).is_err());
+        assert!(CorsPolicy::from_config(None, None, None, Some("bad header")).is_err());
+    }
+}
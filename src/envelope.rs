@@ -0,0 +1,291 @@
+//! Optional server-side envelope encryption of values stored in Redis.
+//!
+//! Clients already encrypt their payloads, but without this layer the stored
+//! `{iv, payload}` JSON sits in Redis as-is, so a snapshot or replica leak
+//! reveals per-ID ciphertexts and their exact lengths. When enabled, every
+//! payload written to Redis is padded and sealed with AES-256-GCM under a
+//! server key before it leaves the process.
+//!
+//! Sealed values are laid out as `enc1:<key_id>:<nonce><ciphertext+tag>`. The
+//! key ID prefix lets old keys keep decrypting in-flight values while a new
+//! key takes over for writes, and the Redis key name is bound in as AAD so a
+//! value can't be replayed under a different request ID.
+
+use std::collections::HashMap;
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+
+/// Marks a stored value as sealed. Legacy plaintext values are JSON objects, so
+/// they can never start with this prefix.
+const SEALED_PREFIX: &[u8] = b"enc1:";
+
+/// Smallest padded plaintext size. Everything below this lands in the same
+/// bucket, so small payloads are indistinguishable from each other.
+const MIN_PADDED_LEN: usize = 1024;
+
+/// Length prefix prepended to the plaintext before padding.
+const LENGTH_PREFIX_LEN: usize = 4;
+
+/// Server-side keys for sealing stored values.
+///
+/// Built once at startup from `STORAGE_ENCRYPTION_KEYS` and
+/// `STORAGE_ENCRYPTION_ACTIVE_KEY` (see [`StorageEncryption::from_config`]).
+/// The default value is disabled: writes are stored as-is and only plaintext
+/// values can be read back.
+#[derive(Debug)]
+pub struct StorageEncryption {
+    /// Every key accepted for decryption, by key ID.
+    keys: HashMap<String, LessSafeKey>,
+    /// The key ID new values are sealed with. `None` keeps writes in plaintext
+    /// while still decrypting values sealed earlier (e.g. when winding the
+    /// feature down).
+    active: Option<String>,
+    rng: SystemRandom,
+}
+
+impl Default for StorageEncryption {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+            active: None,
+            rng: SystemRandom::new(),
+        }
+    }
+}
+
+impl StorageEncryption {
+    /// Parse the key set from its config representation.
+    ///
+    /// `keys` is a JSON object of `key_id → base64(32-byte key)`; `active` names
+    /// the key used for new writes and must be one of them. Key IDs share the
+    /// `request_id` charset minus `.` so they can't be confused with the
+    /// `:`-delimited sealed layout.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the JSON is malformed, a key is
+    /// not 32 bytes of valid base64, a key ID has disallowed characters, or the
+    /// active key ID isn't in the set.
+    pub fn from_config(keys: &str, active: Option<&str>) -> Result<Self, String> {
+        let raw: HashMap<String, String> = serde_json::from_str(keys)
+            .map_err(|e| format!("storage encryption keys are not a JSON object: {e}"))?;
+
+        let mut parsed = HashMap::with_capacity(raw.len());
+        for (key_id, encoded) in raw {
+            if key_id.is_empty()
+                || !key_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+            {
+                return Err(format!("invalid storage encryption key ID: {key_id:?}"));
+            }
+
+            let bytes = STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| format!("storage encryption key {key_id} is not base64: {e}"))?;
+            let key = UnboundKey::new(&AES_256_GCM, &bytes)
+                .map_err(|_| format!("storage encryption key {key_id} must be 32 bytes"))?;
+
+            parsed.insert(key_id, LessSafeKey::new(key));
+        }
+
+        let active = active.map(str::trim).filter(|id| !id.is_empty());
+        if let Some(active) = active {
+            if !parsed.contains_key(active) {
+                return Err(format!(
+                    "active storage encryption key {active} is not in the key set"
+                ));
+            }
+        }
+
+        Ok(Self {
+            keys: parsed,
+            active: active.map(ToString::to_string),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Whether new writes are sealed.
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Number of keys accepted for decryption.
+    #[must_use]
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Seal a value about to be stored under the Redis key `redis_key`.
+    ///
+    /// Returns the plaintext unchanged when no active key is configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::INTERNAL_SERVER_ERROR`] if the value is too large
+    /// to frame or the system RNG fails.
+    pub fn seal(&self, redis_key: &str, plaintext: &[u8]) -> Result<Vec<u8>, StatusCode> {
+        let Some(key_id) = &self.active else {
+            return Ok(plaintext.to_vec());
+        };
+        let key = &self.keys[key_id];
+
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| {
+            tracing::error!("Failed to generate storage encryption nonce");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let mut sealed = pad(plaintext)?;
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(redis_key.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| {
+            tracing::error!("Failed to seal stored value");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let mut out =
+            Vec::with_capacity(SEALED_PREFIX.len() + key_id.len() + 1 + NONCE_LEN + sealed.len());
+        out.extend_from_slice(SEALED_PREFIX);
+        out.extend_from_slice(key_id.as_bytes());
+        out.push(b':');
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    /// Open a value read from the Redis key `redis_key`.
+    ///
+    /// Values without the sealed prefix are returned unchanged, so enabling
+    /// encryption doesn't strand requests written before the rollout.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::INTERNAL_SERVER_ERROR`] if the value is sealed with
+    /// an unknown key ID, is truncated, or fails authentication.
+    pub fn open(&self, redis_key: &str, stored: &[u8]) -> Result<Vec<u8>, StatusCode> {
+        let Some(rest) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_vec());
+        };
+
+        let fail = |reason: &str| {
+            tracing::error!("Failed to open stored value: {reason}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+
+        let separator = rest
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or_else(|| fail("missing key ID"))?;
+        let key_id = std::str::from_utf8(&rest[..separator]).map_err(|_| fail("bad key ID"))?;
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| fail(&format!("unknown key ID {key_id}")))?;
+
+        let body = &rest[separator + 1..];
+        if body.len() < NONCE_LEN {
+            return Err(fail("truncated value"));
+        }
+        let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| fail("bad nonce"))?;
+
+        let mut buffer = ciphertext.to_vec();
+        let padded = key
+            .open_in_place(nonce, Aad::from(redis_key.as_bytes()), &mut buffer)
+            .map_err(|_| fail("authentication failed"))?;
+
+        unpad(padded).ok_or_else(|| fail("bad padding"))
+    }
+}
+
+/// Frame `plaintext` with its length and zero-pad it to the next power of two
+/// (at least [`MIN_PADDED_LEN`]), so the stored size only reveals a size class.
+fn pad(plaintext: &[u8]) -> Result<Vec<u8>, StatusCode> {
+    let len = u32::try_from(plaintext.len()).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let padded_len = (plaintext.len() + LENGTH_PREFIX_LEN)
+        .next_power_of_two()
+        .max(MIN_PADDED_LEN);
+
+    // Room for the GCM tag so sealing doesn't reallocate.
+    let mut out = Vec::with_capacity(padded_len + AES_256_GCM.tag_len());
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(plaintext);
+    out.resize(padded_len, 0);
+    Ok(out)
+}
+
+fn unpad(padded: &[u8]) -> Option<Vec<u8>> {
+    let (len, rest) = padded.split_first_chunk::<LENGTH_PREFIX_LEN>()?;
+    let len = usize::try_from(u32::from_be_bytes(*len)).ok()?;
+    rest.get(..len).map(<[u8]>::to_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+    const KEY_B: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    fn cipher(active: &str) -> StorageEncryption {
+        let keys = format!(r#"{{"a": "{KEY_A}", "b": "{KEY_B}"}}"#);
+        StorageEncryption::from_config(&keys, Some(active)).unwrap()
+    }
+
+    #[test]
+    fn round_trips_and_hides_length() {
+        let enc = cipher("a");
+        let short = enc.seal("req:one", b"{\"iv\":\"x\"}").unwrap();
+        let longer = enc.seal("req:two", &[b'x'; 900]).unwrap();
+
+        assert!(short.starts_with(b"enc1:a:"));
+        assert_eq!(short.len(), longer.len(), "both land in the 1 KiB bucket");
+        assert_eq!(enc.open("req:one", &short).unwrap(), b"{\"iv\":\"x\"}");
+    }
+
+    #[test]
+    fn old_keys_still_decrypt_after_rotation() {
+        let sealed = cipher("a").seal("req:rotate", b"payload").unwrap();
+        let rotated = cipher("b");
+
+        assert_eq!(rotated.open("req:rotate", &sealed).unwrap(), b"payload");
+        assert!(rotated
+            .seal("req:rotate", b"payload")
+            .unwrap()
+            .starts_with(b"enc1:b:"));
+    }
+
+    #[test]
+    fn value_is_bound_to_its_redis_key() {
+        let enc = cipher("a");
+        let sealed = enc.seal("req:one", b"payload").unwrap();
+        assert!(enc.open("req:other", &sealed).is_err());
+    }
+
+    #[test]
+    fn plaintext_passes_through() {
+        let disabled = StorageEncryption::default();
+        assert_eq!(disabled.seal("req:x", b"{}").unwrap(), b"{}");
+        assert_eq!(cipher("a").open("req:x", b"{}").unwrap(), b"{}");
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        assert!(StorageEncryption::from_config("not json", None).is_err());
+        assert!(StorageEncryption::from_config(r#"{"a": "c2hvcnQ="}"#, None).is_err());
+        assert!(StorageEncryption::from_config(&format!(r#"{{"a:b": "{KEY_A}"}}"#), None).is_err());
+        assert!(
+            StorageEncryption::from_config(&format!(r#"{{"a": "{KEY_A}"}}"#), Some("b")).is_err()
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Deployment profiles and the features each one enables.
+//!
+//! The profile is resolved once at startup from `ENVIRONMENT`. Anything that
+//! isn't a known profile or one of its legacy spellings runs as production, so
+//! a typo can only ever turn features off. Debug features additionally need an
+//! explicit opt-in (see [`Feature::is_debug`]), so a development profile left
+//! in a deployed config doesn't expose them. Routes check
+//! [`crate::Config::enables`] instead of reading the environment themselves,
+//! and `/` lists the active features so a deployment can be verified from
+//! outside.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::Serialize;
+
+/// Where the bridge is deployed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Development,
+    Staging,
+    /// Nothing beyond the public API. The default, so anything left
+    /// unconfigured stays off.
+    #[default]
+    Production,
+}
+
+/// Behavior that only some profiles enable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// `PUT /request/:request_id`, the idempotent request upsert.
+    StagingUpsert,
+    /// `PUT` on the `response` route group allowed from browsers, which only
+    /// the simulator needs.
+    SimulatorPut,
+    /// Routes for driving the bridge in tests, never exposed in production.
+    DebugEndpoints,
+}
+
+impl Feature {
+    /// Debug features are only active when explicitly opted into, on top of
+    /// the profile allowing them.
+    #[must_use]
+    pub const fn is_debug(self) -> bool {
+        matches!(self, Self::SimulatorPut | Self::DebugEndpoints)
+    }
+}
+
+impl Environment {
+    /// The features this profile allows.
+    #[must_use]
+    pub const fn features(self) -> &'static [Feature] {
+        match self {
+            Self::Development | Self::Staging => &[
+                Feature::StagingUpsert,
+                Feature::SimulatorPut,
+                Feature::DebugEndpoints,
+            ],
+            Self::Production => &[],
+        }
+    }
+
+    #[must_use]
+    pub fn enables(self, feature: Feature) -> bool {
+        self.features().contains(&feature)
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The short forms are what deployments used before profiles existed.
+        match s.trim().to_lowercase().as_str() {
+            "development" | "dev" => Ok(Self::Development),
+            "staging" | "stage" => Ok(Self::Staging),
+            "production" | "prod" => Ok(Self::Production),
+            other => Err(format!("unknown environment {other:?}")),
+        }
+    }
+}
+
+impl Display for Environment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Development => write!(f, "development"),
+            Self::Staging => write!(f, "staging"),
+            Self::Production => write!(f, "production"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles() {
+        assert_eq!(" Staging ".parse(), Ok(Environment::Staging));
+        assert_eq!("development".parse(), Ok(Environment::Development));
+        assert_eq!("PRODUCTION".parse(), Ok(Environment::Production));
+        assert_eq!("prod".parse(), Ok(Environment::Production));
+        assert_eq!("dev".parse(), Ok(Environment::Development));
+        assert!("stagign".parse::<Environment>().is_err());
+        assert!("".parse::<Environment>().is_err());
+    }
+
+    #[test]
+    fn production_enables_nothing() {
+        assert!(Environment::Production.features().is_empty());
+        assert!(Environment::Staging.enables(Feature::StagingUpsert));
+        assert!(!Environment::default().enables(Feature::DebugEndpoints));
+        assert!(Feature::DebugEndpoints.is_debug());
+        assert!(!Feature::StagingUpsert.is_debug());
+    }
+}
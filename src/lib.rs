@@ -6,11 +6,83 @@ use aide::openapi::{Info, License, OpenApi};
 use axum::{extract::DefaultBodyLimit, Extension};
 use redis::aio::ConnectionManager;
 
-use crate::utils::AppOverrides;
+use crate::{
+    audit::Audit,
+    compression::Compression,
+    cors::CorsPolicy,
+    envelope::StorageEncryption,
+    environment::{Environment, Feature},
+    notify::Notifier,
+    utils::AppOverrides,
+    validation::PayloadValidation,
+    webhook::Webhooks,
+};
 
+pub mod audit;
+pub mod compression;
+pub mod cors;
+pub mod envelope;
+pub mod environment;
+pub mod metadata;
+pub mod notify;
+pub mod redact;
 pub mod routes;
 pub mod server;
 pub mod utils;
+pub mod validation;
+pub mod webhook;
+
+/// Runtime configuration for [`app`], resolved once at startup.
+///
+/// The binary builds this from environment variables; tests construct it
+/// directly. Every field defaults to its feature being off.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Deployment profile, which decides the environment-specific features.
+    pub environment: Environment,
+    /// Opt in to the debug features the profile allows. Off unless set.
+    pub debug_features: bool,
+    /// Per-`app_id` URL overrides echoed to opted-in clients on `POST /request`.
+    pub app_overrides: Arc<AppOverrides>,
+    /// Server-side envelope encryption of values stored in Redis.
+    pub storage_encryption: Arc<StorageEncryption>,
+    /// Opt-in shape validation of stored payloads.
+    pub payload_validation: Arc<PayloadValidation>,
+    /// Route groups with negotiated request/response compression.
+    pub compression: Compression,
+    /// Browser origins, methods and headers allowed per route group.
+    pub cors: CorsPolicy,
+    /// Per-app callback allowlists and secrets for webhook delivery.
+    pub webhooks: Arc<Webhooks>,
+    /// Bus for request status changes. In-process by default; the binary
+    /// relays it through Redis so every replica sees every change.
+    pub notifications: Arc<Notifier>,
+    /// Sink for structured audit events of status transitions.
+    pub audit: Arc<Audit>,
+    /// Bearer token of the `/simulator` routes, which are only mounted when
+    /// it is set and debug endpoints are enabled.
+    pub simulator_token: Option<String>,
+}
+
+impl Config {
+    /// Whether `feature` is active: the profile allows it and, for a debug
+    /// feature, it was opted into.
+    #[must_use]
+    pub fn enables(&self, feature: Feature) -> bool {
+        self.environment.enables(feature) && (!feature.is_debug() || self.debug_features)
+    }
+
+    /// The active features, in the order the profile declares them.
+    #[must_use]
+    pub fn features(&self) -> Vec<Feature> {
+        self.environment
+            .features()
+            .iter()
+            .copied()
+            .filter(|feature| self.enables(*feature))
+            .collect()
+    }
+}
 
 /// Assemble the fully-wired application router.
 ///
@@ -18,7 +90,7 @@ pub mod utils;
 /// both exercise the exact same routes, middleware stack, and `OpenAPI` document.
 /// Tests drive the returned router in-process with `tower::ServiceExt::oneshot`,
 /// so no separately-running bridge is required.
-pub fn app(redis: ConnectionManager, app_overrides: Arc<AppOverrides>) -> axum::Router {
+pub fn app(redis: ConnectionManager, config: Config) -> axum::Router {
     let mut openapi = OpenApi {
         info: Info {
             title: "Message Bridge".to_string(),
@@ -35,10 +107,18 @@ pub fn app(redis: ConnectionManager, app_overrides: Arc<AppOverrides>) -> axum::
         ..Default::default()
     };
 
-    routes::handler()
+    let features = Arc::<[Feature]>::from(config.features());
+    routes::handler(&config)
         .finish_api(&mut openapi)
         .layer(Extension(redis))
-        .layer(Extension(app_overrides))
+        .layer(Extension(config.app_overrides))
+        .layer(Extension(config.storage_encryption))
+        .layer(Extension(config.payload_validation))
+        .layer(Extension(config.webhooks))
+        .layer(Extension(config.notifications))
+        .layer(Extension(config.audit))
+        .layer(Extension(config.environment))
+        .layer(Extension(features))
         .layer(Extension(openapi))
-        .layer(DefaultBodyLimit::max(5 * 1024 * 1024))
+        .layer(DefaultBodyLimit::max(utils::MAX_BODY_BYTES))
 }
@@ -5,7 +5,19 @@ use redis::aio::ConnectionManager;
 use std::env;
 use std::sync::Arc;
 
-use world_id_bridge::utils::AppOverrides;
+use world_id_bridge::{
+    audit::Audit,
+    compression::{Compression, DEFAULT_MIN_COMPRESS_BYTES},
+    cors::CorsPolicy,
+    envelope::StorageEncryption,
+    environment::{Environment, Feature},
+    notify::Notifier,
+    redact::IdLogging,
+    utils::AppOverrides,
+    validation::PayloadValidation,
+    webhook::Webhooks,
+    Config,
+};
 
 #[tokio::main]
 async fn main() {
@@ -15,6 +27,10 @@ async fn main() {
 
     tracing::info!("Starting wallet bridge...");
 
+    let environment = load_environment();
+    let debug_features = load_debug_features(environment);
+    load_id_logging(debug_features).install();
+
     let redis_url = env::var("REDIS_URL").unwrap_or_else(|_| {
         let host = env::var("REDIS_HOST").expect("REDIS_HOST required if REDIS_URL is not set.");
         let port = env::var("REDIS_PORT").expect("REDIS_PORT required if REDIS_URL is not set.");
@@ -39,7 +55,7 @@ async fn main() {
 
     tracing::info!("Attempting to connect to Redis...");
 
-    let redis = build_redis_pool(redis_url)
+    let redis = build_redis_pool(redis_url.clone())
         .await
         .map_err(|e| {
             tracing::error!("Redis connection failed: {}", e);
@@ -49,9 +65,66 @@ async fn main() {
 
     tracing::info!("✅ Connection to Redis established.");
 
-    let app_overrides = Arc::new(load_app_overrides());
+    let notifications = redis::Client::open(redis_url).expect("Invalid REDIS_URL");
+    let notifications = Notifier::redis(notifications)
+        .await
+        .expect("Failed to subscribe to status notifications");
+
+    let config = Config {
+        environment,
+        app_overrides: Arc::new(load_app_overrides()),
+        storage_encryption: Arc::new(load_storage_encryption()),
+        payload_validation: Arc::new(load_payload_validation()),
+        compression: load_compression(),
+        cors: load_cors(),
+        webhooks: Arc::new(load_webhooks(environment)),
+        notifications: Arc::new(notifications),
+        audit: Arc::new(load_audit()),
+        debug_features,
+        simulator_token: load_simulator_token(environment, debug_features),
+    };
+    tracing::info!(
+        "Environment: {environment}, features: {:?}.",
+        config.features()
+    );
+
+    world_id_bridge::server::start(redis, config).await;
+}
+
+/// Load the deployment profile.
+///
+/// `ENVIRONMENT` is `development`, `staging` or `production`, or the legacy
+/// `dev`, `stage` and `prod`; unset ⇒ production, which enables no
+/// environment-specific features. Any other value is logged and also runs as
+/// production, so a typo can only switch features off.
+fn load_environment() -> Environment {
+    match env::var("ENVIRONMENT") {
+        Ok(s) if !s.trim().is_empty() => s.parse().unwrap_or_else(|e| {
+            tracing::error!("Invalid ENVIRONMENT ({e}) — running as production.");
+            Environment::Production
+        }),
+        _ => {
+            tracing::info!("ENVIRONMENT not set — running as production.");
+            Environment::Production
+        }
+    }
+}
+
+/// Load the debug feature opt-in.
+///
+/// `DEBUG_FEATURES=true` turns on the debug features the profile allows
+/// (`simulator-put`, `debug-endpoints`). Unset ⇒ off, whatever the profile.
+/// Ignored in production, which allows none.
+fn load_debug_features(environment: Environment) -> bool {
+    let enabled = env::var("DEBUG_FEATURES")
+        .map(|val| val.to_lowercase() == "true")
+        .unwrap_or(false);
 
-    world_id_bridge::server::start(redis, app_overrides).await;
+    if enabled && !environment.features().iter().any(|f| f.is_debug()) {
+        tracing::warn!("DEBUG_FEATURES is ignored in {environment}.");
+        return false;
+    }
+    enabled
 }
 
 /// Load the per-`app_id` URL override map from the `APP_URL_OVERRIDES` env var.
@@ -80,6 +153,233 @@ fn load_app_overrides() -> AppOverrides {
     overrides
 }
 
+/// Load the server-side storage encryption keys.
+///
+/// `STORAGE_ENCRYPTION_KEYS` is a JSON object of `key_id → base64(32-byte key)`
+/// and `STORAGE_ENCRYPTION_ACTIVE_KEY` names the key new values are sealed
+/// with. Unset keys ⇒ values are stored as-is. To rotate, add the new key,
+/// point the active ID at it, and drop the old key once `EXPIRE_AFTER_SECONDS`
+/// has passed. Leaving the active ID unset while keys are present keeps
+/// decrypting older values but stores new ones in plaintext.
+///
+/// Invalid configuration is fatal, same as `APP_URL_OVERRIDES`.
+fn load_storage_encryption() -> StorageEncryption {
+    let keys = match env::var("STORAGE_ENCRYPTION_KEYS") {
+        Ok(s) if !s.trim().is_empty() => s,
+        _ => {
+            tracing::info!("STORAGE_ENCRYPTION_KEYS not set — storage encryption disabled.");
+            return StorageEncryption::default();
+        }
+    };
+    let active = env::var("STORAGE_ENCRYPTION_ACTIVE_KEY").ok();
+
+    let encryption = StorageEncryption::from_config(&keys, active.as_deref())
+        .unwrap_or_else(|e| panic!("Invalid storage encryption config: {e}"));
+
+    tracing::info!(
+        "Loaded {} storage encryption key(s), sealing new values: {}.",
+        encryption.key_count(),
+        encryption.is_enabled()
+    );
+    encryption
+}
+
+/// Load the opt-in payload validation settings.
+///
+/// `VALIDATE_PAYLOADS=true` turns validation on; `MAX_REQUEST_PAYLOAD_BYTES`
+/// and `MAX_RESPONSE_PAYLOAD_BYTES` optionally cap the decoded payload size for
+/// each route group. A limit that isn't a number is a fatal startup error.
+fn load_payload_validation() -> PayloadValidation {
+    let enabled = env::var("VALIDATE_PAYLOADS")
+        .map(|val| val.to_lowercase() == "true")
+        .unwrap_or(false);
+
+    let limit = |name: &str| {
+        env::var(name).ok().map(|val| {
+            val.trim()
+                .parse::<usize>()
+                .unwrap_or_else(|_| panic!("{name} must be a byte count"))
+        })
+    };
+
+    let validation = PayloadValidation {
+        enabled,
+        max_request_bytes: limit("MAX_REQUEST_PAYLOAD_BYTES"),
+        max_response_bytes: limit("MAX_RESPONSE_PAYLOAD_BYTES"),
+    };
+
+    tracing::info!("Payload validation enabled: {}.", validation.enabled);
+    validation
+}
+
+/// Load the route groups that negotiate compression.
+///
+/// `COMPRESSION_ROUTES` is a comma-separated list of route groups (`request`,
+/// `response`); unset ⇒ no compression anywhere. `COMPRESSION_MIN_BYTES`
+/// overrides the minimum response size worth compressing. An unknown group or
+/// a non-numeric size is a fatal startup error.
+fn load_compression() -> Compression {
+    let routes = env::var("COMPRESSION_ROUTES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|group| !group.is_empty())
+        .map(|group| {
+            group
+                .to_lowercase()
+                .parse()
+                .unwrap_or_else(|e| panic!("{e}"))
+        })
+        .collect();
+
+    let min_size = env::var("COMPRESSION_MIN_BYTES").map_or(DEFAULT_MIN_COMPRESS_BYTES, |val| {
+        val.trim()
+            .parse()
+            .expect("COMPRESSION_MIN_BYTES must be a byte count")
+    });
+
+    let compression = Compression { routes, min_size };
+    tracing::info!("Compression enabled for {:?}.", compression.routes);
+    compression
+}
+
+/// Load the CORS policy.
+///
+/// `CORS_ORIGINS` is `*` or a comma-separated list of origins, each exact or
+/// with one `*` wildcard (`https://*.example.com`). `CORS_METHODS` overrides
+/// the methods of individual route groups as `group:METHOD,METHOD;...`.
+/// `CORS_MAX_AGE` caps how long a preflight is cached, in seconds, and
+/// `CORS_EXPOSE_HEADERS` lists extra headers to expose. Unset ⇒ any origin and
+/// every method each group serves. Invalid configuration is fatal.
+fn load_cors() -> CorsPolicy {
+    let var = |name| {
+        env::var(name)
+            .ok()
+            .filter(|s: &String| !s.trim().is_empty())
+    };
+
+    let cors = CorsPolicy::from_config(
+        var("CORS_ORIGINS").as_deref(),
+        var("CORS_METHODS").as_deref(),
+        var("CORS_MAX_AGE").as_deref(),
+        var("CORS_EXPOSE_HEADERS").as_deref(),
+    )
+    .unwrap_or_else(|e| panic!("Invalid CORS config: {e}"));
+
+    tracing::info!("CORS origins: {:?}.", cors.origins);
+    cors
+}
+
+/// Load the apps that can register webhook callbacks.
+///
+/// `WEBHOOK_APPS` is a JSON object of `app_id → {secrets, callback_urls}`,
+/// where `secrets` lists the base64 HMAC keys deliveries are signed with (all
+/// of them, so a rotation needs no coordinated cut-over) and `callback_urls`
+/// the exact URLs the app may register, which must be `https` (`http` is
+/// allowed outside production). Unset ⇒ webhooks are off and every
+/// `callback_url` is rejected. Invalid configuration is fatal, same as
+/// `APP_URL_OVERRIDES`.
+fn load_webhooks(environment: Environment) -> Webhooks {
+    let raw = match env::var("WEBHOOK_APPS") {
+        Ok(s) if !s.trim().is_empty() => s,
+        _ => {
+            tracing::info!("WEBHOOK_APPS not set — webhook delivery disabled.");
+            return Webhooks::default();
+        }
+    };
+
+    let webhooks = Webhooks::from_config(&raw, environment)
+        .unwrap_or_else(|e| panic!("Invalid webhook config: {e}"));
+
+    tracing::info!("Loaded webhook config for {} app(s).", webhooks.app_count());
+    webhooks
+}
+
+/// Load how request IDs are logged.
+///
+/// `LOG_REQUEST_IDS` is `prefix` (the default), `hash` or `full`. `hash` uses
+/// `AUDIT_HASH_KEY`, so logged hashes match those in audit events. `full`
+/// logs bearer capabilities, so it takes `DEBUG_FEATURES=true` in a profile
+/// with debug features; otherwise it is logged as an error and falls back to
+/// `prefix`. Invalid configuration is fatal.
+fn load_id_logging(debug_features: bool) -> IdLogging {
+    let mode = match env::var("LOG_REQUEST_IDS") {
+        Ok(s) if !s.trim().is_empty() => s,
+        _ => return IdLogging::Prefix,
+    };
+
+    let logging = IdLogging::from_config(&mode, env::var("AUDIT_HASH_KEY").ok().as_deref())
+        .unwrap_or_else(|e| panic!("Invalid request ID logging config: {e}"));
+    if matches!(logging, IdLogging::Full) {
+        if !debug_features {
+            tracing::error!(
+                "LOG_REQUEST_IDS=full needs DEBUG_FEATURES=true outside production, logging prefixes."
+            );
+            return IdLogging::Prefix;
+        }
+        tracing::warn!("Logging full request IDs — anyone with log access can read payloads.");
+    }
+    logging
+}
+
+/// Load the audit event sink.
+///
+/// `AUDIT_SINK` is `stdout`, `file:<path>` (JSON lines) or `redis:<stream>`,
+/// and `AUDIT_HASH_KEY` the base64 key request IDs are hashed with. Replicas
+/// need the same key for their hashes to match. Unset sink ⇒ auditing is off.
+/// Invalid configuration is fatal.
+fn load_audit() -> Audit {
+    let sink = match env::var("AUDIT_SINK") {
+        Ok(s) if !s.trim().is_empty() => s,
+        _ => {
+            tracing::info!("AUDIT_SINK not set — audit events disabled.");
+            return Audit::default();
+        }
+    };
+    let hash_key = env::var("AUDIT_HASH_KEY").ok();
+    if hash_key.is_none() {
+        tracing::warn!("AUDIT_HASH_KEY not set — request hashes won't match across replicas.");
+    }
+
+    let audit = Audit::from_config(&sink, hash_key.as_deref())
+        .unwrap_or_else(|e| panic!("Invalid audit config: {e}"));
+
+    tracing::info!("Audit events enabled ({}).", sink.trim());
+    audit
+}
+
+/// Load the token of the `/simulator` routes.
+///
+/// `SIMULATOR_TOKEN` must be at least 32 characters, and callers send it as
+/// `Authorization: Bearer <token>`. Unset ⇒ the simulator routes are off. It
+/// is ignored unless debug endpoints are allowed by the profile and opted into
+/// with `DEBUG_FEATURES`, so production never mounts them. A short token is a
+/// fatal startup error.
+fn load_simulator_token(environment: Environment, debug_features: bool) -> Option<String> {
+    let token = env::var("SIMULATOR_TOKEN")
+        .ok()
+        .filter(|s| !s.trim().is_empty());
+
+    if !(debug_features && environment.enables(Feature::DebugEndpoints)) {
+        if token.is_some() {
+            tracing::warn!("SIMULATOR_TOKEN is ignored without debug endpoints.");
+        }
+        return None;
+    }
+    let Some(token) = token else {
+        tracing::info!("SIMULATOR_TOKEN not set — simulator routes disabled.");
+        return None;
+    };
+
+    let token = token.trim().to_string();
+    assert!(
+        token.len() >= 32,
+        "SIMULATOR_TOKEN must be at least 32 characters"
+    );
+    tracing::info!("Simulator routes enabled.");
+    Some(token)
+}
+
 async fn build_redis_pool(redis_url: String) -> redis::RedisResult<ConnectionManager> {
     let client = redis::Client::open(redis_url)?;
 
@@ -0,0 +1,121 @@
+//! Plaintext request metadata.
+//!
+//! Everything in a payload is encrypted for the other party, so the bridge
+//! can't tell one app's traffic from another's. Clients can describe a request
+//! with [`RequestMetadata`], which is stored in the clear alongside its status
+//! for metrics, rate limits and per-app policies to key off. The schema is
+//! closed and every field has a fixed format, so it can't turn into a side
+//! channel for anything sensitive.
+
+use axum::http::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Longest `app_id` accepted.
+const APP_ID_MAX_LEN: usize = 64;
+
+/// What a request is for, as declared by the client creating it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RequestMetadata {
+    /// The app the request is made for: `app_` followed by lowercase letters,
+    /// digits and underscores.
+    pub app_id: String,
+    /// Hash of the action, as a `0x`-prefixed 32-byte hex string. Never the
+    /// action itself.
+    #[serde(default)]
+    pub action: Option<String>,
+    /// The kind of client that created the request.
+    pub client: ClientType,
+}
+
+/// The SDK a request was created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientType {
+    IdkitJs,
+    IdkitSwift,
+    IdkitKotlin,
+    /// The Rust client in this repository.
+    Rust,
+    Other,
+}
+
+impl RequestMetadata {
+    /// Check every field has its declared format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::BAD_REQUEST`] if one doesn't.
+    pub fn validate(&self) -> Result<(), StatusCode> {
+        let app_id = self.app_id.strip_prefix("app_").is_some_and(|rest| {
+            !rest.is_empty()
+                && self.app_id.len() <= APP_ID_MAX_LEN
+                && rest
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+        });
+        let action = self.action.as_deref().is_none_or(|action| {
+            action.strip_prefix("0x").is_some_and(|hex| {
+                hex.len() == 64
+                    && hex
+                        .bytes()
+                        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+            })
+        });
+
+        if app_id && action {
+            Ok(())
+        } else {
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(app_id: &str, action: Option<&str>) -> RequestMetadata {
+        RequestMetadata {
+            app_id: app_id.to_string(),
+            action: action.map(ToString::to_string),
+            client: ClientType::IdkitJs,
+        }
+    }
+
+    #[test]
+    fn validates_field_formats() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert!(metadata("app_staging_0123abcd", Some(&hash))
+            .validate()
+            .is_ok());
+        assert!(metadata("app_0123abcd", None).validate().is_ok());
+
+        assert!(metadata("app_", None).validate().is_err());
+        assert!(metadata("0123abcd", None).validate().is_err());
+        assert!(metadata("app_ABCD", None).validate().is_err());
+        assert!(metadata(&format!("app_{}", "a".repeat(61)), None)
+            .validate()
+            .is_err());
+        assert!(metadata("app_1", Some("verify-humanity"))
+            .validate()
+            .is_err());
+        assert!(metadata("app_1", Some(&hash[..64])).validate().is_err());
+        assert!(metadata("app_1", Some(&hash.to_uppercase()))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(serde_json::from_str::<RequestMetadata>(
+            r#"{"app_id": "app_1", "client": "rust", "signal": "x"}"#
+        )
+        .is_err());
+        assert!(
+            serde_json::from_str::<RequestMetadata>(r#"{"app_id": "app_1", "client": "cli"}"#)
+                .is_err()
+        );
+    }
+}
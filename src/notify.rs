@@ -0,0 +1,153 @@
+//! Internal notification bus for request status changes.
+//!
+//! Every handler that moves a request to a new status publishes a
+//! [`StatusEvent`], and any handler can [`Notifier::subscribe`] to wait for
+//! one instead of polling Redis. With several replicas, a response stored on
+//! one pod has to wake a waiter on another, so the binary relays events
+//! through Redis pub/sub ([`Notifier::redis`]). Every replica publishes to
+//! [`STATUS_CHANNEL`] and re-broadcasts what it receives to its local
+//! subscribers. [`Notifier::local`] skips Redis and only reaches subscribers in
+//! the same process, which is all a single replica, or a test, needs.
+//!
+//! Delivery is best-effort. A failed publish is logged rather than failing the
+//! request, and slow subscribers lose the oldest events, so waiters should
+//! re-read the status from Redis after waking.
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::redact;
+use crate::utils::RequestStatus;
+
+/// The Redis pub/sub channel replicas exchange events on.
+pub const STATUS_CHANNEL: &str = "bridge:status";
+
+/// Events buffered per subscriber before the oldest are dropped.
+const CAPACITY: usize = 1024;
+
+/// How long the relay waits before resubscribing after losing Redis.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// A request moved to `status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusEvent {
+    pub request_id: String,
+    pub status: RequestStatus,
+}
+
+/// Publishes status changes and fans them out to subscribers.
+#[derive(Debug)]
+pub struct Notifier {
+    local: broadcast::Sender<StatusEvent>,
+    /// Whether events go through Redis, which echoes them back to `local`.
+    relayed: bool,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::local()
+    }
+}
+
+impl Notifier {
+    /// A bus that only reaches subscribers in this process.
+    #[must_use]
+    pub fn local() -> Self {
+        Self {
+            local: broadcast::channel(CAPACITY).0,
+            relayed: false,
+        }
+    }
+
+    /// A bus relayed through Redis pub/sub, so events published on any replica
+    /// reach subscribers on every replica. Subscribes before returning, then
+    /// keeps the subscription alive in the background, resubscribing if the
+    /// connection drops. Events published while it is down are lost.
+    ///
+    /// # Errors
+    ///
+    /// Returns the Redis error if the first subscription fails.
+    pub async fn redis(client: redis::Client) -> redis::RedisResult<Self> {
+        let notifier = Self {
+            local: broadcast::channel(CAPACITY).0,
+            relayed: true,
+        };
+
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.subscribe(STATUS_CHANNEL).await?;
+
+        let local = notifier.local.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut messages = pubsub.on_message();
+                while let Some(message) = messages.next().await {
+                    match serde_json::from_slice::<StatusEvent>(message.get_payload_bytes()) {
+                        Ok(event) => drop(local.send(event)),
+                        Err(e) => tracing::warn!("Ignoring malformed status event: {e}"),
+                    }
+                }
+                drop(messages);
+
+                tracing::warn!("Lost the status event subscription, resubscribing");
+                pubsub = loop {
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    match resubscribe(&client).await {
+                        Ok(pubsub) => break pubsub,
+                        Err(e) => tracing::warn!("Failed to resubscribe to status events: {e}"),
+                    }
+                };
+            }
+        });
+
+        Ok(notifier)
+    }
+
+    /// Publish that `request_id` moved to `status`.
+    pub async fn publish(
+        &self,
+        redis: &mut ConnectionManager,
+        request_id: &str,
+        status: RequestStatus,
+    ) {
+        let event = StatusEvent {
+            request_id: request_id.to_string(),
+            status,
+        };
+
+        if !self.relayed {
+            // No subscribers is fine.
+            drop(self.local.send(event));
+            return;
+        }
+
+        let message = match serde_json::to_string(&event) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::error!("Failed to encode status event: {e}");
+                return;
+            }
+        };
+        if let Err(e) = redis.publish::<_, _, ()>(STATUS_CHANNEL, message).await {
+            tracing::warn!(
+                "Failed to publish status event for {}: {e}",
+                redact::id(request_id)
+            );
+        }
+    }
+
+    /// Receive every event published from now on.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<StatusEvent> {
+        self.local.subscribe()
+    }
+}
+
+async fn resubscribe(client: &redis::Client) -> redis::RedisResult<redis::aio::PubSub> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(STATUS_CHANNEL).await?;
+    Ok(pubsub)
+}
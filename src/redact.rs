@@ -0,0 +1,175 @@
+//! Redaction of request IDs in logs.
+//!
+//! A `request_id` is the bearer capability to read its payload, so anyone who
+//! can read the logs could race the legitimate consumer to it while the TTL is
+//! live. Handlers therefore log [`id`] instead of the raw value, which renders
+//! it according to the process-wide [`IdLogging`] mode: a short prefix by
+//! default, a keyed hash, or, for debugging only, the full ID. Upload IDs are
+//! capabilities too and are logged the same way.
+
+use std::{fmt::Display, sync::OnceLock};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{hmac, rand::SystemRandom};
+
+/// Characters of the ID kept by [`IdLogging::Prefix`]. Request IDs are at
+/// least twice as long, so the rest can't be guessed within the TTL.
+const PREFIX_LEN: usize = 8;
+
+/// Bytes of the HMAC kept by [`request_hash`].
+const REQUEST_HASH_LEN: usize = 16;
+
+static MODE: OnceLock<IdLogging> = OnceLock::new();
+
+/// How request IDs show up in logs.
+pub enum IdLogging {
+    /// The first few characters, enough to tell requests apart in a log.
+    Prefix,
+    /// `#` and the [`request_hash`] under this key, which matches the hash in
+    /// audit events when both use the same key.
+    Hash(hmac::Key),
+    /// The raw ID. Only for debugging; the binary only allows it with debug
+    /// features on.
+    Full,
+}
+
+impl std::fmt::Debug for IdLogging {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prefix => write!(f, "Prefix"),
+            Self::Hash(_) => write!(f, "Hash"),
+            Self::Full => write!(f, "Full"),
+        }
+    }
+}
+
+impl IdLogging {
+    /// Parse the mode from its config representation: `prefix`, `hash` or
+    /// `full`. `hash_key` is only used by `hash` (see [`hash_key`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the mode is unknown or the key
+    /// is invalid.
+    pub fn from_config(mode: &str, hash_key: Option<&str>) -> Result<Self, String> {
+        match mode.trim() {
+            "prefix" => Ok(Self::Prefix),
+            "hash" => self::hash_key(hash_key).map(Self::Hash),
+            "full" => Ok(Self::Full),
+            other => Err(format!("unknown request ID logging mode {other:?}")),
+        }
+    }
+
+    /// Make this the mode of every [`id`] logged from now on. Only the first
+    /// call has an effect; until then IDs are logged as [`IdLogging::Prefix`].
+    pub fn install(self) {
+        if MODE.set(self).is_err() {
+            tracing::warn!("Request ID logging mode was already set");
+        }
+    }
+
+    fn write(&self, request_id: &str, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prefix => {
+                let end = request_id
+                    .char_indices()
+                    .nth(PREFIX_LEN)
+                    .map_or(request_id.len(), |(i, _)| i);
+                write!(f, "{}…", &request_id[..end])
+            }
+            Self::Hash(key) => write!(f, "#{}", request_hash(key, request_id)),
+            Self::Full => write!(f, "{request_id}"),
+        }
+    }
+}
+
+/// A request ID as it should appear in logs.
+pub struct Redacted<'a>(&'a str);
+
+impl Display for Redacted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        MODE.get().unwrap_or(&IdLogging::Prefix).write(self.0, f)
+    }
+}
+
+/// Render `request_id` for a log line.
+#[must_use]
+pub const fn id(request_id: &str) -> Redacted<'_> {
+    Redacted(request_id)
+}
+
+/// Parse a base64 HMAC key of at least 32 bytes, or generate a random one if
+/// there is none, in which case hashes only match within this process.
+///
+/// # Errors
+///
+/// Returns a description of the problem if the key is not valid base64 or too
+/// short.
+pub fn hash_key(raw: Option<&str>) -> Result<hmac::Key, String> {
+    let Some(raw) = raw else {
+        return hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map_err(|_| "failed to generate a request hash key".to_string());
+    };
+
+    let key = STANDARD
+        .decode(raw.trim())
+        .map_err(|e| format!("request hash key is not base64: {e}"))?;
+    if key.len() < 32 {
+        return Err("request hash key must be at least 32 bytes".to_string());
+    }
+    Ok(hmac::Key::new(hmac::HMAC_SHA256, &key))
+}
+
+/// Hex of a truncated HMAC-SHA256 of `request_id` under `key`.
+#[must_use]
+pub fn request_hash(key: &hmac::Key, request_id: &str) -> String {
+    use std::fmt::Write;
+
+    hmac::sign(key, request_id.as_bytes()).as_ref()[..REQUEST_HASH_LEN]
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+    const ID: &str = "3f1c2a9b-0d4e-4b7a-9c1f-8e2d3a4b5c6d";
+
+    struct Logged<'a>(&'a IdLogging);
+
+    impl Display for Logged<'_> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.write(ID, f)
+        }
+    }
+
+    #[test]
+    fn renders_each_mode() {
+        let logged =
+            |mode: &str| Logged(&IdLogging::from_config(mode, Some(KEY)).unwrap()).to_string();
+
+        assert_eq!(logged("prefix"), "3f1c2a9b…");
+        assert_eq!(logged("full"), ID);
+
+        let hashed = logged("hash");
+        assert_eq!(hashed.len(), 1 + REQUEST_HASH_LEN * 2);
+        assert!(!hashed.contains("3f1c2a9b"));
+        assert_eq!(hashed, logged("hash"));
+        assert_ne!(
+            hashed,
+            Logged(&IdLogging::from_config("hash", None).unwrap()).to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        assert!(IdLogging::from_config("debug", None).is_err());
+        assert!(IdLogging::from_config("hash", Some("c2hvcnQ=")).is_err());
+        assert!(hash_key(Some("not base64")).is_err());
+    }
+}
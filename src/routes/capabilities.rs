@@ -0,0 +1,192 @@
+//! Capability negotiation through a single `bridge-capabilities` header.
+//!
+//! Clients list the optional features they understand, comma-separated (e.g.
+//! `bridge-capabilities: app-overrides, idkit-flow-id`). Tokens are
+//! case-insensitive and unknown ones are ignored, so SDKs can advertise
+//! features a given deployment doesn't have yet. Routes that consult the set
+//! echo the capabilities they applied in the same header on the response (empty
+//! if none), which is how an SDK tells a bridge that supports a feature from one
+//! that predates it.
+
+use std::{collections::BTreeSet, fmt::Display, str::FromStr};
+
+use aide::{
+    gen::GenContext,
+    openapi::{Operation, Response as OpenApiResponse},
+    OperationInput, OperationOutput,
+};
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+
+pub const CAPABILITIES_HEADER: &str = "bridge-capabilities";
+
+/// An optional feature a client can opt in to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// `app_overrides` on `POST /request`. Supersedes the
+    /// `supports_app_overrides` body flag.
+    AppOverrides,
+    /// `idkit_flow_id` on `GET /request/:request_id`. Supersedes the
+    /// `accept-idkit-flow-id` header.
+    IdkitFlowId,
+    /// Two-phase `GET /request/:request_id`: lease the payload and delete it
+    /// on `POST /request/:request_id/ack`. Not applied to fan-out or chunked
+    /// requests.
+    Lease,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AppOverrides => write!(f, "app-overrides"),
+            Self::IdkitFlowId => write!(f, "idkit-flow-id"),
+            Self::Lease => write!(f, "lease"),
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "app-overrides" => Ok(Self::AppOverrides),
+            "idkit-flow-id" => Ok(Self::IdkitFlowId),
+            "lease" => Ok(Self::Lease),
+            _ => Err(format!("Unknown capability: {s}")),
+        }
+    }
+}
+
+/// A set of capabilities: those a client advertised, or those a route applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities(BTreeSet<Capability>);
+
+impl Capabilities {
+    /// Parse every `bridge-capabilities` header, skipping unknown tokens.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self(
+            headers
+                .get_all(CAPABILITIES_HEADER)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(|token| token.trim().parse().ok())
+                .collect(),
+        )
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0.contains(&capability)
+    }
+
+    pub fn insert(&mut self, capability: Capability) {
+        self.0.insert(capability);
+    }
+
+    /// Record `capability` as applied if `enabled`, and return `enabled`.
+    pub fn apply_if(&mut self, capability: Capability, enabled: bool) -> bool {
+        if enabled {
+            self.insert(capability);
+        }
+        enabled
+    }
+
+    fn to_header_value(&self) -> HeaderValue {
+        let value = self
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        HeaderValue::from_str(&value).expect("capability tokens are valid header values")
+    }
+}
+
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Capabilities {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+impl OperationInput for Capabilities {}
+
+/// A response that echoes the capabilities applied to it in
+/// [`CAPABILITIES_HEADER`]. Documented as `T`.
+pub struct WithCapabilities<T> {
+    pub applied: Capabilities,
+    pub inner: T,
+}
+
+impl<T: IntoResponse> IntoResponse for WithCapabilities<T> {
+    fn into_response(self) -> Response {
+        let mut response = self.inner.into_response();
+        response
+            .headers_mut()
+            .insert(CAPABILITIES_HEADER, self.applied.to_header_value());
+        response
+    }
+}
+
+impl<T: OperationOutput> OperationOutput for WithCapabilities<T> {
+    type Inner = T::Inner;
+
+    fn operation_response(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Option<OpenApiResponse> {
+        T::operation_response(ctx, operation)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, OpenApiResponse)> {
+        T::inferred_responses(ctx, operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lenient_header_lists() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            CAPABILITIES_HEADER,
+            HeaderValue::from_static(" IDKIT-flow-id ,future-thing,"),
+        );
+        headers.append(
+            CAPABILITIES_HEADER,
+            HeaderValue::from_static("app-overrides"),
+        );
+
+        let capabilities = Capabilities::from_headers(&headers);
+        assert!(capabilities.contains(Capability::AppOverrides));
+        assert!(capabilities.contains(Capability::IdkitFlowId));
+        assert_eq!(
+            capabilities.to_header_value(),
+            "app-overrides, idkit-flow-id"
+        );
+
+        assert_eq!(
+            Capabilities::from_headers(&HeaderMap::new()),
+            Capabilities::default()
+        );
+        assert_eq!(Capabilities::default().to_header_value(), "");
+    }
+}
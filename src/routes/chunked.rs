@@ -0,0 +1,676 @@
+//! Payloads stored as a sequence of chunks rather than a single Redis value.
+//!
+//! A chunked payload leaves a small manifest under the usual `req:`/`res:` key
+//! and its bytes under `chunk:<upload_id>:<index>`. Reads stream the chunks
+//! back in order, consuming each one as it's sent, so large payloads are never
+//! assembled in memory and keep their single-use semantics.
+//!
+//! Chunks come from explicit chunked uploads (see [`super::upload`]) or from
+//! large `application/octet-stream` bodies, which are spooled into chunks as
+//! they arrive instead of being buffered whole.
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::{stream, StreamExt};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use ring::digest;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+use super::negotiate::{PayloadFormat, CBOR, IV_HEADER, OCTET_STREAM, STATUS_HEADER};
+use super::request::IDKIT_FLOW_ID_HEADER;
+use crate::envelope::StorageEncryption;
+use crate::redact;
+use crate::utils::{
+    handle_redis_error, ApiError, PayloadField, RequestPayload, RequestStatus, Stored,
+    EXPIRE_AFTER_SECONDS, MAX_BODY_BYTES,
+};
+use crate::validation::{InvalidPayload, PayloadKind, PayloadValidation};
+
+pub const CHUNK_PREFIX: &str = "chunk:";
+
+/// Chunk size for spooled `application/octet-stream` bodies. Just under a
+/// power of two so sealed chunks aren't padded to twice their size.
+pub const SPOOL_CHUNK_BYTES: u32 = 256 * 1024 - 64;
+
+/// Prefix that tells a manifest apart from an inline payload. Inline payloads
+/// are CBOR maps (or legacy JSON objects) and never start with it.
+const MANIFEST_MARKER: &[u8] = b"chunks:";
+
+/// Where the pieces of a chunked payload live and how to reassemble them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub upload_id: String,
+    pub iv: PayloadField,
+    /// Total payload size in bytes.
+    pub size: u64,
+    /// Size of every chunk but the last.
+    pub chunk_size: u32,
+    /// SHA-256 of a spooled body, for idempotency fingerprints. Never stored.
+    #[serde(skip)]
+    pub digest: Option<Vec<u8>>,
+    /// The chunks of a body spooled by this request, deleted unless it is
+    /// stored. Never stored.
+    #[serde(skip)]
+    pub spooled: Option<SpooledChunks>,
+}
+
+/// Chunks written while spooling a body. Dropping this deletes them unless
+/// [`StoredPayload::keep`] was called, so a body the handler rejects, for
+/// whatever reason, doesn't sit in Redis for the TTL.
+pub struct SpooledChunks {
+    redis: ConnectionManager,
+    upload_id: String,
+    /// Chunks written, or being written, so far.
+    count: u64,
+    kept: AtomicBool,
+}
+
+impl SpooledChunks {
+    /// The first `count` chunks of `upload_id`, deleted on drop unless kept.
+    #[must_use]
+    pub const fn new(redis: ConnectionManager, upload_id: String, count: u64) -> Self {
+        Self {
+            redis,
+            upload_id,
+            count,
+            kept: AtomicBool::new(false),
+        }
+    }
+}
+
+impl std::fmt::Debug for SpooledChunks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpooledChunks")
+            .field("upload_id", &redact::id(&self.upload_id).to_string())
+            .field("count", &self.count)
+            .field("kept", &self.kept)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for SpooledChunks {
+    fn drop(&mut self) {
+        if self.count == 0 || self.kept.load(Ordering::Acquire) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let keys: Vec<String> = (0..self.count)
+            .map(|index| ChunkManifest::chunk_key(&self.upload_id, index))
+            .collect();
+        let mut redis = self.redis.clone();
+        let upload_id = std::mem::take(&mut self.upload_id);
+        runtime.spawn(async move {
+            if let Err(e) = redis.del::<_, ()>(keys).await {
+                tracing::warn!(
+                    "Failed to delete rejected chunks of {}: {e}",
+                    redact::id(&upload_id)
+                );
+            }
+        });
+    }
+}
+
+impl ChunkManifest {
+    #[must_use]
+    pub fn chunk_count(&self) -> u64 {
+        self.size.div_ceil(u64::from(self.chunk_size))
+    }
+
+    #[must_use]
+    pub fn chunk_key(upload_id: &str, index: u64) -> String {
+        format!("{CHUNK_PREFIX}{upload_id}:{index}")
+    }
+
+    /// Encode the manifest for storage under the request or response key.
+    pub fn to_stored(&self) -> Result<Vec<u8>, StatusCode> {
+        let mut out = MANIFEST_MARKER.to_vec();
+        ciborium::into_writer(self, &mut out).map_err(|e| {
+            tracing::error!("Failed to encode chunk manifest: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(out)
+    }
+}
+
+/// A payload as stored under a request or response key: inline, or a
+/// manifest pointing at its chunks.
+///
+/// Only ever deserialized as [`StoredPayload::Inline`], so clients can't
+/// submit a manifest pointing at someone else's chunks.
+#[derive(Debug)]
+pub enum StoredPayload {
+    Inline(RequestPayload),
+    Chunked(ChunkManifest),
+}
+
+impl StoredPayload {
+    pub fn from_stored(bytes: &[u8]) -> Result<Self, StatusCode> {
+        let Some(manifest) = bytes.strip_prefix(MANIFEST_MARKER) else {
+            return RequestPayload::from_stored(bytes).map(Self::Inline);
+        };
+
+        ciborium::from_reader(manifest)
+            .map(Self::Chunked)
+            .map_err(|e| {
+                tracing::error!("Failed to decode chunk manifest: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })
+    }
+
+    pub fn to_stored(&self) -> Result<Vec<u8>, StatusCode> {
+        match self {
+            Self::Inline(payload) => payload.to_stored(),
+            Self::Chunked(manifest) => manifest.to_stored(),
+        }
+    }
+
+    /// Bytes that identify the payload in an idempotency fingerprint: the
+    /// stored form of an inline payload, or the digest of a spooled body.
+    pub fn fingerprint(&self) -> Result<Vec<u8>, StatusCode> {
+        match self {
+            Self::Inline(payload) => payload.to_stored(),
+            Self::Chunked(manifest) => manifest
+                .digest
+                .clone()
+                .ok_or(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    /// Whether the payload can be sent as `application/octet-stream`, which
+    /// needs raw bytes: a field stored as a non-base64 string has none. Chunks
+    /// are always bytes, so only a chunked payload's IV matters.
+    pub fn has_octet_stream_form(&self) -> bool {
+        match self {
+            Self::Inline(payload) => {
+                payload.iv().as_bytes().is_some() && payload.payload().as_bytes().is_some()
+            }
+            Self::Chunked(manifest) => manifest.iv.as_bytes().is_some(),
+        }
+    }
+
+    /// Keep the chunks of a spooled payload once the manifest pointing at them
+    /// is stored. Without this, they are deleted when the payload is dropped.
+    pub fn keep(&self) {
+        if let Self::Chunked(ChunkManifest {
+            spooled: Some(spooled),
+            ..
+        }) = self
+        {
+            spooled.kept.store(true, Ordering::Release);
+        }
+    }
+
+    /// Size of the payload in bytes: decoded for base64 payloads, the recorded
+    /// size for chunked ones.
+    pub const fn size(&self) -> u64 {
+        match self {
+            Self::Inline(payload) => match payload.payload() {
+                PayloadField::Bytes(bytes) => bytes.len() as u64,
+                PayloadField::Text(text) => text.len() as u64,
+            },
+            Self::Chunked(manifest) => manifest.size,
+        }
+    }
+
+    /// Check the payload against `validation`, using the recorded size for
+    /// chunked payloads.
+    ///
+    /// # Errors
+    ///
+    /// See [`PayloadValidation::validate`].
+    pub fn validate(
+        &self,
+        validation: &PayloadValidation,
+        kind: PayloadKind,
+    ) -> Result<(), InvalidPayload> {
+        match self {
+            Self::Inline(payload) => validation.validate(kind, payload),
+            Self::Chunked(manifest) => {
+                validation.validate_iv(&manifest.iv)?;
+                validation.validate_size(kind, usize::try_from(manifest.size).unwrap_or(usize::MAX))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredPayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RequestPayload::deserialize(deserializer).map(Self::Inline)
+    }
+}
+
+impl JsonSchema for StoredPayload {
+    fn schema_name() -> String {
+        RequestPayload::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        RequestPayload::json_schema(gen)
+    }
+}
+
+/// Seal and store chunk `index` of `upload_id`.
+pub async fn store_chunk(
+    redis: &mut ConnectionManager,
+    encryption: &StorageEncryption,
+    upload_id: &str,
+    index: u64,
+    chunk: &[u8],
+) -> Result<(), StatusCode> {
+    let key = ChunkManifest::chunk_key(upload_id, index);
+    let sealed = encryption.seal(&key, chunk)?;
+
+    redis
+        .set_ex::<_, _, ()>(key, sealed, EXPIRE_AFTER_SECONDS)
+        .await
+        .map_err(handle_redis_error)
+}
+
+/// Read a raw payload body, storing it in [`SPOOL_CHUNK_BYTES`] chunks as it
+/// arrives. Bodies that fit in a single chunk are kept inline.
+///
+/// The IV and the size limit for `kind` are checked up front and as the body
+/// streams in, so an oversized body is cut off at the limit. Chunks are
+/// deleted if spooling fails, and later if the handler never stores the
+/// payload (see [`SpooledChunks`]).
+///
+/// # Errors
+///
+/// Returns [`StatusCode::PAYLOAD_TOO_LARGE`] past [`MAX_BODY_BYTES`],
+/// [`InvalidPayload`] past the limit for `kind` or for an invalid IV, and
+/// [`StatusCode::BAD_REQUEST`] if the body can't be read.
+pub async fn spool(
+    body: Body,
+    iv: PayloadField,
+    (validation, kind): (&PayloadValidation, PayloadKind),
+    redis: &mut ConnectionManager,
+    encryption: &StorageEncryption,
+) -> Result<StoredPayload, ApiError> {
+    validation.validate_iv(&iv)?;
+
+    let chunk_size = SPOOL_CHUNK_BYTES as usize;
+    let mut stream = body.into_data_stream();
+    let mut buffer = Vec::new();
+    let mut spooled: Option<SpooledChunks> = None;
+    let mut size = 0;
+    let mut digest = digest::Context::new(&digest::SHA256);
+
+    while let Some(data) = stream.next().await {
+        let mut data = data.map_err(|_| StatusCode::BAD_REQUEST)?;
+        size += data.len();
+        if size > MAX_BODY_BYTES {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+        }
+        validation.validate_size(kind, size)?;
+        digest.update(&data);
+
+        while !data.is_empty() {
+            let take = (chunk_size - buffer.len()).min(data.len());
+            buffer.extend_from_slice(&data.split_to(take));
+
+            if buffer.len() == chunk_size {
+                let chunks = spooled.get_or_insert_with(|| {
+                    SpooledChunks::new(redis.clone(), Uuid::new_v4().to_string(), 0)
+                });
+                chunks.count += 1;
+                store_chunk(
+                    redis,
+                    encryption,
+                    &chunks.upload_id,
+                    chunks.count - 1,
+                    &buffer,
+                )
+                .await?;
+                buffer.clear();
+            }
+        }
+    }
+
+    let Some(mut chunks) = spooled else {
+        return Ok(StoredPayload::Inline(RequestPayload::new(iv, buffer)));
+    };
+    if !buffer.is_empty() {
+        chunks.count += 1;
+        store_chunk(
+            redis,
+            encryption,
+            &chunks.upload_id,
+            chunks.count - 1,
+            &buffer,
+        )
+        .await?;
+    }
+
+    Ok(StoredPayload::Chunked(ChunkManifest {
+        upload_id: chunks.upload_id.clone(),
+        iv,
+        size: size as u64,
+        chunk_size: SPOOL_CHUNK_BYTES,
+        digest: Some(digest.finish().as_ref().to_vec()),
+        spooled: Some(chunks),
+    }))
+}
+
+/// Stream a chunked request in the shape of `GET /request/:id`.
+pub fn stream_request(
+    format: PayloadFormat,
+    redis: ConnectionManager,
+    encryption: Arc<StorageEncryption>,
+    manifest: ChunkManifest,
+    idkit_flow_id: Option<&str>,
+) -> Result<Response, StatusCode> {
+    let mut frame = Frame::default();
+
+    match format {
+        PayloadFormat::Json => {
+            frame.prefix.extend_from_slice(b"{\"iv\":");
+            frame.prefix.extend(json(&manifest.iv)?);
+            frame.prefix.extend_from_slice(b",\"payload\":\"");
+            frame.suffix.push(b'"');
+            if let Some(flow_id) = idkit_flow_id {
+                frame.suffix.extend_from_slice(b",\"idkit_flow_id\":");
+                frame.suffix.extend(json(&flow_id)?);
+            }
+            frame.suffix.push(b'}');
+        }
+        PayloadFormat::Cbor => {
+            frame
+                .prefix
+                .extend(cbor_header(5, if idkit_flow_id.is_some() { 3 } else { 2 }));
+            frame.prefix.extend(cbor(&"iv")?);
+            frame.prefix.extend(cbor(&manifest.iv)?);
+            frame.prefix.extend(cbor(&"payload")?);
+            frame.prefix.push(INDEFINITE_BYTES);
+            frame.suffix.push(BREAK);
+            if let Some(flow_id) = idkit_flow_id {
+                frame.suffix.extend(cbor(&"idkit_flow_id")?);
+                frame.suffix.extend(cbor(&flow_id)?);
+            }
+        }
+        PayloadFormat::OctetStream => {
+            frame.headers = octet_headers(&manifest.iv)?;
+            if let Some(flow_id) = idkit_flow_id {
+                frame.headers.insert(
+                    IDKIT_FLOW_ID_HEADER,
+                    HeaderValue::from_str(flow_id)
+                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                );
+            }
+        }
+    }
+
+    Ok(frame.into_response(format, redis, encryption, manifest))
+}
+
+/// Stream a chunked response in the shape of a completed `GET /response/:id`.
+pub fn stream_response(
+    format: PayloadFormat,
+    redis: ConnectionManager,
+    encryption: Arc<StorageEncryption>,
+    manifest: ChunkManifest,
+) -> Result<Response, StatusCode> {
+    let status = RequestStatus::Completed;
+    let mut frame = Frame::default();
+
+    match format {
+        PayloadFormat::Json => {
+            frame.prefix.extend_from_slice(b"{\"status\":");
+            frame.prefix.extend(json(&status)?);
+            frame.prefix.extend_from_slice(b",\"response\":{\"iv\":");
+            frame.prefix.extend(json(&manifest.iv)?);
+            frame.prefix.extend_from_slice(b",\"payload\":\"");
+            frame.suffix.extend_from_slice(b"\"}}");
+        }
+        PayloadFormat::Cbor => {
+            frame.prefix.extend(cbor_header(5, 2));
+            frame.prefix.extend(cbor(&"status")?);
+            frame.prefix.extend(cbor(&status)?);
+            frame.prefix.extend(cbor(&"response")?);
+            frame.prefix.extend(cbor_header(5, 2));
+            frame.prefix.extend(cbor(&"iv")?);
+            frame.prefix.extend(cbor(&manifest.iv)?);
+            frame.prefix.extend(cbor(&"payload")?);
+            frame.prefix.push(INDEFINITE_BYTES);
+            frame.suffix.push(BREAK);
+        }
+        PayloadFormat::OctetStream => {
+            frame.headers = octet_headers(&manifest.iv)?;
+            frame.headers.insert(
+                STATUS_HEADER,
+                HeaderValue::from_str(&status.to_string())
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+    }
+
+    Ok(frame.into_response(format, redis, encryption, manifest))
+}
+
+/// CBOR initial byte of an indefinite-length byte string.
+const INDEFINITE_BYTES: u8 = 0x5f;
+/// CBOR "break" stop code ending an indefinite-length item.
+const BREAK: u8 = 0xff;
+
+/// Everything in a streamed response body except the payload bytes.
+#[derive(Default)]
+struct Frame {
+    headers: HeaderMap,
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+}
+
+enum Stage {
+    Prefix,
+    Chunk(u64),
+    Done,
+}
+
+struct StreamState {
+    stage: Stage,
+    frame: Frame,
+    format: PayloadFormat,
+    base64: Base64Carry,
+    redis: ConnectionManager,
+    encryption: Arc<StorageEncryption>,
+    manifest: ChunkManifest,
+}
+
+impl Frame {
+    fn into_response(
+        mut self,
+        format: PayloadFormat,
+        redis: ConnectionManager,
+        encryption: Arc<StorageEncryption>,
+        manifest: ChunkManifest,
+    ) -> Response {
+        let content_type = match format {
+            PayloadFormat::Json => "application/json",
+            PayloadFormat::Cbor => CBOR,
+            PayloadFormat::OctetStream => OCTET_STREAM,
+        };
+        let headers = std::mem::take(&mut self.headers);
+
+        let state = StreamState {
+            stage: Stage::Prefix,
+            frame: self,
+            format,
+            base64: Base64Carry::default(),
+            redis,
+            encryption,
+            manifest,
+        };
+
+        let body = Body::from_stream(stream::unfold(state, |mut state| async move {
+            let item = match state.stage {
+                Stage::Prefix => {
+                    state.stage = Stage::Chunk(0);
+                    Ok(Bytes::from(std::mem::take(&mut state.frame.prefix)))
+                }
+                Stage::Chunk(index) if index < state.manifest.chunk_count() => {
+                    state.stage = Stage::Chunk(index + 1);
+                    state.next_chunk(index).await
+                }
+                Stage::Chunk(_) => {
+                    state.stage = Stage::Done;
+                    let mut tail = state.base64.finish();
+                    tail.append(&mut state.frame.suffix);
+                    Ok(Bytes::from(tail))
+                }
+                Stage::Done => return None,
+            };
+
+            if item.is_err() {
+                state.stage = Stage::Done;
+            }
+            Some((item, state))
+        }));
+
+        (headers, [(CONTENT_TYPE, content_type)], body).into_response()
+    }
+}
+
+impl StreamState {
+    /// Take chunk `index` out of Redis and encode it for the response format.
+    async fn next_chunk(&mut self, index: u64) -> Result<Bytes, io::Error> {
+        let key = ChunkManifest::chunk_key(&self.manifest.upload_id, index);
+        let sealed: Option<Vec<u8>> = self.redis.get_del(&key).await.map_err(|e| {
+            tracing::error!("Redis error while streaming chunk {index}: {e}");
+            io::Error::other("failed to read chunk")
+        })?;
+
+        // A chunk can only be missing if it expired mid-stream; abort rather
+        // than send a silently truncated payload.
+        let sealed = sealed.ok_or_else(|| {
+            tracing::error!("Chunk {index} of upload disappeared while streaming");
+            io::Error::other("missing chunk")
+        })?;
+        let chunk = self
+            .encryption
+            .open(&key, &sealed)
+            .map_err(|_| io::Error::other("failed to open chunk"))?;
+
+        Ok(Bytes::from(match self.format {
+            PayloadFormat::Json => self.base64.encode(&chunk),
+            PayloadFormat::Cbor => {
+                let mut out = cbor_header(2, chunk.len() as u64);
+                out.extend_from_slice(&chunk);
+                out
+            }
+            PayloadFormat::OctetStream => chunk,
+        }))
+    }
+}
+
+/// Incremental base64 encoder: holds back the 0–2 bytes that don't fill a
+/// complete 3-byte group until more input (or the end) arrives.
+#[derive(Default)]
+struct Base64Carry(Vec<u8>);
+
+impl Base64Carry {
+    fn encode(&mut self, mut input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity((self.0.len() + input.len()) / 3 * 4);
+
+        if !self.0.is_empty() {
+            let take = (3 - self.0.len()).min(input.len());
+            self.0.extend_from_slice(&input[..take]);
+            input = &input[take..];
+            if self.0.len() < 3 {
+                return out;
+            }
+            out.extend_from_slice(STANDARD.encode(&self.0).as_bytes());
+            self.0.clear();
+        }
+
+        let whole = input.len() / 3 * 3;
+        out.extend_from_slice(STANDARD.encode(&input[..whole]).as_bytes());
+        self.0.extend_from_slice(&input[whole..]);
+        out
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let out = STANDARD.encode(&self.0).into_bytes();
+        self.0.clear();
+        out
+    }
+}
+
+fn octet_headers(iv: &PayloadField) -> Result<HeaderMap, StatusCode> {
+    let iv = iv.as_bytes().ok_or(StatusCode::NOT_ACCEPTABLE)?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        IV_HEADER,
+        HeaderValue::from_str(&STANDARD.encode(iv)).map_err(|_| StatusCode::NOT_ACCEPTABLE)?,
+    );
+    Ok(headers)
+}
+
+fn json(value: &impl Serialize) -> Result<Vec<u8>, StatusCode> {
+    serde_json::to_vec(value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn cbor(value: &impl Serialize) -> Result<Vec<u8>, StatusCode> {
+    let mut out = Vec::new();
+    ciborium::into_writer(value, &mut out).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(out)
+}
+
+/// Encode a CBOR item header for `major` type with argument `len`.
+// Each arm only casts values its range guarantees fit.
+#[allow(clippy::cast_possible_truncation)]
+fn cbor_header(major: u8, len: u64) -> Vec<u8> {
+    let major = major << 5;
+    match len {
+        0..=23 => vec![major | len as u8],
+        24..=0xff => vec![major | 24, len as u8],
+        0x100..=0xffff => [&[major | 25][..], &(len as u16).to_be_bytes()].concat(),
+        0x1_0000..=0xffff_ffff => [&[major | 26][..], &(len as u32).to_be_bytes()].concat(),
+        _ => [&[major | 27][..], &len.to_be_bytes()].concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_carry_matches_one_shot_encoding() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        for split in [1, 2, 3, 4, 5, 7, 64, 999] {
+            let mut carry = Base64Carry::default();
+            let mut out = Vec::new();
+            for chunk in data.chunks(split) {
+                out.extend(carry.encode(chunk));
+            }
+            out.extend(carry.finish());
+            assert_eq!(out, STANDARD.encode(&data).into_bytes(), "split {split}");
+        }
+    }
+
+    #[test]
+    fn cbor_headers_match_ciborium() {
+        for len in [0u64, 23, 24, 255, 256, 65_535, 65_536] {
+            let mut expected = Vec::new();
+            let bytes = ciborium::Value::Bytes(vec![0; usize::try_from(len).unwrap()]);
+            ciborium::into_writer(&bytes, &mut expected).unwrap();
+            let header = cbor_header(2, len);
+            assert_eq!(header, expected[..header.len()], "len {len}");
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! `Idempotency-Key` support for the creating routes (`POST /request`,
+//! `POST /response`).
+//!
+//! The first request under a key reserves it and, once it succeeds, records
+//! its result for the request TTL. A retry with the same key and the same body
+//! gets the recorded result back instead of creating a second request; a retry
+//! with a different body gets `422`, and one that arrives while the first is
+//! still running gets `409`. Failed attempts release the key so they can be
+//! retried.
+//!
+//! Keys are global rather than per client, so clients should use a random
+//! value such as a UUID.
+
+use aide::OperationInput;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use ring::digest;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::envelope::StorageEncryption;
+use crate::utils::{
+    handle_redis_error, ApiError, EXPIRE_AFTER_SECONDS, IDEMPOTENCY_PENDING_SECONDS,
+    IDEMPOTENCY_PREFIX,
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_KEY_LEN: usize = 255;
+
+/// The request's `Idempotency-Key`, if it sent one. Keys must be 1 to 255
+/// visible ASCII characters (`400` otherwise).
+#[derive(Debug, Default)]
+pub struct IdempotencyKey(Option<String>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IdempotencyKey {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.headers.get(IDEMPOTENCY_KEY_HEADER) else {
+            return Ok(Self(None));
+        };
+        let key = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+        if key.is_empty() || key.len() > MAX_KEY_LEN || !key.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        Ok(Self(Some(key.to_string())))
+    }
+}
+
+impl OperationInput for IdempotencyKey {}
+
+/// What makes two requests under the same key "identical": the route and every
+/// input that affects the result.
+pub struct Fingerprint {
+    route: &'static str,
+    digest: digest::Context,
+}
+
+impl Fingerprint {
+    /// Start a fingerprint for `route`, which also namespaces the key.
+    pub fn new(route: &'static str) -> Self {
+        Self {
+            route,
+            digest: digest::Context::new(&digest::SHA256),
+        }
+        .with(route)
+    }
+
+    /// Add one input. Inputs are length-prefixed so they can't run together.
+    #[must_use]
+    pub fn with(mut self, part: impl AsRef<[u8]>) -> Self {
+        let part = part.as_ref();
+        self.digest.update(&(part.len() as u64).to_be_bytes());
+        self.digest.update(part);
+        self
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.digest.finish().as_ref().to_vec()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record<T> {
+    fingerprint: Vec<u8>,
+    /// `None` while the first request is still running.
+    result: Option<T>,
+}
+
+/// The outcome of [`IdempotencyKey::claim`].
+pub enum Claim<T> {
+    /// Nothing was recorded under the key: run the request, then
+    /// [`Reservation::settle`] it.
+    Fresh(Reservation),
+    /// An identical request already succeeded with this result.
+    Replay(T),
+}
+
+/// A key reserved for the request being handled. A no-op without a key.
+pub struct Reservation {
+    key: Option<String>,
+    fingerprint: Vec<u8>,
+}
+
+impl IdempotencyKey {
+    /// Reserve the key for the fingerprinted route, or return the result
+    /// recorded for an identical earlier request.
+    ///
+    /// # Errors
+    ///
+    /// `422` if the key was used with a different body, `409` if an identical
+    /// request is still running.
+    pub async fn claim<T: DeserializeOwned>(
+        self,
+        redis: &mut ConnectionManager,
+        encryption: &StorageEncryption,
+        fingerprint: Fingerprint,
+    ) -> Result<Claim<T>, ApiError> {
+        let route = fingerprint.route;
+        let fingerprint = fingerprint.finish();
+        let Some(key) = self.0 else {
+            return Ok(Claim::Fresh(Reservation {
+                key: None,
+                fingerprint,
+            }));
+        };
+        let key = format!("{IDEMPOTENCY_PREFIX}{route}:{key}");
+
+        let pending = Record::<()> {
+            fingerprint,
+            result: None,
+        };
+        let reserved: Option<String> = redis
+            .set_options(
+                &key,
+                encryption.seal(&key, &encode(&pending)?)?,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::NX)
+                    .with_expiration(SetExpiry::EX(IDEMPOTENCY_PENDING_SECONDS)),
+            )
+            .await
+            .map_err(handle_redis_error)?;
+        if reserved.is_some() {
+            return Ok(Claim::Fresh(Reservation {
+                key: Some(key),
+                fingerprint: pending.fingerprint,
+            }));
+        }
+
+        let stored: Option<Vec<u8>> = redis.get(&key).await.map_err(handle_redis_error)?;
+        // The reservation expired between the two calls; let the client retry.
+        let stored = stored.ok_or(StatusCode::CONFLICT)?;
+        let record: Record<T> =
+            serde_json::from_slice(&encryption.open(&key, &stored)?).map_err(|e| {
+                tracing::error!("Failed to decode idempotency record: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        if record.fingerprint != pending.fingerprint {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY.into());
+        }
+        record
+            .result
+            .map(Claim::Replay)
+            .ok_or_else(|| StatusCode::CONFLICT.into())
+    }
+}
+
+impl Reservation {
+    /// Record a successful `result` for replay, or release the key after a
+    /// failure so the client can retry. Returns `result` either way.
+    ///
+    /// Failing to record a result doesn't fail the request, which has already
+    /// taken effect; retries get `409` until the reservation expires.
+    pub async fn settle<T: Serialize + Send, E: Send>(
+        self,
+        redis: &mut ConnectionManager,
+        encryption: &StorageEncryption,
+        result: Result<T, E>,
+    ) -> Result<T, E> {
+        let Some(key) = self.key else {
+            return result;
+        };
+
+        let record = result.as_ref().ok().map(|value| {
+            encode(&Record {
+                fingerprint: self.fingerprint,
+                result: Some(value),
+            })
+            .and_then(|bytes| encryption.seal(&key, &bytes))
+        });
+        match record {
+            Some(Ok(stored)) => {
+                if let Err(e) = redis
+                    .set_ex::<_, _, ()>(&key, stored, EXPIRE_AFTER_SECONDS)
+                    .await
+                {
+                    tracing::error!("Failed to record idempotent result: {e}");
+                }
+            }
+            // Already logged; the reservation expires on its own.
+            Some(Err(_)) => {}
+            None => {
+                if let Err(e) = redis.del::<_, ()>(&key).await {
+                    tracing::error!("Failed to release idempotency key: {e}");
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn encode<T: Serialize>(record: &Record<T>) -> Result<Vec<u8>, StatusCode> {
+    serde_json::to_vec(record).map_err(|e| {
+        tracing::error!("Failed to encode idempotency record: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_inputs_dont_run_together() {
+        let a = Fingerprint::new("request").with("ab").with("c").finish();
+        let b = Fingerprint::new("request").with("a").with("bc").finish();
+        let c = Fingerprint::new("response").with("ab").with("c").finish();
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a, Fingerprint::new("request").with("ab").with("c").finish());
+    }
+}
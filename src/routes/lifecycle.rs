@@ -0,0 +1,107 @@
+//! The one place request status transitions are recorded.
+//!
+//! Handlers call [`Lifecycle::record`] after each transition is stored. It logs
+//! it, publishes it on the notification bus, and emits an audit event with the
+//! route and the class of the caller's address.
+
+use std::{net::IpAddr, net::SocketAddr, sync::Arc};
+
+use aide::OperationInput;
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, MatchedPath},
+    http::{request::Parts, StatusCode},
+};
+use redis::aio::ConnectionManager;
+
+use crate::audit::{Audit, IpClass};
+use crate::notify::Notifier;
+use crate::redact;
+use crate::utils::RequestStatus;
+
+/// Set by the load balancer in front of the bridge. Only its class is ever
+/// recorded, so a spoofed value can't do more than skew the statistics.
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Records the status transitions of the request being handled.
+pub struct Lifecycle {
+    notifier: Arc<Notifier>,
+    audit: Arc<Audit>,
+    /// Method and matched route, e.g. `PUT /response/:request_id`.
+    route: String,
+    client_ip: IpClass,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Lifecycle {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let (Some(notifier), Some(audit)) = (
+            parts.extensions.get::<Arc<Notifier>>().cloned(),
+            parts.extensions.get::<Arc<Audit>>().cloned(),
+        ) else {
+            tracing::error!("Lifecycle extensions are missing");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let path = parts
+            .extensions
+            .get::<MatchedPath>()
+            .map_or_else(|| parts.uri.path(), MatchedPath::as_str);
+        let forwarded = parts
+            .headers
+            .get(FORWARDED_FOR_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+        let connected = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+
+        Ok(Self {
+            notifier,
+            audit,
+            route: format!("{} {path}", parts.method),
+            client_ip: IpClass::of(forwarded.or(connected)),
+        })
+    }
+}
+
+impl OperationInput for Lifecycle {}
+
+impl Lifecycle {
+    /// Record that `request_id` moved from `from` (`None` if it's new) to `to`,
+    /// with a payload of `payload_bytes`. Best-effort, like the bus and the
+    /// audit sink it feeds.
+    pub async fn record(
+        &self,
+        redis: &mut ConnectionManager,
+        request_id: &str,
+        from: Option<RequestStatus>,
+        to: RequestStatus,
+        payload_bytes: Option<u64>,
+    ) {
+        tracing::info!(
+            "Request {} state transition: {} -> {to}",
+            redact::id(request_id),
+            from.as_ref()
+                .map_or_else(|| "new".to_string(), ToString::to_string)
+        );
+
+        self.notifier.publish(redis, request_id, to).await;
+
+        if self.audit.is_enabled() {
+            let event = self.audit.event(
+                request_id,
+                from,
+                to,
+                self.route.clone(),
+                payload_bytes,
+                self.client_ip,
+            );
+            self.audit.emit(redis, &event).await;
+        }
+    }
+}
@@ -1,12 +1,42 @@
 use aide::axum::ApiRouter;
+use axum::Router;
 
+use crate::environment::Feature;
+use crate::Config;
+
+mod capabilities;
+mod chunked;
+mod idempotency;
+mod lifecycle;
+mod negotiate;
 mod request;
 mod response;
+mod simulator;
 mod system;
+mod upload;
+mod v2;
 
-pub fn handler() -> ApiRouter {
-    ApiRouter::new()
+pub fn handler(config: &Config) -> ApiRouter {
+    let router = ApiRouter::new()
         .merge(system::handler())
-        .merge(request::handler())
-        .merge(response::handler())
+        // The unversioned paths predate `/v1` and stay as undocumented aliases
+        // of it for shipped clients.
+        .merge(Router::from(v1(config)))
+        .nest("/v1", v1(config))
+        .nest("/v2", v2::handler(config));
+
+    match &config.simulator_token {
+        Some(token) if config.enables(Feature::DebugEndpoints) => {
+            router.merge(simulator::handler(token))
+        }
+        _ => router,
+    }
+}
+
+/// The original contract, with its opt-in flags for newer response fields.
+fn v1(config: &Config) -> ApiRouter {
+    ApiRouter::new()
+        .merge(request::handler(config))
+        .merge(response::handler(config))
+        .merge(upload::handler(config))
 }
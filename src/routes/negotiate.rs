@@ -0,0 +1,446 @@
+//! Content negotiation for payload-carrying routes.
+//!
+//! JSON with base64 strings stays the default. Clients can instead send and
+//! receive CBOR (the same shape, with `iv` and `payload` as byte strings) or
+//! `application/octet-stream`, where the body is the raw payload and the IV and
+//! any other fields travel in `bridge-*` headers.
+
+use std::sync::Arc;
+
+use aide::{
+    gen::GenContext,
+    openapi::{MediaType, Operation, ReferenceOr, Response as OpenApiResponse, SchemaObject},
+    OperationInput, OperationOutput,
+};
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{FromRequest, FromRequestParts, RawPathParams, Request},
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        request::Parts,
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use axum_jsonschema::Json;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use indexmap::IndexMap;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use schemars::{
+    schema::{InstanceType, Schema, SchemaObject as JsonSchemaObject},
+    JsonSchema,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::chunked::{self, StoredPayload};
+use crate::envelope::StorageEncryption;
+use crate::utils::{
+    handle_redis_error, validate_request_id, ApiError, PayloadField, RequestPayload,
+};
+use crate::validation::{PayloadKind, PayloadValidation};
+
+/// Base64 IV of an `application/octet-stream` payload.
+pub const IV_HEADER: &str = "bridge-iv";
+/// Optional client-supplied `request_id` for `application/octet-stream` uploads.
+pub const REQUEST_ID_HEADER: &str = "bridge-request-id";
+/// Request status of an `application/octet-stream` `GET /response/:id`.
+pub const STATUS_HEADER: &str = "bridge-status";
+
+pub const CBOR: &str = "application/cbor";
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Wire format of a payload body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFormat {
+    Json,
+    Cbor,
+    OctetStream,
+}
+
+impl PayloadFormat {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next()?.trim();
+        if essence.eq_ignore_ascii_case("application/json") {
+            Some(Self::Json)
+        } else if essence.eq_ignore_ascii_case(CBOR) {
+            Some(Self::Cbor)
+        } else if essence.eq_ignore_ascii_case(OCTET_STREAM) {
+            Some(Self::OctetStream)
+        } else {
+            None
+        }
+    }
+
+    /// The first supported format listed in `Accept`, defaulting to JSON.
+    /// Quality values are ignored; clients list the format they want first.
+    fn from_accept(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(Self::from_media_type)
+            .unwrap_or(Self::Json)
+    }
+}
+
+/// Response format requested through the `Accept` header.
+#[derive(Debug, Clone, Copy)]
+pub struct Accept(pub PayloadFormat);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Accept {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(PayloadFormat::from_accept(&parts.headers)))
+    }
+}
+
+impl OperationInput for Accept {}
+
+/// Build a body from an `application/octet-stream` request. The headers are
+/// parsed before the payload is read, so a malformed request is rejected
+/// without spooling anything.
+pub trait FromOctetStream: Sized {
+    /// Whatever the body takes from the headers besides the IV.
+    type Headers: Send;
+
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::BAD_REQUEST`] if a required header is missing or
+    /// malformed.
+    fn octet_stream_headers(headers: &HeaderMap) -> Result<Self::Headers, StatusCode>;
+
+    /// Combine the parsed headers with the payload, once it has been read
+    /// (and, if large, spooled into chunks).
+    fn from_octet_stream(headers: Self::Headers, payload: StoredPayload) -> Self;
+}
+
+/// Render a body as an `application/octet-stream` response.
+pub trait IntoOctetStream {
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::NOT_ACCEPTABLE`] if the payload was stored as a
+    /// non-base64 string and has no raw byte form.
+    fn into_octet_stream(self) -> Result<(HeaderMap, Vec<u8>), StatusCode>;
+}
+
+/// Read the base64 IV from [`IV_HEADER`].
+///
+/// # Errors
+///
+/// Returns [`StatusCode::BAD_REQUEST`] if the header is missing or not base64.
+pub fn iv_from_headers(headers: &HeaderMap) -> Result<PayloadField, StatusCode> {
+    let iv = headers
+        .get(IV_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    STANDARD
+        .decode(iv)
+        .map(PayloadField::Bytes)
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+/// Read the optional `request_id` from [`REQUEST_ID_HEADER`], lowercased.
+///
+/// # Errors
+///
+/// Returns [`StatusCode::BAD_REQUEST`] if it isn't a valid `request_id`.
+pub fn request_id_from_headers(headers: &HeaderMap) -> Result<Option<String>, StatusCode> {
+    let Some(value) = headers.get(REQUEST_ID_HEADER) else {
+        return Ok(None);
+    };
+
+    let request_id = value
+        .to_str()
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .to_lowercase();
+    validate_request_id(&request_id)?;
+    Ok(Some(request_id))
+}
+
+/// Turn away an `application/octet-stream` read of the payload stored under
+/// `key` if it has no raw byte form. Single-use payloads are consumed as they
+/// are read, so this peeks before anything is deleted.
+///
+/// # Errors
+///
+/// Returns [`StatusCode::NOT_ACCEPTABLE`] if the stored payload can't be sent
+/// as octet-stream, or the error of reading it.
+pub async fn check_octet_stream_read(
+    Accept(format): Accept,
+    redis: &mut ConnectionManager,
+    encryption: &StorageEncryption,
+    key: &str,
+) -> Result<(), StatusCode> {
+    if format != PayloadFormat::OctetStream {
+        return Ok(());
+    }
+
+    let stored: Option<Vec<u8>> = redis.get(key).await.map_err(handle_redis_error)?;
+    match stored {
+        Some(stored)
+            if !StoredPayload::from_stored(&encryption.open(key, &stored)?)?
+                .has_octet_stream_form() =>
+        {
+            Err(StatusCode::NOT_ACCEPTABLE)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Split a payload into its octet-stream headers and body.
+///
+/// # Errors
+///
+/// Returns [`StatusCode::NOT_ACCEPTABLE`] if either field is a non-base64
+/// string.
+pub fn payload_into_octet_stream(
+    payload: RequestPayload,
+) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+    let (iv, payload) = payload.into_parts();
+    let iv = iv.into_bytes().ok_or(StatusCode::NOT_ACCEPTABLE)?;
+    let body = payload.into_bytes().ok_or(StatusCode::NOT_ACCEPTABLE)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        IV_HEADER,
+        HeaderValue::from_str(&STANDARD.encode(iv)).map_err(|_| StatusCode::NOT_ACCEPTABLE)?,
+    );
+    Ok((headers, body))
+}
+
+impl FromOctetStream for StoredPayload {
+    type Headers = ();
+
+    fn octet_stream_headers(_headers: &HeaderMap) -> Result<Self::Headers, StatusCode> {
+        Ok(())
+    }
+
+    fn from_octet_stream((): Self::Headers, payload: StoredPayload) -> Self {
+        payload
+    }
+}
+
+impl IntoOctetStream for RequestPayload {
+    fn into_octet_stream(self) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+        payload_into_octet_stream(self)
+    }
+}
+
+/// A request body in whichever format its `Content-Type` names.
+///
+/// Anything that isn't CBOR or octet-stream goes through the schema-validating
+/// JSON extractor, so JSON clients see exactly the same rejections as before.
+pub struct Negotiated<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for Negotiated<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned + JsonSchema + FromOctetStream + 'static,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(PayloadFormat::from_media_type);
+
+        match format {
+            Some(PayloadFormat::Cbor) => {
+                let body = Bytes::from_request(req, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                ciborium::from_reader(body.as_ref())
+                    .map(Self)
+                    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into_response())
+            }
+            Some(PayloadFormat::OctetStream) => {
+                let (mut parts, body) = req.into_parts();
+                let headers = check_octet_stream::<S, T>(&mut parts, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                spool_octet_stream(&parts, body)
+                    .await
+                    .map(|payload| Self(T::from_octet_stream(headers, payload)))
+                    .map_err(IntoResponse::into_response)
+            }
+            _ => Json::<T>::from_request(req, state)
+                .await
+                .map(|Json(value)| Self(value))
+                .map_err(IntoResponse::into_response),
+        }
+    }
+}
+
+/// Everything about an octet-stream request that can be checked without its
+/// body: the headers, and the `request_id` in the path, if there is one.
+async fn check_octet_stream<S, T>(parts: &mut Parts, state: &S) -> Result<T::Headers, StatusCode>
+where
+    S: Send + Sync,
+    T: FromOctetStream,
+{
+    let params = RawPathParams::from_request_parts(parts, state)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Some((_, request_id)) = params.iter().find(|(name, _)| *name == "request_id") {
+        validate_request_id(&request_id.to_lowercase())?;
+    }
+
+    T::octet_stream_headers(&parts.headers)
+}
+
+/// Read a raw body without buffering it whole; see [`chunked::spool`]. The
+/// route group sets which size limit applies through a [`PayloadKind`]
+/// extension.
+async fn spool_octet_stream(parts: &Parts, body: Body) -> Result<StoredPayload, ApiError> {
+    let iv = iv_from_headers(&parts.headers)?;
+    let kind = parts
+        .extensions
+        .get::<PayloadKind>()
+        .copied()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let validation = parts
+        .extensions
+        .get::<Arc<PayloadValidation>>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut redis = parts
+        .extensions
+        .get::<ConnectionManager>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let encryption = parts
+        .extensions
+        .get::<Arc<StorageEncryption>>()
+        .cloned()
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    chunked::spool(body, iv, (&validation, kind), &mut redis, &encryption).await
+}
+
+impl<T: JsonSchema> OperationInput for Negotiated<T> {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        Json::<T>::operation_input(ctx, operation);
+        if let Some(ReferenceOr::Item(body)) = &mut operation.request_body {
+            add_binary_media_types(&mut body.content);
+        }
+    }
+}
+
+/// A response body rendered in the format the client asked for.
+pub enum Encoded<T> {
+    /// A value serialized in full.
+    Value { format: PayloadFormat, value: T },
+    /// A body already rendered as a stream in the requested format (see
+    /// [`super::chunked`]). Documented as `T`, which it's shaped like.
+    Stream(Response),
+}
+
+impl<T> Encoded<T> {
+    pub const fn new(Accept(format): Accept, value: T) -> Self {
+        Self::Value { format, value }
+    }
+}
+
+impl<T: Serialize + IntoOctetStream> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let (format, value) = match self {
+            Self::Value { format, value } => (format, value),
+            Self::Stream(response) => return response,
+        };
+
+        match format {
+            PayloadFormat::Json => Json(value).into_response(),
+            PayloadFormat::Cbor => {
+                let mut body = Vec::new();
+                match ciborium::into_writer(&value, &mut body) {
+                    Ok(()) => ([(CONTENT_TYPE, CBOR)], body).into_response(),
+                    Err(e) => {
+                        tracing::error!("Failed to encode CBOR response: {e}");
+                        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                    }
+                }
+            }
+            PayloadFormat::OctetStream => match value.into_octet_stream() {
+                Ok((headers, body)) => {
+                    (headers, [(CONTENT_TYPE, OCTET_STREAM)], body).into_response()
+                }
+                Err(status) => status.into_response(),
+            },
+        }
+    }
+}
+
+impl<T: JsonSchema> OperationOutput for Encoded<T> {
+    type Inner = T;
+
+    fn operation_response(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Option<OpenApiResponse> {
+        let mut response = Json::<T>::operation_response(ctx, operation)?;
+        add_binary_media_types(&mut response.content);
+        Some(response)
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, OpenApiResponse)> {
+        Self::operation_response(ctx, operation)
+            .map(|res| vec![(Some(200), res)])
+            .unwrap_or_default()
+    }
+}
+
+/// Document CBOR (same shape as the JSON body) and octet-stream alternatives
+/// next to an existing `application/json` entry.
+fn add_binary_media_types(content: &mut IndexMap<String, MediaType>) {
+    if let Some(json) = content.get("application/json").cloned() {
+        content.insert(CBOR.to_string(), json);
+    }
+
+    let binary = JsonSchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        format: Some("binary".to_string()),
+        ..Default::default()
+    };
+    content.insert(
+        OCTET_STREAM.to_string(),
+        MediaType {
+            schema: Some(SchemaObject {
+                json_schema: Schema::Object(binary),
+                external_docs: None,
+                example: None,
+            }),
+            ..Default::default()
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_picks_first_supported_format() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(PayloadFormat::from_accept(&headers), PayloadFormat::Json);
+
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("text/html, application/cbor;q=0.9, */*"),
+        );
+        assert_eq!(PayloadFormat::from_accept(&headers), PayloadFormat::Cbor);
+
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        assert_eq!(PayloadFormat::from_accept(&headers), PayloadFormat::Json);
+    }
+}
@@ -4,35 +4,57 @@ use aide::axum::{
 };
 use axum::{
     extract::Path,
-    http::{HeaderMap, Method, StatusCode},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::Response,
     Extension,
 };
 use axum_jsonschema::Json;
-use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, Script, SetExpiry, SetOptions};
 use schemars::JsonSchema;
-use std::env;
 use std::str::FromStr;
-use std::sync::Arc;
-use tower_http::cors::{AllowHeaders, Any, CorsLayer};
+use std::sync::{Arc, LazyLock};
+use tower_http::cors::CorsLayer;
 use uuid::Uuid;
 
+use super::capabilities::{Capabilities, Capability, WithCapabilities, CAPABILITIES_HEADER};
+use super::chunked::{self, StoredPayload};
+use super::idempotency::{Claim, Fingerprint, IdempotencyKey};
+use super::lifecycle::Lifecycle;
+use super::negotiate::{
+    check_octet_stream_read, payload_into_octet_stream, request_id_from_headers, Accept, Encoded,
+    FromOctetStream, IntoOctetStream, Negotiated, IV_HEADER,
+};
+use crate::compression::RouteGroup;
+use crate::cors::CorsPolicy;
+use crate::envelope::StorageEncryption;
+use crate::environment::Feature;
+use crate::metadata::RequestMetadata;
+use crate::redact;
 use crate::utils::{
-    handle_redis_error, validate_request_id, AppOverrides, RequestPayload, RequestStatus,
-    EXPIRE_AFTER_SECONDS, REQ_STATUS_PREFIX,
+    handle_redis_error, validate_request_id, ApiError, AppOverrides, RequestPayload, RequestStatus,
+    EXPIRE_AFTER_SECONDS, LEASE_SECONDS, MAX_READS_PER_REQUEST, MAX_RESPONSES_PER_REQUEST,
+    REQ_CALLBACK_PREFIX, REQ_LEASE_PREFIX, REQ_METADATA_PREFIX, REQ_PREFIX, REQ_READS_PREFIX,
+    REQ_READ_QUOTA_PREFIX, REQ_STATUS_PREFIX, RES_COUNT_PREFIX, RES_QUOTA_PREFIX,
 };
+use crate::validation::{PayloadKind, PayloadValidation};
+use crate::webhook::{Callback, Webhooks};
+use crate::Config;
 
-const REQ_PREFIX: &str = "req:";
 /// If this header is present and to `true`, the GET /request will include an `idkit_flow_id` for telemetry correlation
 /// We're adding this header to avoid breaking existing client that don't expect this field in the response
+/// Superseded by the `idkit-flow-id` capability, but still honored for shipped clients
 const ACCEPT_IDKIT_FLOW_ID_HEADER: &str = "accept-idkit-flow-id";
 const IDKIT_FLOW_ID_PREFIX: &str = "idkitflow_";
+/// Carries the `idkit_flow_id` on `application/octet-stream` responses.
+pub(super) const IDKIT_FLOW_ID_HEADER: &str = "idkit-flow-id";
+/// Carries the `lease_token` on `application/octet-stream` responses.
+pub(super) const LEASE_TOKEN_HEADER: &str = "bridge-lease-token";
 
 #[derive(Debug, serde::Deserialize, JsonSchema)]
 struct CreateRequestBody {
-    /// The initialization vector for the encrypted payload (opaque to the bridge).
-    iv: String,
-    /// The encrypted payload (opaque to the bridge).
-    payload: String,
+    /// The encrypted `iv` and `payload` (opaque to the bridge).
+    #[serde(flatten)]
+    payload: StoredPayload,
     /// Optional client-supplied `request_id`. When present, the bridge stores
     /// the request under this key with NX semantics (409 on collision); when
     /// absent, the bridge generates a UUID v4. Lets the RP address requests by
@@ -60,11 +82,62 @@ struct CreateRequestBody {
     /// an explicit flag instead: clients that predate the feature omit it and
     /// get the lean `{request_id}` response, so a server-side override rollout
     /// can never break them. Updated SDKs send `true` to receive overrides.
+    ///
+    /// Superseded by the `app-overrides` capability in `bridge-capabilities`,
+    /// which also works for octet-stream uploads.
     #[serde(default)]
     supports_app_overrides: bool,
+    /// Opt in to fan-in: accept up to this many responses (at most 32) instead
+    /// of one. Each `PUT /response/:request_id` is appended, every
+    /// `GET /response/:request_id` drains what has arrived, and the request
+    /// completes once the quota is met or it expires.
+    #[serde(default)]
+    max_responses: Option<u32>,
+    /// Opt in to fan-out: let the request be read up to this many times (at
+    /// most 16) instead of once, e.g. when the QR code is scanned on two
+    /// devices or a first fetch is lost on the network. Reads past the limit
+    /// get `410 Gone`, and `GET /response/:request_id` reports the read count.
+    #[serde(default)]
+    max_reads: Option<u32>,
+    /// Deliver the response to this URL instead of waiting for a poll. It must
+    /// be on the allowlist configured for `app_id`.
+    #[serde(default)]
+    callback_url: Option<String>,
+    /// The app `callback_url` is registered under. Required with it.
+    #[serde(default)]
+    app_id: Option<String>,
+    /// What the request is for, in plaintext, so the bridge can report and
+    /// apply policies per app. Stored alongside the status; never put anything
+    /// here the bridge shouldn't see.
+    #[serde(default)]
+    metadata: Option<RequestMetadata>,
 }
 
-#[derive(Debug, serde::Serialize, JsonSchema)]
+/// Octet-stream uploads carry the IV in `bridge-iv` and an optional
+/// `request_id` in `bridge-request-id`. They opt in to app overrides through
+/// `bridge-capabilities` only.
+impl FromOctetStream for CreateRequestBody {
+    type Headers = Option<String>;
+
+    fn octet_stream_headers(headers: &HeaderMap) -> Result<Self::Headers, StatusCode> {
+        request_id_from_headers(headers)
+    }
+
+    fn from_octet_stream(request_id: Self::Headers, payload: StoredPayload) -> Self {
+        Self {
+            payload,
+            request_id,
+            supports_app_overrides: false,
+            max_responses: None,
+            max_reads: None,
+            callback_url: None,
+            app_id: None,
+            metadata: None,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, JsonSchema)]
 struct RequestCreatedPayload {
     /// The unique identifier for the request — the client-supplied value if
     /// one was provided, otherwise a server-generated UUID v4.
@@ -74,7 +147,7 @@ struct RequestCreatedPayload {
     /// clients that opt in via `supports_app_overrides`; omitted entirely
     /// otherwise (empty map) so unaware clients see the legacy `{request_id}`
     /// response shape.
-    #[serde(skip_serializing_if = "AppOverrides::is_empty")]
+    #[serde(default, skip_serializing_if = "AppOverrides::is_empty")]
     app_overrides: AppOverrides,
 }
 
@@ -86,34 +159,60 @@ struct RequestResponse {
     /// Only present when the client explicitly opts in via the `accept-idkit-flow-id` header.
     #[serde(skip_serializing_if = "Option::is_none")]
     idkit_flow_id: Option<String>,
+    /// Only present when the request was leased (the `lease` capability).
+    /// Send it to `POST /request/:request_id/ack` to delete the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lease_token: Option<String>,
 }
 
-pub fn handler() -> ApiRouter {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_headers(AllowHeaders::any())
-        .allow_methods([Method::GET, Method::POST, Method::HEAD, Method::PUT]);
-
-    let environment = env::var("ENVIRONMENT")
-        .unwrap_or_else(|_| "unknown".to_string())
-        .trim()
-        .to_lowercase();
+impl IntoOctetStream for RequestResponse {
+    fn into_octet_stream(self) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+        let (mut headers, body) = payload_into_octet_stream(self.payload)?;
+        if let Some(flow_id) = self.idkit_flow_id {
+            headers.insert(
+                IDKIT_FLOW_ID_HEADER,
+                HeaderValue::from_str(&flow_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        if let Some(token) = self.lease_token {
+            headers.insert(
+                LEASE_TOKEN_HEADER,
+                HeaderValue::from_str(&token).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        Ok((headers, body))
+    }
+}
 
+pub fn handler(config: &Config) -> ApiRouter {
     // Base routes
     let mut router = ApiRouter::new()
         .api_route("/request", post(insert_request))
         .api_route("/request/:request_id", head(has_request).get(get_request))
-        .layer(cors);
+        .api_route("/request/:request_id/ack", post(ack_request))
+        .layer(cors(&config.cors));
 
-    // Only enable PUT in staging
-    if environment == "staging" {
+    if config.enables(Feature::StagingUpsert) {
         router = router.api_route("/request/:request_id", put(put_request));
     }
+    let router = router.layer(Extension(PayloadKind::Request));
+
+    config.compression.apply(RouteGroup::Request, router)
+}
 
-    router
+pub(super) fn cors(policy: &CorsPolicy) -> CorsLayer {
+    policy.layer(
+        RouteGroup::Request,
+        &[
+            HeaderName::from_static(CAPABILITIES_HEADER),
+            HeaderName::from_static(LEASE_TOKEN_HEADER),
+            HeaderName::from_static(IV_HEADER),
+            HeaderName::from_static(IDKIT_FLOW_ID_HEADER),
+        ],
+    )
 }
 
-async fn has_request(
+pub(super) async fn has_request(
     Path(request_id): Path<String>,
     Extension(mut redis): Extension<ConnectionManager>,
 ) -> StatusCode {
@@ -138,57 +237,296 @@ async fn has_request(
 
 async fn get_request(
     Path(request_id): Path<String>,
-    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    lifecycle: Lifecycle,
     headers: HeaderMap,
-) -> Result<Json<RequestResponse>, StatusCode> {
+    capabilities: Capabilities,
+    accept: Accept,
+) -> Result<WithCapabilities<Encoded<RequestResponse>>, StatusCode> {
+    let mut applied = Capabilities::default();
+    // TODO: use this for telemetry in the bridge PR #103
+    let idkit_flow_id = applied
+        .apply_if(
+            Capability::IdkitFlowId,
+            accepts_idkit_flow_id(&headers) || capabilities.contains(Capability::IdkitFlowId),
+        )
+        .then(new_idkit_flow_id);
+
+    let (payload, lease_token) = match take_request(
+        request_id,
+        redis,
+        encryption,
+        &lifecycle,
+        accept,
+        idkit_flow_id.as_deref(),
+        capabilities.contains(Capability::Lease),
+    )
+    .await?
+    {
+        Taken::Payload {
+            payload,
+            lease_token,
+        } => (payload, lease_token),
+        Taken::Stream(response) => {
+            return Ok(WithCapabilities {
+                applied,
+                inner: Encoded::Stream(response),
+            })
+        }
+    };
+    applied.apply_if(Capability::Lease, lease_token.is_some());
+
+    Ok(WithCapabilities {
+        applied,
+        inner: Encoded::new(
+            accept,
+            RequestResponse {
+                payload,
+                idkit_flow_id,
+                lease_token,
+            },
+        ),
+    })
+}
+
+/// A request read (and consumed) by [`take_request`].
+pub(super) enum Taken {
+    Payload {
+        payload: RequestPayload,
+        /// Set if the payload was leased rather than consumed; it's only
+        /// deleted once this token is acknowledged.
+        lease_token: Option<String>,
+    },
+    /// A chunked payload, already framed for the negotiated format.
+    Stream(Response),
+}
+
+/// `GETDEL` a request (or count a read of a fan-out one, deleting it on the
+/// last) and mark it retrieved. Shared by every version of
+/// `GET /request/:request_id`; `idkit_flow_id` only ends up in the framing of
+/// streamed payloads; inline ones are wrapped by the caller.
+///
+/// With `lease`, a regular inline request is leased for [`LEASE_SECONDS`]
+/// instead, and only deleted by `POST /request/:request_id/ack`. Fan-out and
+/// chunked requests aren't leased, which the caller sees as a missing token.
+/// Only other leased reads are turned away while a lease is held; a plain read
+/// consumes the request as it always has.
+pub(super) async fn take_request(
+    request_id: String,
+    mut redis: ConnectionManager,
+    encryption: Arc<StorageEncryption>,
+    lifecycle: &Lifecycle,
+    accept: Accept,
+    idkit_flow_id: Option<&str>,
+    lease: bool,
+) -> Result<Taken, StatusCode> {
     let request_id = request_id.to_lowercase();
     if validate_request_id(&request_id).is_err() {
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    // Use a transaction to get both status and request data atomically
-    let mut pipe = redis::pipe();
-    pipe.get(format!("{REQ_STATUS_PREFIX}{request_id}"))
-        .get_del(format!("{REQ_PREFIX}{request_id}"));
-
-    let (status, value): (Option<String>, Option<Vec<u8>>) = pipe
-        .query_async(&mut redis)
+    let key = format!("{REQ_PREFIX}{request_id}");
+    check_octet_stream_read(accept, &mut redis, &encryption, &key).await?;
+
+    let token = lease.then(|| Uuid::new_v4().to_string());
+    let (outcome, status, value): (String, String, Vec<u8>) = TAKE_REQUEST
+        .key(&key)
+        .key(format!("{REQ_STATUS_PREFIX}{request_id}"))
+        .key(format!("{REQ_READ_QUOTA_PREFIX}{request_id}"))
+        .key(format!("{REQ_READS_PREFIX}{request_id}"))
+        .key(format!("{REQ_LEASE_PREFIX}{request_id}"))
+        .arg(token.as_deref().unwrap_or_default())
+        .arg(LEASE_SECONDS)
+        .invoke_async(&mut redis)
         .await
         .map_err(handle_redis_error)?;
 
-    let current_status = status
-        .and_then(|s| RequestStatus::from_str(&s).ok())
-        .unwrap_or(RequestStatus::Initialized);
+    let lease_token = match outcome.as_str() {
+        "missing" => return Err(StatusCode::NOT_FOUND),
+        "gone" => {
+            tracing::info!("Request {} is past its reads", redact::id(&request_id));
+            return Err(StatusCode::GONE);
+        }
+        // Nobody else gets a leased payload until the lease is acknowledged
+        // or expires.
+        "held" => return Err(StatusCode::CONFLICT),
+        "leased" => {
+            tracing::info!(
+                "Request {} leased for {LEASE_SECONDS}s",
+                redact::id(&request_id)
+            );
+            token
+        }
+        _ => None,
+    };
+
+    let current_status = RequestStatus::from_str(&status).unwrap_or(RequestStatus::Initialized);
+    let payload = StoredPayload::from_stored(&encryption.open(&key, &value)?)?;
+
+    // A request leased again after its lease expired was already retrieved.
+    // Fan-out reads refresh the status, which also keeps the read count
+    // alive.
+    if outcome == "read" || current_status != RequestStatus::Retrieved {
+        mark_retrieved(&mut redis, &request_id).await?;
+        lifecycle
+            .record(
+                &mut redis,
+                &request_id,
+                Some(current_status),
+                RequestStatus::Retrieved,
+                Some(payload.size()),
+            )
+            .await;
+    }
+
+    match payload {
+        StoredPayload::Inline(payload) => Ok(Taken::Payload {
+            payload,
+            lease_token,
+        }),
+        StoredPayload::Chunked(manifest) => {
+            // Chunks are deleted as they stream, so there's nothing to lease.
+            if lease_token.is_some() {
+                release_lease(&mut redis, &request_id, true).await?;
+            }
+            chunked::stream_request(accept.0, redis, encryption, manifest, idkit_flow_id)
+                .map(Taken::Stream)
+        }
+    }
+}
 
-    let value = value.ok_or(StatusCode::NOT_FOUND)?;
+/// Checks a request can be read and claims the read in one step, so
+/// concurrent readers can't both get a single-use payload or a lease.
+///
+/// Returns `[outcome, status, payload]`: `missing`, `gone` past a fan-out
+/// request's reads, `held` while another lease is live, or `read` (fan-out),
+/// `leased` and `taken` with the payload. A fan-out read is only counted when
+/// there is a payload to read.
+static TAKE_REQUEST: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"-- bridge:take_request
+-- KEYS: payload, status, read quota, read count, lease
+-- ARGV: lease token (empty for a plain read), lease seconds
+local quota = tonumber(redis.call('GET', KEYS[3]) or '')
+if quota and tonumber(redis.call('GET', KEYS[4]) or '0') >= quota then
+  return {'gone', '', ''}
+end
+local value = redis.call('GET', KEYS[1])
+if not value then
+  return {'missing', '', ''}
+end
+local status = redis.call('GET', KEYS[2]) or ''
+if quota then
+  if redis.call('INCR', KEYS[4]) == quota then
+    redis.call('DEL', KEYS[1])
+  end
+  return {'read', status, value}
+end
+if ARGV[1] ~= '' then
+  if not redis.call('SET', KEYS[5], ARGV[1], 'NX', 'EX', ARGV[2]) then
+    return {'held', '', ''}
+  end
+  return {'leased', status, value}
+end
+redis.call('DEL', KEYS[1])
+return {'taken', status, value}
+",
+    )
+});
+
+/// Drop a lease, and with `consume` the request it covers.
+async fn release_lease(
+    redis: &mut ConnectionManager,
+    request_id: &str,
+    consume: bool,
+) -> Result<(), StatusCode> {
+    let mut keys = vec![format!("{REQ_LEASE_PREFIX}{request_id}")];
+    if consume {
+        keys.push(format!("{REQ_PREFIX}{request_id}"));
+    }
+    redis.del(keys).await.map_err(handle_redis_error)
+}
 
+/// Move a request to `retrieved`.
+async fn mark_retrieved(redis: &mut ConnectionManager, request_id: &str) -> Result<(), StatusCode> {
     //ANCHOR - Update the status of the request
-    redis
-        .set_ex::<_, _, ()>(
+    // Fan-in and fan-out bookkeeping (if any) share the status's refreshed TTL.
+    redis::pipe()
+        .set_ex(
             format!("{REQ_STATUS_PREFIX}{request_id}"),
             RequestStatus::Retrieved.to_string(),
             EXPIRE_AFTER_SECONDS,
         )
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(format!("{RES_QUOTA_PREFIX}{request_id}"))
+        .arg(EXPIRE_AFTER_SECONDS)
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(format!("{RES_COUNT_PREFIX}{request_id}"))
+        .arg(EXPIRE_AFTER_SECONDS)
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(format!("{REQ_READ_QUOTA_PREFIX}{request_id}"))
+        .arg(EXPIRE_AFTER_SECONDS)
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(format!("{REQ_READS_PREFIX}{request_id}"))
+        .arg(EXPIRE_AFTER_SECONDS)
+        .ignore()
+        .query_async::<()>(redis)
         .await
-        .map_err(handle_redis_error)?;
+        .map_err(handle_redis_error)
+}
 
-    tracing::info!(
-        "Request {request_id} state transition: {} -> {}",
-        current_status,
-        RequestStatus::Retrieved
-    );
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub(super) struct AckBody {
+    /// The `lease_token` returned by the leased `GET /request/:request_id`.
+    lease_token: String,
+}
 
-    let payload: RequestPayload =
-        serde_json::from_slice(&value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+/// Acknowledge a leased request, deleting it for good. `409` if the lease
+/// expired (the request may have been leased again since) or the token is
+/// wrong, `404` if the request is gone.
+pub(super) async fn ack_request(
+    Path(request_id): Path<String>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    Json(body): Json<AckBody>,
+) -> Result<StatusCode, StatusCode> {
+    let request_id = request_id.to_lowercase();
+    if validate_request_id(&request_id).is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
-    // TODO: use this for telemetry in the bridge PR #103
-    let idkit_flow_id = accepts_idkit_flow_id(&headers)
-        .then(|| format!("{IDKIT_FLOW_ID_PREFIX}{}", Uuid::new_v4()));
+    let lease: Option<String> = redis
+        .get(format!("{REQ_LEASE_PREFIX}{request_id}"))
+        .await
+        .map_err(handle_redis_error)?;
+    if lease.as_deref() != Some(body.lease_token.as_str()) {
+        return Err(StatusCode::CONFLICT);
+    }
 
-    Ok(Json(RequestResponse {
-        payload,
-        idkit_flow_id,
-    }))
+    // Not atomic with the check: if the lease expires in between and someone
+    // else leases the request, they already have the payload, and their ack
+    // gets a 404.
+    let deleted: u32 = redis
+        .del(&[
+            format!("{REQ_PREFIX}{request_id}"),
+            format!("{REQ_LEASE_PREFIX}{request_id}"),
+        ])
+        .await
+        .map_err(handle_redis_error)?;
+    if deleted < 2 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    tracing::info!("Request {} acknowledged", redact::id(&request_id));
+    Ok(StatusCode::OK)
+}
+
+pub(super) fn new_idkit_flow_id() -> String {
+    format!("{IDKIT_FLOW_ID_PREFIX}{}", Uuid::new_v4())
 }
 
 /// Treat the opt-in header as enabled only for the explicit value `true`.
@@ -204,25 +542,150 @@ fn accepts_idkit_flow_id(headers: &HeaderMap) -> bool {
 
 /// Create a new request. Optionally accepts a client-supplied `request_id`
 /// with NX semantics; otherwise generates a UUID v4.
+#[allow(clippy::too_many_arguments)]
 async fn insert_request(
     Extension(mut redis): Extension<ConnectionManager>,
     Extension(app_overrides): Extension<Arc<AppOverrides>>,
-    Json(body): Json<CreateRequestBody>,
-) -> Result<Json<RequestCreatedPayload>, StatusCode> {
-    let request_id = match body.request_id {
-        Some(id) => {
-            let id = id.to_lowercase();
-            validate_request_id(&id)?;
-            id
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(validation): Extension<Arc<PayloadValidation>>,
+    Extension(webhooks): Extension<Arc<Webhooks>>,
+    lifecycle: Lifecycle,
+    capabilities: Capabilities,
+    idempotency: IdempotencyKey,
+    Negotiated(body): Negotiated<CreateRequestBody>,
+) -> Result<WithCapabilities<Json<RequestCreatedPayload>>, ApiError> {
+    let mut applied = Capabilities::default();
+    let opted_in = applied.apply_if(
+        Capability::AppOverrides,
+        body.supports_app_overrides || capabilities.contains(Capability::AppOverrides),
+    );
+    let options = RequestOptions {
+        request_id: body.request_id,
+        max_responses: body.max_responses,
+        max_reads: body.max_reads,
+        callback: Callback::from_parts(body.app_id, body.callback_url)?,
+        metadata: body.metadata,
+    };
+
+    let fingerprint =
+        fingerprint_request("v1:request", &body.payload, &options)?.with([u8::from(opted_in)]);
+    let created = match idempotency
+        .claim(&mut redis, &encryption, fingerprint)
+        .await?
+    {
+        Claim::Replay(created) => created,
+        Claim::Fresh(reservation) => {
+            let result = store_request(
+                redis.clone(),
+                &encryption,
+                &validation,
+                &webhooks,
+                &lifecycle,
+                &body.payload,
+                options,
+            )
+            .await
+            .map(|request_id| RequestCreatedPayload {
+                request_id,
+                app_overrides: select_response_overrides(opted_in, &app_overrides),
+            });
+            reservation.settle(&mut redis, &encryption, result).await?
         }
-        None => Uuid::new_v4().to_string(),
     };
 
-    tracing::info!("Processing /request: {request_id}");
+    Ok(WithCapabilities {
+        applied,
+        inner: Json(created),
+    })
+}
+
+/// Fingerprint a `POST /request` body for [`IdempotencyKey`]: the payload and
+/// every option.
+pub(super) fn fingerprint_request(
+    route: &'static str,
+    payload: &StoredPayload,
+    options: &RequestOptions,
+) -> Result<Fingerprint, StatusCode> {
+    let options = serde_json::to_vec(&(
+        &options.request_id,
+        options.max_responses,
+        options.max_reads,
+        &options.callback,
+        &options.metadata,
+    ))
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Fingerprint::new(route)
+        .with(payload.fingerprint()?)
+        .with(options))
+}
+
+/// Everything `POST /request` accepts besides the payload, in every version.
+#[derive(Debug, Default)]
+pub(super) struct RequestOptions {
+    pub request_id: Option<String>,
+    pub max_responses: Option<u32>,
+    pub max_reads: Option<u32>,
+    pub callback: Option<Callback>,
+    pub metadata: Option<RequestMetadata>,
+}
+
+impl RequestOptions {
+    fn validate(&self, payload: &StoredPayload) -> Result<(), StatusCode> {
+        let out_of_range = |value: Option<u32>, max| value.is_some_and(|n| n == 0 || n > max);
+        if out_of_range(self.max_responses, MAX_RESPONSES_PER_REQUEST)
+            || out_of_range(self.max_reads, MAX_READS_PER_REQUEST)
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        // Chunks are deleted as they stream, so only inline payloads can be
+        // read more than once.
+        if self.max_reads.is_some() && matches!(payload, StoredPayload::Chunked(_)) {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        // Webhooks deliver a single response.
+        if self.callback.is_some() && self.max_responses.is_some() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        self.metadata
+            .as_ref()
+            .map_or(Ok(()), RequestMetadata::validate)
+    }
+}
+
+/// Validate and store a new request under `request_id` (or a fresh UUID v4)
+/// with NX semantics, returning the ID. Shared by every version of
+/// `POST /request`.
+pub(super) async fn store_request(
+    mut redis: ConnectionManager,
+    encryption: &StorageEncryption,
+    validation: &PayloadValidation,
+    webhooks: &Webhooks,
+    lifecycle: &Lifecycle,
+    payload: &StoredPayload,
+    options: RequestOptions,
+) -> Result<String, ApiError> {
+    options.validate(payload)?;
+    let RequestOptions {
+        request_id,
+        max_responses,
+        max_reads,
+        callback,
+        metadata,
+    } = options;
+    if let Some(callback) = &callback {
+        webhooks.check(callback)?;
+    }
+
+    let request_id = resolve_request_id(request_id)?;
 
-    let payload = RequestPayload::new(body.iv, body.payload);
-    let payload_bytes =
-        serde_json::to_vec(&payload).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::info!("Processing /request: {}", redact::id(&request_id));
+
+    let key = format!("{REQ_PREFIX}{request_id}");
+    payload.validate(validation, PayloadKind::Request)?;
+
+    let payload_bytes = encryption.seal(&key, &payload.to_stored()?)?;
 
     // SET NX on the payload — collisions return 409 in a single round trip.
     let options = SetOptions::default()
@@ -230,34 +693,122 @@ async fn insert_request(
         .with_expiration(SetExpiry::EX(EXPIRE_AFTER_SECONDS));
 
     let set_ok: Option<String> = redis
-        .set_options(format!("{REQ_PREFIX}{request_id}"), payload_bytes, options)
+        .set_options(key, payload_bytes, options)
         .await
         .map_err(handle_redis_error)?;
 
     if set_ok.is_none() {
-        return Err(StatusCode::CONFLICT);
+        return Err(StatusCode::CONFLICT.into());
     }
-
-    redis
-        .set_ex::<_, _, ()>(
-            format!("{REQ_STATUS_PREFIX}{request_id}"),
-            RequestStatus::Initialized.to_string(),
-            EXPIRE_AFTER_SECONDS,
+    payload.keep();
+
+    initialize_status(
+        &mut redis,
+        &request_id,
+        max_responses,
+        max_reads,
+        callback.as_ref(),
+        metadata.as_ref(),
+    )
+    .await?;
+    lifecycle
+        .record(
+            &mut redis,
+            &request_id,
+            None,
+            RequestStatus::Initialized,
+            Some(payload.size()),
         )
-        .await
-        .map_err(handle_redis_error)?;
+        .await;
 
     tracing::info!(
-        "Request {request_id} state transition: new -> {}",
-        RequestStatus::Initialized
+        "Successfully processed /request: {}",
+        redact::id(&request_id)
     );
 
-    tracing::info!("Successfully processed /request: {request_id}");
+    Ok(request_id)
+}
 
-    Ok(Json(RequestCreatedPayload {
-        request_id,
-        app_overrides: select_response_overrides(body.supports_app_overrides, &app_overrides),
-    }))
+/// Normalize and validate a client-supplied `request_id`, or generate a UUID
+/// v4 if there isn't one.
+pub(super) fn resolve_request_id(request_id: Option<String>) -> Result<String, StatusCode> {
+    match request_id {
+        Some(id) => {
+            let id = id.to_lowercase();
+            validate_request_id(&id)?;
+            Ok(id)
+        }
+        None => Ok(Uuid::new_v4().to_string()),
+    }
+}
+
+/// Mark a freshly stored request `initialized`, along with the fan-in and
+/// fan-out bookkeeping if it accepts several responses or reads, and its
+/// webhook callback if it registered one.
+async fn initialize_status(
+    redis: &mut ConnectionManager,
+    request_id: &str,
+    max_responses: Option<u32>,
+    max_reads: Option<u32>,
+    callback: Option<&Callback>,
+    metadata: Option<&RequestMetadata>,
+) -> Result<(), StatusCode> {
+    let mut pipe = redis::pipe();
+    pipe.set_ex(
+        format!("{REQ_STATUS_PREFIX}{request_id}"),
+        RequestStatus::Initialized.to_string(),
+        EXPIRE_AFTER_SECONDS,
+    )
+    .ignore();
+    if let Some(max_responses) = max_responses {
+        pipe.set_ex(
+            format!("{RES_QUOTA_PREFIX}{request_id}"),
+            max_responses,
+            EXPIRE_AFTER_SECONDS,
+        )
+        .ignore()
+        .set_ex(
+            format!("{RES_COUNT_PREFIX}{request_id}"),
+            0,
+            EXPIRE_AFTER_SECONDS,
+        )
+        .ignore();
+    }
+    if let Some(max_reads) = max_reads {
+        pipe.set_ex(
+            format!("{REQ_READ_QUOTA_PREFIX}{request_id}"),
+            max_reads,
+            EXPIRE_AFTER_SECONDS,
+        )
+        .ignore()
+        .set_ex(
+            format!("{REQ_READS_PREFIX}{request_id}"),
+            0,
+            EXPIRE_AFTER_SECONDS,
+        )
+        .ignore();
+    }
+    if let Some(callback) = callback {
+        let callback =
+            serde_json::to_string(callback).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pipe.set_ex(
+            format!("{REQ_CALLBACK_PREFIX}{request_id}"),
+            callback,
+            EXPIRE_AFTER_SECONDS,
+        )
+        .ignore();
+    }
+    if let Some(metadata) = metadata {
+        let metadata =
+            serde_json::to_string(metadata).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        pipe.set_ex(
+            format!("{REQ_METADATA_PREFIX}{request_id}"),
+            metadata,
+            EXPIRE_AFTER_SECONDS,
+        )
+        .ignore();
+    }
+    pipe.query_async(redis).await.map_err(handle_redis_error)
 }
 
 /// Pick the overrides to echo back on `POST /request`: the configured map for
@@ -275,15 +826,19 @@ fn select_response_overrides(opted_in: bool, configured: &AppOverrides) -> AppOv
 
 /// Create a new request by ID idempotently — retries succeed, even if the request exists.
 /// Note: only enabled in staging.
-async fn put_request(
+pub(super) async fn put_request(
     Path(request_id): Path<String>,
     Extension(mut redis): Extension<ConnectionManager>,
-    Json(request): Json<RequestPayload>,
-) -> Result<StatusCode, StatusCode> {
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(validation): Extension<Arc<PayloadValidation>>,
+    lifecycle: Lifecycle,
+    Negotiated(request): Negotiated<StoredPayload>,
+) -> Result<StatusCode, ApiError> {
     let request_id = request_id.to_lowercase();
     validate_request_id(&request_id)?;
+    request.validate(&validation, PayloadKind::Request)?;
 
-    tracing::info!("Processing PUT /request: {request_id}");
+    tracing::info!("Processing PUT /request: {}", redact::id(&request_id));
 
     // Same logic as POST, but always overwrites the existing payload, sets status, and resets the TTL.
     redis
@@ -295,21 +850,26 @@ async fn put_request(
         .await
         .map_err(handle_redis_error)?;
 
-    tracing::info!(
-        "Request {request_id} state transition: new -> {}",
-        RequestStatus::Initialized
-    );
+    let key = format!("{REQ_PREFIX}{request_id}");
+    let payload_bytes = encryption.seal(&key, &request.to_stored()?)?;
 
     redis
-        .set_ex::<_, _, ()>(
-            format!("{REQ_PREFIX}{request_id}"),
-            serde_json::to_vec(&request).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-            EXPIRE_AFTER_SECONDS,
-        )
+        .set_ex::<_, _, ()>(key, payload_bytes, EXPIRE_AFTER_SECONDS)
         .await
         .map_err(handle_redis_error)?;
+    request.keep();
+
+    lifecycle
+        .record(
+            &mut redis,
+            &request_id,
+            None,
+            RequestStatus::Initialized,
+            Some(request.size()),
+        )
+        .await;
 
-    tracing::info!("Successfully PUT /request: {request_id}");
+    tracing::info!("Successfully PUT /request: {}", redact::id(&request_id));
 
     Ok(StatusCode::CREATED)
 }
@@ -1,47 +1,120 @@
 use std::str::FromStr;
-
-use aide::axum::{
-    routing::{get, post},
-    ApiRouter,
+use std::sync::{Arc, LazyLock};
+
+use aide::{
+    axum::{
+        routing::{get, post},
+        ApiRouter,
+    },
+    OperationInput,
 };
 use axum::{
-    extract::Path,
-    http::{Method, StatusCode},
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::{request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     Extension,
 };
 use axum_jsonschema::Json;
-use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, Script, SetExpiry, SetOptions};
 use schemars::JsonSchema;
 use std::str;
-use tower_http::cors::{AllowHeaders, Any, CorsLayer};
-use uuid::Uuid;
-
+use world_id_bridge_client::types::Response;
+
+use super::chunked::{self, StoredPayload};
+use super::idempotency::{Claim, Fingerprint, IdempotencyKey};
+use super::lifecycle::Lifecycle;
+use super::negotiate::{
+    check_octet_stream_read, payload_into_octet_stream, request_id_from_headers, Accept, Encoded,
+    FromOctetStream, IntoOctetStream, Negotiated, PayloadFormat, IV_HEADER, STATUS_HEADER,
+};
+use super::request;
+use crate::compression::RouteGroup;
+use crate::envelope::StorageEncryption;
+use crate::environment::Feature;
+use crate::redact;
 use crate::utils::{
-    handle_redis_error, validate_request_id, RequestPayload, RequestStatus, EXPIRE_AFTER_SECONDS,
-    REQ_STATUS_PREFIX,
+    handle_redis_error, validate_request_id, ApiError, RequestStatus, EXPIRE_AFTER_SECONDS,
+    REQ_CALLBACK_PREFIX, REQ_READS_PREFIX, REQ_READ_QUOTA_PREFIX, REQ_STATUS_PREFIX,
+    RES_COUNT_PREFIX, RES_LIST_PREFIX, RES_PREFIX, RES_QUOTA_PREFIX,
 };
+use crate::validation::{PayloadKind, PayloadValidation};
+use crate::webhook::Webhooks;
+use crate::Config;
+
+/// The status travels in `bridge-status`; the body is the raw response payload,
+/// or empty while there isn't one yet.
+/// Fan-in batches have no octet-stream form.
+impl IntoOctetStream for Response {
+    fn into_octet_stream(self) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+        if !self.responses.is_empty() {
+            return Err(StatusCode::NOT_ACCEPTABLE);
+        }
+        let (mut headers, body) = match self.response {
+            Some(payload) => payload_into_octet_stream(payload)?,
+            None => (HeaderMap::new(), Vec::new()),
+        };
+        headers.insert(
+            STATUS_HEADER,
+            HeaderValue::from_str(&self.status.to_string())
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+        Ok((headers, body))
+    }
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+struct CreateResponseBody {
+    /// The encrypted `iv` and `payload` (opaque to the bridge).
+    #[serde(flatten)]
+    payload: StoredPayload,
+    /// Optional client-supplied `request_id`, with the same rules as on
+    /// `POST /request`: it is lowercased and validated, and stored with NX
+    /// semantics against every request and response (409 on collision). A
+    /// UUID v4 is generated when absent. Entropy is the client's
+    /// responsibility.
+    #[serde(default)]
+    request_id: Option<String>,
+}
 
-const RES_PREFIX: &str = "res:";
+/// Octet-stream uploads carry the IV in `bridge-iv` and an optional
+/// `request_id` in `bridge-request-id`.
+impl FromOctetStream for CreateResponseBody {
+    type Headers = Option<String>;
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, JsonSchema)]
-struct Response {
-    status: RequestStatus,
-    response: Option<RequestPayload>,
+    fn octet_stream_headers(headers: &HeaderMap) -> Result<Self::Headers, StatusCode> {
+        request_id_from_headers(headers)
+    }
+
+    fn from_octet_stream(request_id: Self::Headers, payload: StoredPayload) -> Self {
+        Self {
+            payload,
+            request_id,
+        }
+    }
 }
 
-#[derive(Debug, serde::Serialize, JsonSchema)]
+#[derive(Debug, serde::Serialize, serde::Deserialize, JsonSchema)]
 struct ResponseCreatedPayload {
     /// The unique identifier for the response
     request_id: String,
 }
 
-pub fn handler() -> ApiRouter {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_headers(AllowHeaders::any())
-        .allow_methods([Method::GET, Method::PUT, Method::POST]); //TODO: PUT is required by the simulator but should not be included
+pub fn handler(config: &Config) -> ApiRouter {
+    // PUT is only needed by the simulator, so browsers only get it in the
+    // profiles that run one.
+    let mut policy = config.cors.clone();
+    if !config.enables(Feature::SimulatorPut) {
+        policy.disallow(RouteGroup::Response, &Method::PUT);
+    }
+    let cors = policy.layer(
+        RouteGroup::Response,
+        &[
+            HeaderName::from_static(IV_HEADER),
+            HeaderName::from_static(STATUS_HEADER),
+        ],
+    );
 
-    ApiRouter::new()
+    let router = ApiRouter::new()
         .api_route(
             "/response/:request_id",
             get(get_response)
@@ -50,34 +123,72 @@ pub fn handler() -> ApiRouter {
                 .layer(cors.clone()),
         )
         .api_route("/response", post(create_response).layer(cors))
+        .layer(Extension(PayloadKind::Response));
+
+    config.compression.apply(RouteGroup::Response, router)
 }
 
 async fn get_response(
     Path(request_id): Path<String>,
     Extension(mut redis): Extension<ConnectionManager>,
-) -> Result<Json<Response>, StatusCode> {
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    accept: Accept,
+) -> Result<Encoded<Response>, StatusCode> {
     let request_id = request_id.to_lowercase();
     if validate_request_id(&request_id).is_err() {
         return Err(StatusCode::BAD_REQUEST);
     }
 
+    check_octet_stream_read(
+        accept,
+        &mut redis,
+        &encryption,
+        &format!("{RES_PREFIX}{request_id}"),
+    )
+    .await?;
+
     // Use a transaction to get both status and response atomically
     let mut pipe = redis::pipe();
     pipe.get(format!("{REQ_STATUS_PREFIX}{request_id}"))
-        .get_del(format!("{RES_PREFIX}{request_id}"));
-
-    let (status, value): (Option<String>, Option<Vec<u8>>) = pipe
+        .get_del(format!("{RES_PREFIX}{request_id}"))
+        .exists(format!("{RES_QUOTA_PREFIX}{request_id}"))
+        .get(format!("{REQ_READS_PREFIX}{request_id}"))
+        .get(format!("{REQ_READ_QUOTA_PREFIX}{request_id}"));
+
+    #[allow(clippy::type_complexity)]
+    let (status, value, fan_in, reads, max_reads): (
+        Option<String>,
+        Option<Vec<u8>>,
+        bool,
+        Option<u32>,
+        Option<u32>,
+    ) = pipe
         .query_async(&mut redis)
         .await
         .map_err(handle_redis_error)?;
 
+    // Rejected reads past the limit still bump the counter.
+    let reads = reads.zip(max_reads).map(|(reads, max)| reads.min(max));
+
+    if fan_in {
+        // Fan-in batches have no octet-stream form, and draining removes them,
+        // so turn the poll away while the responses are still there.
+        if accept.0 == PayloadFormat::OctetStream {
+            return Err(StatusCode::NOT_ACCEPTABLE);
+        }
+        return drain_responses(&request_id, redis, &encryption, reads)
+            .await
+            .map(|response| Encoded::new(accept, response));
+    }
+
     if let Some(value) = value {
         let current_status = status
             .and_then(|s| RequestStatus::from_str(&s).ok())
             .unwrap_or(RequestStatus::Retrieved);
 
         tracing::info!(
-            "Request {request_id} state transition: {} -> {}",
+            "Request {} state transition: {} -> {}",
+            redact::id(&request_id),
             current_status,
             RequestStatus::Completed
         );
@@ -89,19 +200,29 @@ async fn get_response(
             .await
         {
             tracing::warn!(
-                "Failed to delete status for {request_id} after response retrieval: {e}"
+                "Failed to delete status for {} after response retrieval: {e}",
+                redact::id(&request_id)
             );
         }
 
-        return serde_json::from_slice(&value).map_or(
-            Err(StatusCode::INTERNAL_SERVER_ERROR),
-            |value| {
-                Ok(Json(Response {
-                    response: value,
-                    status: RequestStatus::Completed,
-                }))
+        let value = encryption.open(&format!("{RES_PREFIX}{request_id}"), &value)?;
+        let payload = match StoredPayload::from_stored(&value)? {
+            StoredPayload::Inline(payload) => payload,
+            StoredPayload::Chunked(manifest) => {
+                return chunked::stream_response(accept.0, redis, encryption, manifest)
+                    .map(Encoded::Stream);
+            }
+        };
+
+        return Ok(Encoded::new(
+            accept,
+            Response {
+                response: Some(payload),
+                status: RequestStatus::Completed,
+                responses: Vec::new(),
+                reads,
             },
-        );
+        ));
     }
 
     //ANCHOR - Return the current status for the request
@@ -115,10 +236,81 @@ async fn get_response(
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    Ok(Json(Response {
+    Ok(Encoded::new(
+        accept,
+        Response {
+            status,
+            response: None,
+            responses: Vec::new(),
+            reads,
+        },
+    ))
+}
+
+/// Return (and remove) every response a fan-in request has received so far.
+/// Once the quota is met the request is `completed`, and draining it for the
+/// last time clears its bookkeeping.
+async fn drain_responses(
+    request_id: &str,
+    mut redis: ConnectionManager,
+    encryption: &StorageEncryption,
+    reads: Option<u32>,
+) -> Result<Response, StatusCode> {
+    let list_key = format!("{RES_LIST_PREFIX}{request_id}");
+
+    // Read and clear the list atomically so a response appended in between
+    // is neither lost nor returned twice.
+    let (status, values): (Option<String>, Vec<Vec<u8>>) = redis::pipe()
+        .atomic()
+        .get(format!("{REQ_STATUS_PREFIX}{request_id}"))
+        .lrange(&list_key, 0, -1)
+        .del(&list_key)
+        .ignore()
+        .query_async(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+
+    let status = status.ok_or(StatusCode::NOT_FOUND)?;
+    let status = RequestStatus::from_str(&status).map_err(|e| {
+        tracing::error!("Failed to parse status: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let responses = values
+        .iter()
+        .map(
+            |value| match StoredPayload::from_stored(&encryption.open(&list_key, value)?)? {
+                StoredPayload::Inline(payload) => Ok(payload),
+                StoredPayload::Chunked(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            },
+        )
+        .collect::<Result<Vec<_>, StatusCode>>()?;
+
+    if status == RequestStatus::Completed {
+        tracing::info!("Fan-in request {} fully drained", redact::id(request_id));
+
+        // Best-effort cleanup (will expire via TTL anyway)
+        if let Err(e) = redis
+            .del::<_, ()>(&[
+                format!("{REQ_STATUS_PREFIX}{request_id}"),
+                format!("{RES_QUOTA_PREFIX}{request_id}"),
+                format!("{RES_COUNT_PREFIX}{request_id}"),
+            ])
+            .await
+        {
+            tracing::warn!(
+                "Failed to clean up fan-in request {}: {e}",
+                redact::id(request_id)
+            );
+        }
+    }
+
+    Ok(Response {
         status,
         response: None,
-    }))
+        responses,
+        reads,
+    })
 }
 
 async fn has_response_status(
@@ -144,48 +336,145 @@ async fn has_response_status(
     }
 }
 
-async fn insert_response(
-    Path(request_id): Path<String>,
-    Extension(mut redis): Extension<ConnectionManager>,
-    Json(request): Json<RequestPayload>,
-) -> Result<StatusCode, StatusCode> {
-    let request_id = request_id.to_lowercase();
-    validate_request_id(&request_id)?;
+/// The pending request a `PUT /response/:request_id` answers. It is looked up
+/// before the body is read, so a response to an unknown request is rejected
+/// without spooling it.
+pub(super) struct Answering {
+    request_id: String,
+    /// `None` if the request doesn't exist, which only the simulator accepts.
+    status: Option<RequestStatus>,
+    callback: Option<String>,
+}
 
-    //ANCHOR - Check the request is valid
-    let current_status = redis
-        .get::<_, Option<String>>(format!("{REQ_STATUS_PREFIX}{request_id}"))
-        .await
-        .map_err(handle_redis_error)?
-        .and_then(|s| RequestStatus::from_str(&s).ok());
+impl Answering {
+    /// Whether there is a request to answer.
+    pub(super) const fn is_pending(&self) -> bool {
+        self.status.is_some()
+    }
 
-    let Some(current_status) = current_status else {
-        return Err(StatusCode::BAD_REQUEST);
-    };
+    /// Look up the status and callback of `request_id`.
+    pub(super) async fn lookup(
+        redis: &mut ConnectionManager,
+        request_id: String,
+    ) -> Result<Self, StatusCode> {
+        let (status, callback): (Option<String>, Option<String>) = redis::pipe()
+            .get(format!("{REQ_STATUS_PREFIX}{request_id}"))
+            .get(format!("{REQ_CALLBACK_PREFIX}{request_id}"))
+            .query_async(redis)
+            .await
+            .map_err(handle_redis_error)?;
+
+        Ok(Self {
+            request_id,
+            status: status.and_then(|s| RequestStatus::from_str(&s).ok()),
+            callback,
+        })
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Answering {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(request_id) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let request_id = request_id.to_lowercase();
+        validate_request_id(&request_id)?;
+
+        let mut redis = parts
+            .extensions
+            .get::<ConnectionManager>()
+            .cloned()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        //ANCHOR - Check the request is valid
+        let answering = Self::lookup(&mut redis, request_id).await?;
+        if !answering.is_pending() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+
+        Ok(answering)
+    }
+}
+
+impl OperationInput for Answering {
+    fn operation_input(ctx: &mut aide::gen::GenContext, operation: &mut aide::openapi::Operation) {
+        Path::<String>::operation_input(ctx, operation);
+    }
+}
+
+async fn insert_response(
+    answering: Answering,
+    Extension(redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(validation): Extension<Arc<PayloadValidation>>,
+    Extension(webhooks): Extension<Arc<Webhooks>>,
+    lifecycle: Lifecycle,
+    Negotiated(request): Negotiated<StoredPayload>,
+) -> Result<StatusCode, ApiError> {
+    request.validate(&validation, PayloadKind::Response)?;
+
+    store_response(
+        answering,
+        redis,
+        &encryption,
+        &webhooks,
+        &lifecycle,
+        &request,
+        false,
+    )
+    .await
+}
+
+/// Store `request` as the response to `answering`: appended to a fan-in
+/// request, otherwise stored once (or over an existing one if `replace`), then
+/// pushed to the request's callback, if it registered one.
+pub(super) async fn store_response(
+    Answering {
+        request_id,
+        status: current_status,
+        callback,
+    }: Answering,
+    mut redis: ConnectionManager,
+    encryption: &StorageEncryption,
+    webhooks: &Arc<Webhooks>,
+    lifecycle: &Lifecycle,
+    request: &StoredPayload,
+    replace: bool,
+) -> Result<StatusCode, ApiError> {
+    if let Some(max_responses) = response_quota(&mut redis, &request_id).await? {
+        return append_response(
+            &request_id,
+            redis,
+            encryption,
+            lifecycle,
+            request,
+            max_responses,
+            current_status,
+        )
+        .await;
+    }
 
     //ANCHOR - Atomically store the response with TTL if not already set (idempotent)
-    let options = SetOptions::default()
-        .conditional_set(ExistenceCheck::NX)
-        .with_expiration(SetExpiry::EX(EXPIRE_AFTER_SECONDS));
+    let mut options = SetOptions::default().with_expiration(SetExpiry::EX(EXPIRE_AFTER_SECONDS));
+    if !replace {
+        options = options.conditional_set(ExistenceCheck::NX);
+    }
+
+    let key = format!("{RES_PREFIX}{request_id}");
+    let payload_bytes = encryption.seal(&key, &request.to_stored()?)?;
 
     let set_ok: Option<String> = redis
-        .set_options(
-            format!("{RES_PREFIX}{request_id}"),
-            serde_json::to_vec(&request).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-            options,
-        )
+        .set_options(key, payload_bytes, options)
         .await
         .map_err(handle_redis_error)?;
 
     if set_ok.is_none() {
-        return Err(StatusCode::CONFLICT);
+        return Err(StatusCode::CONFLICT.into());
     }
-
-    tracing::info!(
-        "Request {request_id} state transition: {} -> {}",
-        current_status,
-        RequestStatus::Completed
-    );
+    request.keep();
 
     //ANCHOR - Delete status
     //NOTE - We can delete the status at this point as the presence of a response implies the request is complete
@@ -193,48 +482,221 @@ async fn insert_response(
         .del::<_, ()>(format!("{REQ_STATUS_PREFIX}{request_id}"))
         .await
         .map_err(handle_redis_error)?;
+    lifecycle
+        .record(
+            &mut redis,
+            &request_id,
+            current_status,
+            RequestStatus::Completed,
+            Some(request.size()),
+        )
+        .await;
+
+    //ANCHOR - Push the response to the RP's callback, if it registered one.
+    //NOTE - Chunked responses are too large to deliver in one body, so they are left for polling.
+    if let (Some(callback), StoredPayload::Inline(payload)) = (callback, request) {
+        match serde_json::from_str(&callback) {
+            Ok(callback) => webhooks.deliver(callback, request_id, payload, redis),
+            Err(e) => tracing::error!(
+                "Failed to decode callback for {}: {e}",
+                redact::id(&request_id)
+            ),
+        }
+    }
+
+    Ok(StatusCode::CREATED)
+}
+
+/// The `max_responses` of a fan-in request, or `None` for a regular one.
+pub(super) async fn response_quota(
+    redis: &mut ConnectionManager,
+    request_id: &str,
+) -> Result<Option<u32>, StatusCode> {
+    redis
+        .get(format!("{RES_QUOTA_PREFIX}{request_id}"))
+        .await
+        .map_err(handle_redis_error)
+}
+
+/// Append a response to a fan-in request, completing it on the last one.
+async fn append_response(
+    request_id: &str,
+    mut redis: ConnectionManager,
+    encryption: &StorageEncryption,
+    lifecycle: &Lifecycle,
+    request: &StoredPayload,
+    max_responses: u32,
+    current_status: Option<RequestStatus>,
+) -> Result<StatusCode, ApiError> {
+    // Responses are drained as a batch, so they have to be inline.
+    if matches!(request, StoredPayload::Chunked(_)) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    let list_key = format!("{RES_LIST_PREFIX}{request_id}");
+    let payload_bytes = encryption.seal(&list_key, &request.to_stored()?)?;
+
+    //ANCHOR - Claim a slot and store the response in one step; the counter expires with the request
+    let count: u32 = APPEND_RESPONSE
+        .key(format!("{RES_COUNT_PREFIX}{request_id}"))
+        .key(&list_key)
+        .key(format!("{REQ_STATUS_PREFIX}{request_id}"))
+        .arg(max_responses)
+        .arg(payload_bytes)
+        .arg(EXPIRE_AFTER_SECONDS)
+        .arg(RequestStatus::Completed.to_string())
+        .invoke_async(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+    if count == 0 {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    if count == max_responses {
+        lifecycle
+            .record(
+                &mut redis,
+                request_id,
+                current_status,
+                RequestStatus::Completed,
+                Some(request.size()),
+            )
+            .await;
+    }
+
+    tracing::info!(
+        "Stored response {count}/{max_responses} for {}",
+        redact::id(request_id)
+    );
 
     Ok(StatusCode::CREATED)
 }
 
+/// Appends a response to a fan-in request unless it already has all of them,
+/// completing the request with the last one. Returns the response's position,
+/// or 0 if the quota was already met.
+static APPEND_RESPONSE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"-- bridge:append_response
+-- KEYS: response count, response list, status
+-- ARGV: max responses, payload, TTL, completed status
+local max = tonumber(ARGV[1])
+if tonumber(redis.call('GET', KEYS[1]) or '0') >= max then
+  return 0
+end
+local count = redis.call('INCR', KEYS[1])
+redis.call('RPUSH', KEYS[2], ARGV[2])
+redis.call('EXPIRE', KEYS[2], ARGV[3])
+if count == max then
+  redis.call('SET', KEYS[3], ARGV[4], 'EX', ARGV[3])
+end
+return count
+",
+    )
+});
+
 /// Create a new standalone response
 async fn create_response(
     Extension(mut redis): Extension<ConnectionManager>,
-    Json(request): Json<RequestPayload>,
-) -> Result<(StatusCode, Json<ResponseCreatedPayload>), StatusCode> {
-    let request_id = Uuid::new_v4().to_string();
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(validation): Extension<Arc<PayloadValidation>>,
+    lifecycle: Lifecycle,
+    idempotency: IdempotencyKey,
+    Negotiated(body): Negotiated<CreateResponseBody>,
+) -> Result<(StatusCode, Json<ResponseCreatedPayload>), ApiError> {
+    body.payload.validate(&validation, PayloadKind::Response)?;
+
+    let request_id = body.request_id.map(|id| id.to_lowercase());
+    let fingerprint = Fingerprint::new("response")
+        .with(body.payload.fingerprint()?)
+        .with(request_id.as_deref().unwrap_or_default());
+    let created = match idempotency
+        .claim(&mut redis, &encryption, fingerprint)
+        .await?
+    {
+        Claim::Replay(created) => created,
+        Claim::Fresh(reservation) => {
+            let result = store_standalone_response(
+                redis.clone(),
+                &encryption,
+                &lifecycle,
+                &body.payload,
+                request_id,
+            )
+            .await;
+            reservation.settle(&mut redis, &encryption, result).await?
+        }
+    };
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
 
-    tracing::info!("Processing POST /response: {request_id}");
+async fn store_standalone_response(
+    mut redis: ConnectionManager,
+    encryption: &StorageEncryption,
+    lifecycle: &Lifecycle,
+    request: &StoredPayload,
+    request_id: Option<String>,
+) -> Result<ResponseCreatedPayload, StatusCode> {
+    let request_id = request::resolve_request_id(request_id)?;
 
-    // Initialize status marker (will be deleted when IDKit retrieves response)
-    redis
-        .set_ex::<_, _, ()>(
+    tracing::info!("Processing POST /response: {}", redact::id(&request_id));
+
+    // Initialize status marker (will be deleted when IDKit retrieves response).
+    // A pending request has one too, so SET NX rejects its `request_id`.
+    let created: Option<String> = redis
+        .set_options(
             format!("{REQ_STATUS_PREFIX}{request_id}"),
             RequestStatus::Initialized.to_string(),
-            EXPIRE_AFTER_SECONDS,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(EXPIRE_AFTER_SECONDS)),
         )
         .await
         .map_err(handle_redis_error)?;
-
-    tracing::info!(
-        "Standalone response {request_id} state transition: new -> {}",
-        RequestStatus::Initialized
-    );
+    if created.is_none() {
+        return Err(StatusCode::CONFLICT);
+    }
 
     // Store response payload with TTL
-    redis
-        .set_ex::<_, _, ()>(
-            format!("{RES_PREFIX}{request_id}"),
-            serde_json::to_vec(&request).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
-            EXPIRE_AFTER_SECONDS,
+    let key = format!("{RES_PREFIX}{request_id}");
+    let payload_bytes = encryption.seal(&key, &request.to_stored()?)?;
+
+    // An answered request has no status left, only its response, so the
+    // response is claimed with SET NX too rather than overwritten.
+    let stored: Option<String> = redis
+        .set_options(
+            key,
+            payload_bytes,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(EXPIRE_AFTER_SECONDS)),
         )
         .await
         .map_err(handle_redis_error)?;
+    if stored.is_none() {
+        redis
+            .del::<_, ()>(format!("{REQ_STATUS_PREFIX}{request_id}"))
+            .await
+            .map_err(handle_redis_error)?;
+        return Err(StatusCode::CONFLICT);
+    }
+    request.keep();
+    // Pollers see a stored response as completed, whatever the status marker says.
+    lifecycle
+        .record(
+            &mut redis,
+            &request_id,
+            None,
+            RequestStatus::Completed,
+            Some(request.size()),
+        )
+        .await;
 
-    tracing::info!("Successfully processed POST /response: {request_id}");
+    tracing::info!(
+        "Successfully processed POST /response: {}",
+        redact::id(&request_id)
+    );
 
-    Ok((
-        StatusCode::CREATED,
-        Json(ResponseCreatedPayload { request_id }),
-    ))
+    Ok(ResponseCreatedPayload { request_id })
 }
@@ -0,0 +1,298 @@
+//! Controls for driving the bridge from end-to-end tests.
+//!
+//! The simulator stands in for the Authenticator, and these routes let it set
+//! up states a real flow would take minutes or a second device to reach: force
+//! a request's status, inject a response for any `request_id`, and
+//! fast-forward the TTLs of everything stored for it. They work on the same
+//! keys as the public API and record transitions through [`Lifecycle`], so
+//! pollers and status subscribers see them like any other transition.
+//!
+//! The group is only mounted in profiles with [`Feature::DebugEndpoints`]
+//! and a configured token, which every call must send as
+//! `Authorization: Bearer <token>`.
+//!
+//! [`Feature::DebugEndpoints`]: crate::environment::Feature::DebugEndpoints
+
+use std::{str::FromStr, sync::Arc};
+
+use aide::{
+    axum::{
+        routing::{post, put},
+        ApiRouter,
+    },
+    OperationInput,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    Extension,
+};
+use axum_jsonschema::Json;
+use redis::{aio::ConnectionManager, AsyncCommands, SetExpiry, SetOptions};
+use ring::{hmac, rand::SystemRandom};
+use schemars::JsonSchema;
+
+use super::chunked::{ChunkManifest, StoredPayload};
+use super::lifecycle::Lifecycle;
+use super::response::{self, Answering};
+use crate::envelope::StorageEncryption;
+use crate::redact;
+use crate::utils::{
+    handle_redis_error, validate_request_id, ApiError, RequestPayload, RequestStatus,
+    EXPIRE_AFTER_SECONDS, REQ_CALLBACK_PREFIX, REQ_LEASE_PREFIX, REQ_METADATA_PREFIX, REQ_PREFIX,
+    REQ_READS_PREFIX, REQ_READ_QUOTA_PREFIX, REQ_STATUS_PREFIX, RES_COUNT_PREFIX, RES_LIST_PREFIX,
+    RES_PREFIX, RES_QUOTA_PREFIX,
+};
+use crate::webhook::Webhooks;
+
+/// Every key prefix holding state of a single `request_id`, besides the
+/// chunks a chunked payload points to.
+const REQUEST_KEY_PREFIXES: [&str; 11] = [
+    REQ_PREFIX,
+    REQ_STATUS_PREFIX,
+    REQ_LEASE_PREFIX,
+    REQ_CALLBACK_PREFIX,
+    REQ_METADATA_PREFIX,
+    REQ_READS_PREFIX,
+    REQ_READ_QUOTA_PREFIX,
+    RES_PREFIX,
+    RES_LIST_PREFIX,
+    RES_QUOTA_PREFIX,
+    RES_COUNT_PREFIX,
+];
+
+/// The configured token, kept as an HMAC tag under a random key so checking
+/// a presented token takes the same time whatever it shares with the real one.
+struct Token {
+    key: hmac::Key,
+    tag: hmac::Tag,
+}
+
+impl Token {
+    fn new(token: &str) -> Self {
+        let key = hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .expect("failed to generate a simulator token key");
+        let tag = hmac::sign(&key, token.as_bytes());
+        Self { key, tag }
+    }
+
+    fn verify(&self, presented: &str) -> bool {
+        hmac::verify(&self.key, presented.as_bytes(), self.tag.as_ref()).is_ok()
+    }
+}
+
+/// Proof that the caller sent the simulator token.
+struct Authorized;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Authorized {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(token) = parts.extensions.get::<Arc<Token>>() else {
+            tracing::error!("Simulator token extension is missing");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+
+        let presented = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match presented {
+            Some(presented) if token.verify(presented.trim()) => Ok(Self),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+impl OperationInput for Authorized {}
+
+pub fn handler(token: &str) -> ApiRouter {
+    ApiRouter::new()
+        .api_route("/simulator/request/:request_id/status", put(force_status))
+        .api_route(
+            "/simulator/request/:request_id/fast-forward",
+            post(fast_forward),
+        )
+        .api_route("/simulator/response/:request_id", put(inject_response))
+        .layer(Extension(Arc::new(Token::new(token))))
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+struct ForceStatusBody {
+    status: RequestStatus,
+}
+
+/// Set the status of `request_id`, whatever it was, keeping its TTL. A
+/// request without a status gets a fresh one.
+async fn force_status(
+    _: Authorized,
+    Path(request_id): Path<String>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    lifecycle: Lifecycle,
+    Json(body): Json<ForceStatusBody>,
+) -> Result<StatusCode, ApiError> {
+    let request_id = request_id.to_lowercase();
+    validate_request_id(&request_id)?;
+
+    let key = format!("{REQ_STATUS_PREFIX}{request_id}");
+    let (current, ttl_ms): (Option<String>, i64) = redis::pipe()
+        .get(&key)
+        .pttl(&key)
+        .query_async(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+    let current = current.and_then(|s| RequestStatus::from_str(&s).ok());
+
+    let expiry = u64::try_from(ttl_ms)
+        .ok()
+        .filter(|ms| *ms > 0)
+        .map_or(SetExpiry::EX(EXPIRE_AFTER_SECONDS), SetExpiry::PX);
+    redis
+        .set_options::<_, _, ()>(
+            key,
+            body.status.to_string(),
+            SetOptions::default().with_expiration(expiry),
+        )
+        .await
+        .map_err(handle_redis_error)?;
+
+    lifecycle
+        .record(&mut redis, &request_id, current, body.status, None)
+        .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Store `payload` as the response to `request_id`, replacing any response
+/// already there, whether or not the request exists. Otherwise it is stored
+/// like `PUT /response/:request_id`: appended to a fan-in request and pushed
+/// to the request's callback.
+async fn inject_response(
+    _: Authorized,
+    Path(request_id): Path<String>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(webhooks): Extension<Arc<Webhooks>>,
+    lifecycle: Lifecycle,
+    Json(payload): Json<RequestPayload>,
+) -> Result<StatusCode, ApiError> {
+    let request_id = request_id.to_lowercase();
+    validate_request_id(&request_id)?;
+
+    let answering = Answering::lookup(&mut redis, request_id).await?;
+    response::store_response(
+        answering,
+        redis,
+        &encryption,
+        &webhooks,
+        &lifecycle,
+        &StoredPayload::Inline(payload),
+        true,
+    )
+    .await
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+struct FastForwardBody {
+    /// How far to move the clock, in seconds.
+    seconds: u64,
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+struct FastForwarded {
+    /// Keys that ran out and were deleted.
+    expired: u32,
+    /// Keys still alive, with their TTL shortened.
+    remaining: u32,
+}
+
+/// Shorten the TTL of everything stored for `request_id` by `seconds`,
+/// deleting what runs out, as if that much time had passed.
+async fn fast_forward(
+    _: Authorized,
+    Path(request_id): Path<String>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Json(body): Json<FastForwardBody>,
+) -> Result<Json<FastForwarded>, ApiError> {
+    let request_id = request_id.to_lowercase();
+    validate_request_id(&request_id)?;
+
+    let mut keys: Vec<String> = REQUEST_KEY_PREFIXES
+        .iter()
+        .map(|prefix| format!("{prefix}{request_id}"))
+        .collect();
+    keys.extend(chunk_keys(&mut redis, &encryption, &request_id).await?);
+
+    let mut pipe = redis::pipe();
+    for key in &keys {
+        pipe.pttl(key);
+    }
+    let ttls: Vec<i64> = pipe
+        .query_async(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+
+    // Not atomic with the reads, which is fine for a test harness.
+    let skipped = i64::try_from(body.seconds.saturating_mul(1000)).unwrap_or(i64::MAX);
+    let mut result = FastForwarded {
+        expired: 0,
+        remaining: 0,
+    };
+    let mut pipe = redis::pipe();
+    // A TTL of -2 means the key doesn't exist and -1 that it never expires.
+    for (key, ttl) in keys.iter().zip(ttls).filter(|(_, ttl)| *ttl >= 0) {
+        if ttl <= skipped {
+            pipe.del(key);
+            result.expired += 1;
+        } else {
+            pipe.pexpire(key, ttl - skipped);
+            result.remaining += 1;
+        }
+    }
+    pipe.query_async::<()>(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+
+    tracing::info!(
+        "Fast-forwarded {} by {}s: {} key(s) expired, {} remaining",
+        redact::id(&request_id),
+        body.seconds,
+        result.expired,
+        result.remaining
+    );
+
+    Ok(Json(result))
+}
+
+/// The chunks behind a chunked request or response of `request_id`.
+async fn chunk_keys(
+    redis: &mut ConnectionManager,
+    encryption: &StorageEncryption,
+    request_id: &str,
+) -> Result<Vec<String>, ApiError> {
+    let mut keys = Vec::new();
+    for key in [
+        format!("{REQ_PREFIX}{request_id}"),
+        format!("{RES_PREFIX}{request_id}"),
+    ] {
+        let stored: Option<Vec<u8>> = redis.get(&key).await.map_err(handle_redis_error)?;
+        let Some(stored) = stored else {
+            continue;
+        };
+        if let StoredPayload::Chunked(manifest) =
+            StoredPayload::from_stored(&encryption.open(&key, &stored)?)?
+        {
+            keys.extend(
+                (0..manifest.chunk_count())
+                    .map(|index| ChunkManifest::chunk_key(&manifest.upload_id, index)),
+            );
+        }
+    }
+
+    Ok(keys)
+}
@@ -1,7 +1,11 @@
+use std::sync::Arc;
+
 use aide::{axum::ApiRouter, openapi::OpenApi, scalar::Scalar};
 use axum::{routing::get, Extension};
 use axum_jsonschema::Json;
 
+use crate::environment::{Environment, Feature};
+
 pub fn handler() -> ApiRouter {
     let scalar = Scalar::new("/openapi.json").with_title("Wallet Bridge Docs");
 
@@ -26,12 +30,21 @@ pub struct RootResponse {
     pub version: AppVersion,
     /// Documentation URL
     pub docs_url: String,
+    /// Deployment profile
+    pub environment: Environment,
+    /// Active features
+    pub features: Vec<Feature>,
 }
 
 #[allow(clippy::unused_async)]
-async fn get_info() -> Json<RootResponse> {
+async fn get_info(
+    Extension(environment): Extension<Environment>,
+    Extension(features): Extension<Arc<[Feature]>>,
+) -> Json<RootResponse> {
     Json(RootResponse {
         docs_url: "/docs".to_string(),
+        environment,
+        features: features.to_vec(),
         repo_url: "https://github.com/worldcoin/wallet-bridge".to_string(),
         version: AppVersion {
             semver: env!("CARGO_PKG_VERSION").to_string(),
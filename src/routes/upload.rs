@@ -0,0 +1,524 @@
+//! Resumable chunked uploads for payloads larger than the request body limit.
+//!
+//! `POST /upload` declares the payload's IV, size and chunk size, each chunk is
+//! then `PUT` as a raw body (in any order, retried as often as needed), and
+//! `POST /upload/:upload_id/finalize` publishes it as a request or a response.
+//! Readers get the payload through the usual `GET /request/:id` and
+//! `GET /response/:id`, streamed back chunk by chunk.
+
+use std::sync::{Arc, LazyLock};
+
+use aide::axum::{
+    routing::{get, post, put},
+    ApiRouter,
+};
+use axum::{body::Bytes, extract::Path, http::StatusCode, Extension};
+use axum_jsonschema::Json;
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, Script, SetExpiry, SetOptions};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::chunked::{ChunkManifest, SpooledChunks, StoredPayload};
+use super::lifecycle::Lifecycle;
+use super::response::{self, Answering};
+use crate::compression::RouteGroup;
+use crate::envelope::StorageEncryption;
+use crate::redact;
+use crate::utils::{
+    handle_redis_error, validate_request_id, ApiError, PayloadField, RequestStatus,
+    EXPIRE_AFTER_SECONDS, MAX_BODY_BYTES, REQ_PREFIX, REQ_STATUS_PREFIX,
+};
+use crate::validation::{PayloadKind, PayloadValidation};
+use crate::webhook::Webhooks;
+use crate::Config;
+
+const UPLOAD_PREFIX: &str = "upload:";
+
+/// Largest payload accepted through a chunked upload.
+pub const MAX_UPLOAD_BYTES: u64 = 64 * 1024 * 1024;
+/// Smallest chunk size, unless the whole payload fits in one chunk. Keeps a
+/// single upload from fanning out into an unbounded number of Redis keys.
+pub const MIN_CHUNK_BYTES: u32 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum UploadTarget {
+    /// Published like `POST /request`.
+    Request,
+    /// Published like `PUT /response/:request_id`.
+    Response,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct CreateUploadBody {
+    /// Whether the finished upload becomes a request or a response.
+    target: UploadTarget,
+    /// For requests, an optional client-supplied `request_id` (generated when
+    /// absent). For responses, the request being answered (required).
+    #[serde(default)]
+    request_id: Option<String>,
+    /// The initialization vector for the encrypted payload (opaque to the bridge).
+    iv: PayloadField,
+    /// Total payload size in bytes.
+    size: u64,
+    /// Size of every chunk but the last, which holds the remainder.
+    chunk_size: u32,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct UploadCreatedPayload {
+    /// Identifies the upload in the chunk and finalize routes.
+    upload_id: String,
+    /// The `request_id` the payload will be published under.
+    request_id: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct UploadProgress {
+    /// Number of chunks the payload is split into.
+    chunk_count: u64,
+    /// Indices of the chunks received so far, in ascending order.
+    received: Vec<u64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct UploadFinalizedPayload {
+    /// The `request_id` the payload was published under.
+    request_id: String,
+}
+
+/// An upload in progress, stored under `upload:<upload_id>` until finalized.
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadState {
+    target: UploadTarget,
+    request_id: String,
+    iv: PayloadField,
+    size: u64,
+    chunk_size: u32,
+}
+
+impl UploadState {
+    fn manifest(self, upload_id: String) -> ChunkManifest {
+        ChunkManifest {
+            upload_id,
+            iv: self.iv,
+            size: self.size,
+            chunk_size: self.chunk_size,
+            digest: None,
+            spooled: None,
+        }
+    }
+}
+
+pub fn handler(config: &Config) -> ApiRouter {
+    let cors = config.cors.layer(RouteGroup::Upload, &[]);
+
+    let router = ApiRouter::new()
+        .api_route("/upload", post(create_upload))
+        .api_route("/upload/:upload_id", get(get_upload))
+        .api_route("/upload/:upload_id/chunk/:index", put(put_chunk))
+        .api_route("/upload/:upload_id/finalize", post(finalize_upload))
+        .layer(cors);
+
+    config.compression.apply(RouteGroup::Upload, router)
+}
+
+/// Start a chunked upload.
+async fn create_upload(
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(validation): Extension<Arc<PayloadValidation>>,
+    Json(body): Json<CreateUploadBody>,
+) -> Result<(StatusCode, Json<UploadCreatedPayload>), ApiError> {
+    if body.size == 0
+        || body.size > MAX_UPLOAD_BYTES
+        || body.chunk_size == 0
+        || body.chunk_size as usize > MAX_BODY_BYTES
+        || (body.chunk_size < MIN_CHUNK_BYTES && u64::from(body.chunk_size) < body.size)
+    {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let kind = match body.target {
+        UploadTarget::Request => PayloadKind::Request,
+        UploadTarget::Response => PayloadKind::Response,
+    };
+    validation.validate_iv(&body.iv)?;
+    validation.validate_size(
+        kind,
+        usize::try_from(body.size).map_err(|_| StatusCode::BAD_REQUEST)?,
+    )?;
+
+    let request_id = match (body.target, body.request_id) {
+        (_, Some(id)) => {
+            let id = id.to_lowercase();
+            validate_request_id(&id)?;
+            id
+        }
+        (UploadTarget::Request, None) => Uuid::new_v4().to_string(),
+        (UploadTarget::Response, None) => return Err(StatusCode::BAD_REQUEST.into()),
+    };
+
+    // Fail fast rather than after every chunk has been sent; finalize checks
+    // again in case the request expired or was answered in the meantime.
+    if body.target == UploadTarget::Response {
+        let exists: bool = redis
+            .exists(format!("{REQ_STATUS_PREFIX}{request_id}"))
+            .await
+            .map_err(handle_redis_error)?;
+        if !exists {
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+        // Fan-in responses are drained as a batch and must be inline.
+        if response::response_quota(&mut redis, &request_id)
+            .await?
+            .is_some()
+        {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+        }
+    }
+
+    let upload_id = Uuid::new_v4().to_string();
+    let state = UploadState {
+        target: body.target,
+        request_id: request_id.clone(),
+        iv: body.iv,
+        size: body.size,
+        chunk_size: body.chunk_size,
+    };
+
+    let key = format!("{UPLOAD_PREFIX}{upload_id}");
+    let mut stored = Vec::new();
+    ciborium::into_writer(&state, &mut stored).map_err(|e| {
+        tracing::error!("Failed to encode upload state: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let stored = encryption.seal(&key, &stored)?;
+
+    redis
+        .set_ex::<_, _, ()>(key, stored, EXPIRE_AFTER_SECONDS)
+        .await
+        .map_err(handle_redis_error)?;
+
+    tracing::info!(
+        "Started upload {} for {}",
+        redact::id(&upload_id),
+        redact::id(&request_id)
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadCreatedPayload {
+            upload_id,
+            request_id,
+        }),
+    ))
+}
+
+/// List the chunks received so far, so an interrupted upload can resume.
+async fn get_upload(
+    Path(upload_id): Path<String>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+) -> Result<Json<UploadProgress>, StatusCode> {
+    let state = load_upload(&mut redis, &encryption, &upload_id).await?;
+    let manifest = state.manifest(upload_id);
+    let chunk_count = manifest.chunk_count();
+    let present = chunks_present(&mut redis, &manifest).await?;
+
+    Ok(Json(UploadProgress {
+        chunk_count,
+        received: (0..chunk_count)
+            .zip(present)
+            .filter_map(|(index, present)| present.then_some(index))
+            .collect(),
+    }))
+}
+
+/// Store one chunk. Every chunk but the last must be exactly `chunk_size`
+/// bytes. Re-sending a chunk overwrites it until the upload is finalized.
+async fn put_chunk(
+    Path((upload_id, index)): Path<(String, u64)>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let state = load_upload(&mut redis, &encryption, &upload_id).await?;
+    let manifest = state.manifest(upload_id);
+
+    let chunk_size = u64::from(manifest.chunk_size);
+    let start = index
+        .checked_mul(chunk_size)
+        .filter(|&start| start < manifest.size)
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let expected = chunk_size.min(manifest.size - start);
+    if body.len() as u64 != expected {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // A finalize may have published the upload since it was loaded, so the
+    // chunk is only written while the upload is still open.
+    let key = ChunkManifest::chunk_key(&manifest.upload_id, index);
+    let sealed = encryption.seal(&key, &body)?;
+    let stored: bool = STORE_CHUNK
+        .key(format!("{UPLOAD_PREFIX}{}", manifest.upload_id))
+        .key(key)
+        .arg(sealed)
+        .arg(EXPIRE_AFTER_SECONDS)
+        .invoke_async(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+    if !stored {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::CREATED)
+}
+
+/// Writes a chunk only if its upload hasn't been finalized (or expired).
+static STORE_CHUNK: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"-- bridge:store_upload_chunk
+-- KEYS: upload, chunk
+-- ARGV: sealed chunk, TTL
+if redis.call('EXISTS', KEYS[1]) == 0 then
+  return 0
+end
+redis.call('SET', KEYS[2], ARGV[1], 'EX', ARGV[2])
+return 1
+",
+    )
+});
+
+/// Publish a complete upload as a request or a response.
+async fn finalize_upload(
+    Path(upload_id): Path<String>,
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(webhooks): Extension<Arc<Webhooks>>,
+    lifecycle: Lifecycle,
+) -> Result<(StatusCode, Json<UploadFinalizedPayload>), ApiError> {
+    let upload_key = format!("{UPLOAD_PREFIX}{upload_id}");
+    let state = load_upload(&mut redis, &encryption, &upload_id).await?;
+    let (target, request_id) = (state.target, state.request_id.clone());
+    let mut manifest = state.manifest(upload_id);
+
+    if chunks_present(&mut redis, &manifest)
+        .await?
+        .contains(&false)
+    {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    // Close the upload before publishing it, so no chunk changes under the
+    // manifest and a concurrent finalize gets a 404. Its state is kept in case
+    // it has to be reopened.
+    let (ttl_ms, closed): (i64, Option<Vec<u8>>) = redis::pipe()
+        .atomic()
+        .pttl(&upload_key)
+        .get_del(&upload_key)
+        .query_async(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+    let Some(closed) = closed else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+
+    // Chunks were written over the course of the upload; line their expiry up
+    // with the payload they now belong to.
+    let mut pipe = redis::pipe();
+    for index in 0..manifest.chunk_count() {
+        pipe.cmd("EXPIRE")
+            .arg(ChunkManifest::chunk_key(&manifest.upload_id, index))
+            .arg(EXPIRE_AFTER_SECONDS)
+            .ignore();
+    }
+    pipe.query_async::<()>(&mut redis)
+        .await
+        .map_err(handle_redis_error)?;
+
+    // From here on the chunks are deleted unless the payload is published or
+    // the upload reopened.
+    let (upload_id, chunk_count) = (manifest.upload_id.clone(), manifest.chunk_count());
+    manifest.spooled = Some(SpooledChunks::new(
+        redis.clone(),
+        upload_id.clone(),
+        chunk_count,
+    ));
+    let payload = StoredPayload::Chunked(manifest);
+
+    let published = match target {
+        UploadTarget::Request => {
+            publish_request(&mut redis, &encryption, &lifecycle, &request_id, &payload).await
+        }
+        UploadTarget::Response => {
+            publish_response(
+                redis.clone(),
+                &encryption,
+                &webhooks,
+                &lifecycle,
+                &request_id,
+                &payload,
+            )
+            .await
+        }
+    };
+    if let Err(e) = published {
+        // Failures on our side can be retried, so the client gets its upload
+        // back. Anything else (the request was answered, say) never will be,
+        // so the chunks go.
+        if matches!(e, ApiError::Status(status) if status.is_server_error())
+            && reopen_upload(&mut redis, &upload_key, closed, ttl_ms).await
+        {
+            payload.keep();
+        }
+        return Err(e);
+    }
+
+    tracing::info!(
+        "Finalized upload {} ({chunk_count} chunks) for {}",
+        redact::id(&upload_id),
+        redact::id(&request_id)
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(UploadFinalizedPayload { request_id }),
+    ))
+}
+
+/// Put back the state of an upload that failed to publish, with the TTL it had
+/// left. Returns whether it is open again.
+async fn reopen_upload(
+    redis: &mut ConnectionManager,
+    upload_key: &str,
+    state: Vec<u8>,
+    ttl_ms: i64,
+) -> bool {
+    let expiry = u64::try_from(ttl_ms)
+        .ok()
+        .filter(|ms| *ms > 0)
+        .map_or(SetExpiry::EX(EXPIRE_AFTER_SECONDS), SetExpiry::PX);
+    let reopened: Result<Option<String>, _> = redis
+        .set_options(
+            upload_key,
+            state,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(expiry),
+        )
+        .await;
+
+    match reopened {
+        Ok(reopened) => reopened.is_some(),
+        Err(e) => {
+            tracing::warn!("Failed to reopen an upload that failed to publish: {e}");
+            false
+        }
+    }
+}
+
+/// Same transitions as `POST /request`.
+async fn publish_request(
+    redis: &mut ConnectionManager,
+    encryption: &StorageEncryption,
+    lifecycle: &Lifecycle,
+    request_id: &str,
+    payload: &StoredPayload,
+) -> Result<(), ApiError> {
+    let key = format!("{REQ_PREFIX}{request_id}");
+    let stored = encryption.seal(&key, &payload.to_stored()?)?;
+    let set_ok: Option<String> = redis
+        .set_options(
+            key,
+            stored,
+            SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(EXPIRE_AFTER_SECONDS)),
+        )
+        .await
+        .map_err(handle_redis_error)?;
+    if set_ok.is_none() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+    payload.keep();
+
+    redis
+        .set_ex::<_, _, ()>(
+            format!("{REQ_STATUS_PREFIX}{request_id}"),
+            RequestStatus::Initialized.to_string(),
+            EXPIRE_AFTER_SECONDS,
+        )
+        .await
+        .map_err(handle_redis_error)?;
+    lifecycle
+        .record(
+            redis,
+            request_id,
+            None,
+            RequestStatus::Initialized,
+            Some(payload.size()),
+        )
+        .await;
+
+    Ok(())
+}
+
+/// Stored through the same path as `PUT /response/:request_id`.
+async fn publish_response(
+    mut redis: ConnectionManager,
+    encryption: &StorageEncryption,
+    webhooks: &Arc<Webhooks>,
+    lifecycle: &Lifecycle,
+    request_id: &str,
+    payload: &StoredPayload,
+) -> Result<(), ApiError> {
+    let answering = Answering::lookup(&mut redis, request_id.to_string()).await?;
+    if !answering.is_pending() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    response::store_response(
+        answering, redis, encryption, webhooks, lifecycle, payload, false,
+    )
+    .await
+    .map(drop)
+}
+
+async fn load_upload(
+    redis: &mut ConnectionManager,
+    encryption: &StorageEncryption,
+    upload_id: &str,
+) -> Result<UploadState, StatusCode> {
+    // Upload IDs are always server-generated UUIDs.
+    if Uuid::parse_str(upload_id).is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let key = format!("{UPLOAD_PREFIX}{upload_id}");
+    let stored: Vec<u8> = redis
+        .get::<_, Option<Vec<u8>>>(&key)
+        .await
+        .map_err(handle_redis_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let stored = encryption.open(&key, &stored)?;
+
+    ciborium::from_reader(stored.as_slice()).map_err(|e| {
+        tracing::error!("Failed to decode upload state: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Which of the manifest's chunks exist, by index.
+async fn chunks_present(
+    redis: &mut ConnectionManager,
+    manifest: &ChunkManifest,
+) -> Result<Vec<bool>, StatusCode> {
+    let mut pipe = redis::pipe();
+    for index in 0..manifest.chunk_count() {
+        pipe.exists(ChunkManifest::chunk_key(&manifest.upload_id, index));
+    }
+
+    pipe.query_async(redis).await.map_err(handle_redis_error)
+}
@@ -0,0 +1,259 @@
+//! The `/v2` contract.
+//!
+//! v1 grew opt-in flags (`supports_app_overrides`, `accept-idkit-flow-id`)
+//! because strict clients such as `kotlinx.serialization` reject unknown JSON
+//! keys, so no field could be added to a v1 response without one. v2 clients
+//! must ignore unknown fields instead, which lets v2 responses carry every
+//! field unconditionally and grow new ones without a flag.
+//!
+//! Only the request routes differ from v1; responses and uploads are shared.
+//! The request routes still echo `bridge-capabilities`: the capabilities that
+//! only add fields are always applied, while `lease`, which changes how a
+//! request is consumed, stays opt-in.
+
+use std::sync::Arc;
+
+use aide::axum::{
+    routing::{head, post, put},
+    ApiRouter,
+};
+use axum::{
+    extract::Path,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    Extension,
+};
+use axum_jsonschema::Json;
+use redis::aio::ConnectionManager;
+use schemars::JsonSchema;
+
+use super::capabilities::{Capabilities, Capability, WithCapabilities};
+use super::chunked::StoredPayload;
+use super::idempotency::{Claim, IdempotencyKey};
+use super::lifecycle::Lifecycle;
+use super::negotiate::{
+    payload_into_octet_stream, request_id_from_headers, Accept, Encoded, FromOctetStream,
+    IntoOctetStream, Negotiated,
+};
+use super::request::{self, RequestOptions, Taken, IDKIT_FLOW_ID_HEADER, LEASE_TOKEN_HEADER};
+use super::{response, upload};
+use crate::compression::RouteGroup;
+use crate::envelope::StorageEncryption;
+use crate::environment::Feature;
+use crate::metadata::RequestMetadata;
+use crate::utils::{ApiError, AppOverrides, RequestPayload};
+use crate::validation::{PayloadKind, PayloadValidation};
+use crate::webhook::{Callback, Webhooks};
+use crate::Config;
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+#[schemars(rename = "CreateRequestBodyV2")]
+struct CreateRequestBody {
+    /// The encrypted `iv` and `payload` (opaque to the bridge).
+    #[serde(flatten)]
+    payload: StoredPayload,
+    /// Optional client-supplied `request_id`, stored with NX semantics (409 on
+    /// collision). A UUID v4 is generated when absent. As in v1, the entropy
+    /// of a supplied ID is the client's responsibility.
+    #[serde(default)]
+    request_id: Option<String>,
+    /// Accept up to this many responses (at most 32) instead of one; see v1.
+    #[serde(default)]
+    max_responses: Option<u32>,
+    /// Allow up to this many reads (at most 16) instead of one; see v1.
+    #[serde(default)]
+    max_reads: Option<u32>,
+    /// Deliver the response to this allowlisted URL; see v1.
+    #[serde(default)]
+    callback_url: Option<String>,
+    /// The app `callback_url` is registered under. Required with it.
+    #[serde(default)]
+    app_id: Option<String>,
+    /// Plaintext description of the request for per-app policies; see v1.
+    #[serde(default)]
+    metadata: Option<RequestMetadata>,
+}
+
+/// Octet-stream uploads carry the IV in `bridge-iv` and an optional
+/// `request_id` in `bridge-request-id`.
+impl FromOctetStream for CreateRequestBody {
+    type Headers = Option<String>;
+
+    fn octet_stream_headers(headers: &HeaderMap) -> Result<Self::Headers, StatusCode> {
+        request_id_from_headers(headers)
+    }
+
+    fn from_octet_stream(request_id: Self::Headers, payload: StoredPayload) -> Self {
+        Self {
+            payload,
+            request_id,
+            max_responses: None,
+            max_reads: None,
+            callback_url: None,
+            app_id: None,
+            metadata: None,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, JsonSchema)]
+#[schemars(rename = "RequestCreatedV2")]
+struct RequestCreated {
+    /// The client-supplied `request_id`, or the generated UUID v4.
+    request_id: String,
+    /// Per-`app_id` deeplink values and overrides configured on the server.
+    /// Always present, and empty when nothing is configured.
+    app_overrides: AppOverrides,
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+#[schemars(rename = "RequestResponseV2")]
+struct RequestResponse {
+    /// The opaque encrypted request payload.
+    #[serde(flatten)]
+    payload: RequestPayload,
+    /// Correlates this retrieval in telemetry.
+    idkit_flow_id: String,
+    /// Present when the request was leased (the `lease` capability). Send it
+    /// to `POST /v2/request/:request_id/ack` to delete the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lease_token: Option<String>,
+}
+
+impl IntoOctetStream for RequestResponse {
+    fn into_octet_stream(self) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+        let (mut headers, body) = payload_into_octet_stream(self.payload)?;
+        headers.insert(
+            IDKIT_FLOW_ID_HEADER,
+            HeaderValue::from_str(&self.idkit_flow_id)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+        if let Some(token) = self.lease_token {
+            headers.insert(
+                LEASE_TOKEN_HEADER,
+                HeaderValue::from_str(&token).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        Ok((headers, body))
+    }
+}
+
+pub fn handler(config: &Config) -> ApiRouter {
+    let mut router = ApiRouter::new()
+        .api_route("/request", post(insert_request))
+        .api_route(
+            "/request/:request_id",
+            head(request::has_request).get(get_request),
+        )
+        .api_route("/request/:request_id/ack", post(request::ack_request))
+        .layer(request::cors(&config.cors));
+
+    if config.enables(Feature::StagingUpsert) {
+        router = router.api_route("/request/:request_id", put(request::put_request));
+    }
+    let router = router.layer(Extension(PayloadKind::Request));
+
+    ApiRouter::new()
+        .merge(config.compression.apply(RouteGroup::Request, router))
+        .merge(response::handler(config))
+        .merge(upload::handler(config))
+}
+
+/// Create a new request. Optionally accepts a client-supplied `request_id`
+/// with NX semantics; otherwise generates a UUID v4.
+#[allow(clippy::too_many_arguments)]
+async fn insert_request(
+    Extension(mut redis): Extension<ConnectionManager>,
+    Extension(app_overrides): Extension<Arc<AppOverrides>>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    Extension(validation): Extension<Arc<PayloadValidation>>,
+    Extension(webhooks): Extension<Arc<Webhooks>>,
+    lifecycle: Lifecycle,
+    idempotency: IdempotencyKey,
+    Negotiated(body): Negotiated<CreateRequestBody>,
+) -> Result<WithCapabilities<Json<RequestCreated>>, ApiError> {
+    let options = RequestOptions {
+        request_id: body.request_id,
+        max_responses: body.max_responses,
+        max_reads: body.max_reads,
+        callback: Callback::from_parts(body.app_id, body.callback_url)?,
+        metadata: body.metadata,
+    };
+
+    let fingerprint = request::fingerprint_request("v2:request", &body.payload, &options)?;
+    let created = match idempotency
+        .claim(&mut redis, &encryption, fingerprint)
+        .await?
+    {
+        Claim::Replay(created) => created,
+        Claim::Fresh(reservation) => {
+            let result = request::store_request(
+                redis.clone(),
+                &encryption,
+                &validation,
+                &webhooks,
+                &lifecycle,
+                &body.payload,
+                options,
+            )
+            .await
+            .map(|request_id| RequestCreated {
+                request_id,
+                app_overrides: AppOverrides::clone(&app_overrides),
+            });
+            reservation.settle(&mut redis, &encryption, result).await?
+        }
+    };
+
+    Ok(WithCapabilities {
+        applied: Capabilities::from_iter([Capability::AppOverrides]),
+        inner: Json(created),
+    })
+}
+
+async fn get_request(
+    Path(request_id): Path<String>,
+    Extension(redis): Extension<ConnectionManager>,
+    Extension(encryption): Extension<Arc<StorageEncryption>>,
+    lifecycle: Lifecycle,
+    capabilities: Capabilities,
+    accept: Accept,
+) -> Result<WithCapabilities<Encoded<RequestResponse>>, StatusCode> {
+    let mut applied = Capabilities::from_iter([Capability::IdkitFlowId]);
+    let idkit_flow_id = request::new_idkit_flow_id();
+
+    let (payload, lease_token) = match request::take_request(
+        request_id,
+        redis,
+        encryption,
+        &lifecycle,
+        accept,
+        Some(&idkit_flow_id),
+        capabilities.contains(Capability::Lease),
+    )
+    .await?
+    {
+        Taken::Payload {
+            payload,
+            lease_token,
+        } => (payload, lease_token),
+        Taken::Stream(response) => {
+            return Ok(WithCapabilities {
+                applied,
+                inner: Encoded::Stream(response),
+            })
+        }
+    };
+    applied.apply_if(Capability::Lease, lease_token.is_some());
+
+    Ok(WithCapabilities {
+        applied,
+        inner: Encoded::new(
+            accept,
+            RequestResponse {
+                payload,
+                idkit_flow_id,
+                lease_token,
+            },
+        ),
+    })
+}
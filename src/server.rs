@@ -1,9 +1,9 @@
-use std::{env, net::SocketAddr, sync::Arc};
+use std::{env, net::SocketAddr};
 
 use redis::aio::ConnectionManager;
 use tokio::{net::TcpListener, signal};
 
-use crate::utils::AppOverrides;
+use crate::Config;
 
 /// Bind the configured address and serve the bridge until a shutdown signal.
 ///
@@ -11,8 +11,8 @@ use crate::utils::AppOverrides;
 ///
 /// Panics if `PORT` is set but not parseable as a port, if binding the TCP
 /// listener fails, or if the server exits with an error.
-pub async fn start(redis: ConnectionManager, app_overrides: Arc<AppOverrides>) {
-    let router = crate::app(redis, app_overrides);
+pub async fn start(redis: ConnectionManager, config: Config) {
+    let router = crate::app(redis, config);
 
     let address = SocketAddr::from((
         [0, 0, 0, 0],
@@ -24,10 +24,14 @@ pub async fn start(redis: ConnectionManager, app_overrides: Arc<AppOverrides>) {
 
     println!("🔛💬 Message Bridge started on http://{address}");
 
-    axum::serve(listener, router.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .expect("Failed to start server");
+    // Connection info gives audit events the client's address class.
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .expect("Failed to start server");
 }
 
 async fn shutdown_signal() {
@@ -1,12 +1,62 @@
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::collections::HashMap;
 
-use axum::http::StatusCode;
+use aide::{
+    gen::GenContext,
+    openapi::{Operation, Response as OpenApiResponse},
+    operation::OperationOutput,
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_jsonschema::Json;
 use redis::RedisError;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+pub use world_id_bridge_client::types::{PayloadField, RequestPayload, RequestStatus};
+
+use crate::validation::InvalidPayload;
 
 pub const EXPIRE_AFTER_SECONDS: u64 = 900; // Increasing to allow partner verifications.
+pub const REQ_PREFIX: &str = "req:";
+pub const RES_PREFIX: &str = "res:";
 pub const REQ_STATUS_PREFIX: &str = "req:status:";
+/// Token of the current lease on a request, for two-phase retrieval.
+pub const REQ_LEASE_PREFIX: &str = "req:lease:";
+/// How long a leased request stays hidden from other readers before it can be
+/// fetched again, unless acknowledged first.
+pub const LEASE_SECONDS: u64 = 30;
+/// Where a request's response is delivered, if it registered a callback.
+pub const REQ_CALLBACK_PREFIX: &str = "req:callback:";
+/// The plaintext [`RequestMetadata`](crate::metadata::RequestMetadata) a
+/// request was created with, if any.
+pub const REQ_METADATA_PREFIX: &str = "req:meta:";
+/// Cached result of a request sent with an `Idempotency-Key`, per route.
+pub const IDEMPOTENCY_PREFIX: &str = "idem:";
+/// How long an in-flight idempotent request holds its key before a retry may
+/// run it again, in case the first attempt died before recording a result.
+pub const IDEMPOTENCY_PENDING_SECONDS: u64 = 30;
+/// Reads so far of a fan-out request (one created with `max_reads`).
+pub const REQ_READS_PREFIX: &str = "req:reads:";
+/// The `max_reads` of a fan-out request. Its presence marks the mode.
+pub const REQ_READ_QUOTA_PREFIX: &str = "req:maxreads:";
+/// Responses to a fan-in request (one created with `max_responses`), as a list.
+pub const RES_LIST_PREFIX: &str = "res:list:";
+/// The `max_responses` of a fan-in request. Its presence marks the mode.
+pub const RES_QUOTA_PREFIX: &str = "res:quota:";
+/// How many responses a fan-in request has accepted so far.
+pub const RES_COUNT_PREFIX: &str = "res:count:";
+
+/// Upper bound on `max_responses`. Fan-in responses are held inline in a
+/// single Redis list, so this also bounds its memory.
+pub const MAX_RESPONSES_PER_REQUEST: u32 = 32;
+
+/// Upper bound on `max_reads`.
+pub const MAX_READS_PER_REQUEST: u32 = 16;
+
+/// Maximum size of a request body, after any decompression. Larger payloads go
+/// through the chunked upload routes.
+pub const MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
 
 /// Maximum length of a `request_id`.
 ///
@@ -46,52 +96,91 @@ pub struct AppOverride {
 /// A map with app overrides, loaded from environment during startup
 pub type AppOverrides = HashMap<String, AppOverride>;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "lowercase")]
-pub enum RequestStatus {
-    /// The request has been initiated by the client
-    Initialized,
-    /// The request has been retrieved
-    Retrieved,
-    /// The request has received a response
-    Completed,
+/// Redis encoding of [`RequestPayload`].
+pub trait Stored: Sized {
+    /// Encode for storage in Redis as compact CBOR, with base64 fields held as
+    /// raw bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::INTERNAL_SERVER_ERROR`] if encoding fails.
+    fn to_stored(&self) -> Result<Vec<u8>, StatusCode>;
+
+    /// Decode a value read from Redis. Values written before the binary format
+    /// are JSON objects and are still accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::INTERNAL_SERVER_ERROR`] if the value is neither.
+    fn from_stored(bytes: &[u8]) -> Result<Self, StatusCode>;
 }
 
-impl Display for RequestStatus {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Retrieved => write!(f, "retrieved"),
-            Self::Completed => write!(f, "completed"),
-            Self::Initialized => write!(f, "initialized"),
-        }
+impl Stored for RequestPayload {
+    fn to_stored(&self) -> Result<Vec<u8>, StatusCode> {
+        let mut out = Vec::new();
+        ciborium::into_writer(self, &mut out).map_err(|e| {
+            tracing::error!("Failed to encode stored payload: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(out)
+    }
+
+    fn from_stored(bytes: &[u8]) -> Result<Self, StatusCode> {
+        let decoded = if bytes.first() == Some(&b'{') {
+            serde_json::from_slice(bytes).map_err(|e| e.to_string())
+        } else {
+            ciborium::from_reader(bytes).map_err(|e| e.to_string())
+        };
+
+        decoded.map_err(|e| {
+            tracing::error!("Failed to decode stored payload: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
     }
 }
 
-impl FromStr for RequestStatus {
-    type Err = String;
+/// Error type for handlers that can reject a request with a structured body.
+///
+/// Most failures are still a bare status code; `?` converts them via `From`.
+#[derive(Debug)]
+pub enum ApiError {
+    /// A bare status code with an empty body.
+    Status(StatusCode),
+    /// The payload failed opt-in shape validation (422).
+    InvalidPayload(InvalidPayload),
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "initialized" => Ok(Self::Initialized),
-            "retrieved" => Ok(Self::Retrieved),
-            "completed" => Ok(Self::Completed),
-            _ => Err(format!("Invalid status: {s}")),
-        }
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::Status(status)
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, JsonSchema)]
-pub struct RequestPayload {
-    /// The initialization vector for the encrypted payload
-    iv: String,
-    /// The encrypted payload
-    payload: String,
+impl From<InvalidPayload> for ApiError {
+    fn from(err: InvalidPayload) -> Self {
+        Self::InvalidPayload(err)
+    }
 }
 
-impl RequestPayload {
-    #[must_use]
-    pub const fn new(iv: String, payload: String) -> Self {
-        Self { iv, payload }
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Status(status) => status.into_response(),
+            Self::InvalidPayload(err) => err.into_response(),
+        }
+    }
+}
+
+impl OperationOutput for ApiError {
+    type Inner = Self;
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, OpenApiResponse)> {
+        Json::<InvalidPayload>::operation_response(ctx, operation)
+            .map(|res| vec![(Some(422), res)])
+            .unwrap_or_default()
     }
 }
 
@@ -177,6 +266,24 @@ mod tests {
         assert!(serde_json::from_str::<AppOverrides>(raw).is_err());
     }
 
+    #[test]
+    fn payload_fields_round_trip_through_json_and_storage() {
+        let payload = RequestPayload::new("AAAAAAAAAAAAAAAA".to_string(), "not base64".to_string());
+        assert_eq!(payload.iv().as_bytes(), Some(&[0u8; 12][..]));
+        assert!(payload.payload().as_bytes().is_none());
+
+        let stored = payload.to_stored().unwrap();
+        let restored = RequestPayload::from_stored(&stored).unwrap();
+        assert_eq!(
+            serde_json::to_value(&restored).unwrap(),
+            serde_json::json!({"iv": "AAAAAAAAAAAAAAAA", "payload": "not base64"})
+        );
+
+        // Values stored as JSON before the binary format still decode.
+        let legacy = RequestPayload::from_stored(br#"{"iv":"a","payload":"b"}"#).unwrap();
+        assert_eq!(legacy.iv(), &PayloadField::Text("a".to_string()));
+    }
+
     #[test]
     fn empty_json_object_is_valid_and_disables_feature() {
         let map: AppOverrides = serde_json::from_str("{}").expect("empty object is valid");
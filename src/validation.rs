@@ -0,0 +1,222 @@
+//! Opt-in shape validation for [`RequestPayload`].
+//!
+//! The bridge treats payloads as opaque, so by default any strings are
+//! accepted. When enabled, payloads must at least *look* like the output of the
+//! client-side AEAD: a base64 IV of a standard nonce length and a base64
+//! ciphertext within a per-route size limit. Misbehaving clients get a
+//! structured 422 instead of filling Redis with junk for the TTL.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_jsonschema::Json;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::utils::{PayloadField, RequestPayload};
+
+/// Decoded IV lengths accepted when validation is enabled: 96-bit (AES-GCM,
+/// ChaCha20-Poly1305) and 128-bit nonces.
+pub const ALLOWED_IV_LENGTHS: [usize; 2] = [12, 16];
+
+/// Which side of the exchange a payload belongs to, for per-route limits.
+#[derive(Debug, Clone, Copy)]
+pub enum PayloadKind {
+    /// Written via `POST /request` or `PUT /request/:id`.
+    Request,
+    /// Written via `PUT /response/:id` or `POST /response`.
+    Response,
+}
+
+/// Payload validation settings. The default value disables validation.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadValidation {
+    /// Whether payloads are validated at all.
+    pub enabled: bool,
+    /// Maximum decoded size of a request payload, in bytes.
+    pub max_request_bytes: Option<usize>,
+    /// Maximum decoded size of a response payload, in bytes.
+    pub max_response_bytes: Option<usize>,
+}
+
+/// Body of the 422 returned for a payload that fails validation.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct InvalidPayload {
+    /// Always `invalid_payload`.
+    error: &'static str,
+    /// The offending field (`iv` or `payload`).
+    field: &'static str,
+    /// Human-readable description of the problem.
+    reason: String,
+}
+
+impl InvalidPayload {
+    fn new(field: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            error: "invalid_payload",
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl IntoResponse for InvalidPayload {
+    fn into_response(self) -> Response {
+        (StatusCode::UNPROCESSABLE_ENTITY, Json(self)).into_response()
+    }
+}
+
+impl PayloadValidation {
+    const fn max_bytes(&self, kind: PayloadKind) -> Option<usize> {
+        match kind {
+            PayloadKind::Request => self.max_request_bytes,
+            PayloadKind::Response => self.max_response_bytes,
+        }
+    }
+
+    /// Check `payload` against the configured rules. Always passes when
+    /// validation is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidPayload`] if the IV isn't base64 of an allowed length,
+    /// the payload isn't base64, or the decoded payload exceeds the limit for
+    /// `kind`.
+    pub fn validate(
+        &self,
+        kind: PayloadKind,
+        payload: &RequestPayload,
+    ) -> Result<(), InvalidPayload> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        self.validate_iv(payload.iv())?;
+
+        // Canonical base64 is decoded to bytes on deserialization, so anything
+        // still held as text isn't valid base64.
+        let decoded = payload
+            .payload()
+            .as_bytes()
+            .ok_or_else(|| InvalidPayload::new("payload", "not valid base64"))?;
+        self.validate_size(kind, decoded.len())
+    }
+
+    /// Check just the IV, for payloads whose body arrives separately.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidPayload`] if the IV isn't base64 of an allowed length.
+    pub fn validate_iv(&self, iv: &PayloadField) -> Result<(), InvalidPayload> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let iv = iv
+            .as_bytes()
+            .ok_or_else(|| InvalidPayload::new("iv", "not valid base64"))?;
+        if !ALLOWED_IV_LENGTHS.contains(&iv.len()) {
+            return Err(InvalidPayload::new(
+                "iv",
+                format!(
+                    "decoded length {} is not one of {ALLOWED_IV_LENGTHS:?}",
+                    iv.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check a decoded payload size against the limit for `kind`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`InvalidPayload`] if `len` exceeds the configured limit.
+    pub fn validate_size(&self, kind: PayloadKind, len: usize) -> Result<(), InvalidPayload> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        match self.max_bytes(kind) {
+            Some(max) if len > max => Err(InvalidPayload::new(
+                "payload",
+                format!("decoded length {len} exceeds the limit of {max} bytes"),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    fn enabled(max: Option<usize>) -> PayloadValidation {
+        PayloadValidation {
+            enabled: true,
+            max_request_bytes: max,
+            max_response_bytes: None,
+        }
+    }
+
+    fn payload(iv: &str, payload: &str) -> RequestPayload {
+        RequestPayload::new(iv.to_string(), payload.to_string())
+    }
+
+    #[test]
+    fn disabled_accepts_anything() {
+        let validation = PayloadValidation::default();
+        assert!(validation
+            .validate(PayloadKind::Request, &payload("x", "y"))
+            .is_ok());
+    }
+
+    #[test]
+    fn checks_iv_encoding_and_length() {
+        let validation = enabled(None);
+        let twelve = STANDARD.encode([0u8; 12]);
+        let sixteen = STANDARD.encode([0u8; 16]);
+        let eight = STANDARD.encode([0u8; 8]);
+
+        assert!(validation
+            .validate(PayloadKind::Request, &payload(&twelve, "AAAA"))
+            .is_ok());
+        assert!(validation
+            .validate(PayloadKind::Request, &payload(&sixteen, "AAAA"))
+            .is_ok());
+
+        let err = validation
+            .validate(PayloadKind::Request, &payload(&eight, "AAAA"))
+            .unwrap_err();
+        assert_eq!(err.field, "iv");
+        let err = validation
+            .validate(PayloadKind::Request, &payload("not base64!", "AAAA"))
+            .unwrap_err();
+        assert_eq!(err.field, "iv");
+    }
+
+    #[test]
+    fn checks_payload_encoding_and_per_route_limit() {
+        let validation = enabled(Some(3));
+        let iv = STANDARD.encode([0u8; 12]);
+
+        let err = validation
+            .validate(PayloadKind::Request, &payload(&iv, "%%%%"))
+            .unwrap_err();
+        assert_eq!(err.field, "payload");
+
+        assert!(validation
+            .validate(PayloadKind::Request, &payload(&iv, "AAAA"))
+            .is_ok());
+        assert!(validation
+            .validate(PayloadKind::Request, &payload(&iv, "AAAAAAAA"))
+            .is_err());
+        // The limit only applies to the configured route.
+        assert!(validation
+            .validate(PayloadKind::Response, &payload(&iv, "AAAAAAAA"))
+            .is_ok());
+    }
+}
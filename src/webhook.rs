@@ -0,0 +1,419 @@
+//! Optional webhook delivery of responses to RP backends.
+//!
+//! Instead of polling `GET /response/:request_id` from its backend, an RP can
+//! create a request with a `callback_url` (and the `app_id` it is registered
+//! under). When the response arrives, the bridge POSTs the encrypted
+//! `RequestPayload` there, signed with the app's secrets in the format of
+//! [`world_id_bridge_client::webhook`], and retries with exponential backoff
+//! until the callback answers `2xx`. A delivered response is consumed as if it
+//! had been polled.
+//!
+//! Callback URLs must be on the app's allowlist, so the bridge can't be turned
+//! into a request proxy for arbitrary hosts. They must be `https` (plain `http`
+//! is only accepted outside production), and redirects are not followed, so an
+//! allowlisted host can't bounce a delivery somewhere else.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+use world_id_bridge_client::webhook::{self, REQUEST_ID_HEADER, SIGNATURE_HEADER};
+
+use crate::environment::Environment;
+use crate::redact;
+use crate::utils::{RequestPayload, RES_PREFIX};
+
+/// How long a single delivery attempt may take.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a request's response should be delivered, as stored alongside it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Callback {
+    pub app_id: String,
+    pub url: String,
+}
+
+impl Callback {
+    /// Pair the `app_id` and `callback_url` of a `POST /request` body.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::BAD_REQUEST`] for a `callback_url` without an
+    /// `app_id`.
+    pub fn from_parts(
+        app_id: Option<String>,
+        callback_url: Option<String>,
+    ) -> Result<Option<Self>, StatusCode> {
+        match (app_id, callback_url) {
+            (Some(app_id), Some(url)) => Ok(Some(Self { app_id, url })),
+            (None, Some(_)) => Err(StatusCode::BAD_REQUEST),
+            (_, None) => Ok(None),
+        }
+    }
+}
+
+/// How often, and how patiently, a delivery is retried.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first.
+    pub attempts: u32,
+    /// Wait before the first retry; doubled before each one after it.
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 6,
+            initial_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+struct App {
+    /// Every delivery is signed with each of these, so receivers can rotate.
+    secrets: Vec<Vec<u8>>,
+    callback_urls: HashSet<String>,
+}
+
+/// Secrets never show up in logs.
+impl std::fmt::Debug for App {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("App")
+            .field("secrets", &self.secrets.len())
+            .field("callback_urls", &self.callback_urls)
+            .finish()
+    }
+}
+
+#[derive(Deserialize)]
+struct AppConfig {
+    secrets: Vec<String>,
+    callback_urls: Vec<String>,
+}
+
+/// Per-app webhook secrets and callback allowlists.
+///
+/// Built once at startup from `WEBHOOK_APPS` (see [`Webhooks::from_config`]).
+/// The default value has no apps, so every `callback_url` is rejected.
+#[derive(Debug)]
+pub struct Webhooks {
+    apps: HashMap<String, App>,
+    pub retry: RetryPolicy,
+    http: reqwest::Client,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        let http = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build the webhook HTTP client");
+
+        Self {
+            apps: HashMap::new(),
+            retry: RetryPolicy::default(),
+            http,
+        }
+    }
+}
+
+impl Webhooks {
+    /// Parse the app set from its config representation: a JSON object of
+    /// `app_id → {secrets, callback_urls}`, where `secrets` lists the base64
+    /// HMAC keys (at least 32 bytes each) deliveries are signed with and
+    /// `callback_urls` the exact URLs the app may register. To rotate, add the
+    /// new secret, move receivers over, then drop the old one.
+    ///
+    /// # Errors
+    ///
+    /// Returns a description of the problem if the JSON is malformed, an app
+    /// has no secrets, a secret is not valid base64 or too short, or a callback
+    /// URL isn't `https` (or `http` outside [`Environment::Production`]).
+    pub fn from_config(raw: &str, environment: Environment) -> Result<Self, String> {
+        let allows_scheme = |scheme: &str| {
+            scheme == "https" || (scheme == "http" && environment != Environment::Production)
+        };
+
+        let config: HashMap<String, AppConfig> = serde_json::from_str(raw)
+            .map_err(|e| format!("webhook apps are not a JSON object: {e}"))?;
+
+        let mut apps = HashMap::with_capacity(config.len());
+        for (app_id, app) in config {
+            if app.secrets.is_empty() {
+                return Err(format!("webhook app {app_id} has no secrets"));
+            }
+            let secrets = app
+                .secrets
+                .iter()
+                .map(|secret| {
+                    let secret = STANDARD
+                        .decode(secret.trim())
+                        .map_err(|e| format!("webhook secret for {app_id} is not base64: {e}"))?;
+                    if secret.len() < 32 {
+                        return Err(format!(
+                            "webhook secrets for {app_id} must be at least 32 bytes"
+                        ));
+                    }
+                    Ok(secret)
+                })
+                .collect::<Result<_, String>>()?;
+            if let Some(url) = app
+                .callback_urls
+                .iter()
+                .find(|url| reqwest::Url::parse(url).map_or(true, |u| !allows_scheme(u.scheme())))
+            {
+                return Err(format!("invalid callback URL for {app_id}: {url:?}"));
+            }
+
+            apps.insert(
+                app_id,
+                App {
+                    secrets,
+                    callback_urls: app.callback_urls.into_iter().collect(),
+                },
+            );
+        }
+
+        Ok(Self {
+            apps,
+            ..Self::default()
+        })
+    }
+
+    /// Number of apps that can register callbacks.
+    #[must_use]
+    pub fn app_count(&self) -> usize {
+        self.apps.len()
+    }
+
+    /// Check that `callback` is on its app's allowlist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode::BAD_REQUEST`] if the app is unknown or the URL
+    /// isn't listed for it.
+    pub fn check(&self, callback: &Callback) -> Result<(), StatusCode> {
+        self.apps
+            .get(&callback.app_id)
+            .filter(|app| app.callback_urls.contains(&callback.url))
+            .map(drop)
+            .ok_or(StatusCode::BAD_REQUEST)
+    }
+
+    /// Deliver `payload` to `callback` in the background, retrying with
+    /// backoff, and consume the stored response once the callback accepts it.
+    /// A delivery that never succeeds leaves the response for polling.
+    pub fn deliver(
+        self: &std::sync::Arc<Self>,
+        callback: Callback,
+        request_id: String,
+        payload: &RequestPayload,
+        mut redis: ConnectionManager,
+    ) {
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!(
+                    "Failed to encode webhook body for {}: {e}",
+                    redact::id(&request_id)
+                );
+                return;
+            }
+        };
+        let webhooks = self.clone();
+
+        tokio::spawn(async move {
+            if !webhooks.send(&callback, &request_id, body).await {
+                tracing::warn!(
+                    "Giving up on webhook delivery for {}",
+                    redact::id(&request_id)
+                );
+                return;
+            }
+
+            tracing::info!(
+                "Delivered response for {} to its callback",
+                redact::id(&request_id)
+            );
+            if let Err(e) = redis
+                .del::<_, ()>(format!("{RES_PREFIX}{request_id}"))
+                .await
+            {
+                tracing::warn!(
+                    "Failed to consume delivered response for {}: {e}",
+                    redact::id(&request_id)
+                );
+            }
+        });
+    }
+
+    /// POST `body` to `callback` until it answers `2xx` or the attempts run
+    /// out. Returns whether it was delivered.
+    async fn send(&self, callback: &Callback, request_id: &str, body: Vec<u8>) -> bool {
+        let Some(app) = self.apps.get(&callback.app_id) else {
+            tracing::warn!("Webhook app {} is no longer configured", callback.app_id);
+            return false;
+        };
+        let mut backoff = self.retry.initial_backoff;
+
+        for attempt in 1..=self.retry.attempts {
+            if self
+                .attempt(app, callback, request_id, &body, attempt)
+                .await
+            {
+                return true;
+            }
+
+            if attempt < self.retry.attempts {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        false
+    }
+
+    /// Make one delivery attempt, signed as of now. Returns whether the
+    /// callback accepted it.
+    async fn attempt(
+        &self,
+        app: &App,
+        callback: &Callback,
+        request_id: &str,
+        body: &[u8],
+        attempt: u32,
+    ) -> bool {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let signature = webhook::sign(&app.secrets, request_id, body, now);
+
+        let result = self
+            .http
+            .post(&callback.url)
+            .timeout(ATTEMPT_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(REQUEST_ID_HEADER, request_id)
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.to_vec())
+            .send()
+            .await;
+
+        match result {
+            Ok(response) if response.status().is_success() => true,
+            Ok(response) => {
+                tracing::warn!(
+                    "Webhook for {} got {} (attempt {attempt})",
+                    redact::id(request_id),
+                    response.status()
+                );
+                false
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Webhook for {} failed (attempt {attempt}): {e}",
+                    redact::id(request_id)
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    fn callback(app_id: &str, url: &str) -> Callback {
+        Callback {
+            app_id: app_id.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn checks_the_allowlist() {
+        let webhooks = Webhooks::from_config(&format!(
+            r#"{{"app_a": {{"secrets": ["{SECRET}"], "callback_urls": ["https://rp.example/hook"]}}}}"#
+        ), Environment::Production)
+        .unwrap();
+
+        assert!(webhooks
+            .check(&callback("app_a", "https://rp.example/hook"))
+            .is_ok());
+        assert!(webhooks
+            .check(&callback("app_a", "https://rp.example/other"))
+            .is_err());
+        assert!(webhooks
+            .check(&callback("app_b", "https://rp.example/hook"))
+            .is_err());
+        assert!(Webhooks::default()
+            .check(&callback("app_a", "https://rp.example/hook"))
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_bad_config() {
+        assert!(Webhooks::from_config("[]", Environment::Production).is_err());
+        assert!(Webhooks::from_config(
+            r#"{"app_a": {"secrets": [], "callback_urls": []}}"#,
+            Environment::Production
+        )
+        .is_err());
+        assert!(Webhooks::from_config(
+            r#"{"app_a": {"secrets": ["c2hvcnQ="], "callback_urls": []}}"#,
+            Environment::Production
+        )
+        .is_err());
+        assert!(Webhooks::from_config(
+            &format!(
+                r#"{{"app_a": {{"secrets": ["{SECRET}"], "callback_urls": ["ftp://rp.example"]}}}}"#
+            ),
+            Environment::Production
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn requires_https_in_production() {
+        let config = |url: &str| {
+            format!(r#"{{"app_a": {{"secrets": ["{SECRET}"], "callback_urls": ["{url}"]}}}}"#)
+        };
+
+        assert!(
+            Webhooks::from_config(&config("https://rp.example/hook"), Environment::Production)
+                .is_ok()
+        );
+        assert!(
+            Webhooks::from_config(&config("http://rp.example/hook"), Environment::Production)
+                .is_err()
+        );
+        assert!(
+            Webhooks::from_config(&config("http://localhost/hook"), Environment::Staging).is_ok()
+        );
+        assert!(
+            Webhooks::from_config(&config("httpx://rp.example/hook"), Environment::Staging)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn loads_every_secret() {
+        let webhooks = Webhooks::from_config(
+            &format!(
+                r#"{{"app_a": {{"secrets": ["{SECRET}", "{SECRET}"], "callback_urls": []}}}}"#
+            ),
+            Environment::Production,
+        )
+        .unwrap();
+        assert_eq!(webhooks.apps["app_a"].secrets.len(), 2);
+    }
+}
@@ -7,13 +7,13 @@
 use std::sync::Arc;
 
 use axum::body::Body;
-use axum::http::{Method, Request};
+use axum::http::{HeaderMap, Method, Request};
 use http_body_util::BodyExt;
 use redis::aio::ConnectionManager;
 use serde_json::Value;
 use tower::ServiceExt;
-use world_id_bridge::app;
 use world_id_bridge::utils::{AppOverride, AppOverrides};
+use world_id_bridge::{app, Config};
 
 /// App-override fixture the override tests assert against. Injected directly
 /// into the router by the harness, so those tests need no `APP_URL_OVERRIDES`
@@ -34,7 +34,22 @@ fn fixture_overrides() -> AppOverrides {
     overrides
 }
 
-async fn redis_connection() -> ConnectionManager {
+/// Serve `app` on an ephemeral local port, for tests that go through a real
+/// HTTP client. Returns the base URL.
+pub async fn serve(app: axum::Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind test listener");
+    let address = listener.local_addr().expect("listener has an address");
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service())
+            .await
+            .expect("test server failed");
+    });
+    format!("http://{address}")
+}
+
+pub async fn redis_connection() -> ConnectionManager {
     let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
     let client = redis::Client::open(url).expect("REDIS_URL must be a valid Redis URL");
     ConnectionManager::new(client).await.expect(
@@ -45,7 +60,18 @@ async fn redis_connection() -> ConnectionManager {
 
 /// Build the real bridge router, wired to a local Redis and the override fixture.
 pub async fn test_app() -> axum::Router {
-    app(redis_connection().await, Arc::new(fixture_overrides()))
+    test_app_with(Config::default()).await
+}
+
+/// Like [`test_app`], but with the remaining config supplied by the test.
+pub async fn test_app_with(config: Config) -> axum::Router {
+    app(
+        redis_connection().await,
+        Config {
+            app_overrides: Arc::new(fixture_overrides()),
+            ..config
+        },
+    )
 }
 
 async fn send(
@@ -114,3 +140,39 @@ pub async fn post(app: &axum::Router, route: &str, body: &Value) -> (u16, String
 pub async fn put(app: &axum::Router, route: &str, body: &Value) -> (u16, String) {
     send(app, Method::PUT, route, Some(body)).await
 }
+
+/// Send a raw (non-JSON) body and return the status, headers, and raw body.
+pub async fn send_raw(
+    app: &axum::Router,
+    method: Method,
+    route: &str,
+    body: Option<(&str, Vec<u8>)>,
+    headers: &[(&str, &str)],
+) -> (u16, HeaderMap, Vec<u8>) {
+    let mut builder = Request::builder().uri(route).method(method);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let request = match body {
+        Some((content_type, bytes)) => builder
+            .header("Content-Type", content_type)
+            .body(Body::from(bytes)),
+        None => builder.body(Body::empty()),
+    }
+    .expect("failed to build request");
+
+    let response = app
+        .clone()
+        .oneshot(request)
+        .await
+        .expect("router service is infallible");
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let bytes = response
+        .into_body()
+        .collect()
+        .await
+        .expect("failed to read response body")
+        .to_bytes();
+    (status, headers, bytes.to_vec())
+}
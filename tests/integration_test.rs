@@ -1,5 +1,29 @@
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::Method;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use redis::AsyncCommands;
 use serde_json::{json, Value};
 use uuid::Uuid;
+use world_id_bridge::{
+    audit::{Audit, AuditEvent, IpClass},
+    compression::{Compression, RouteGroup},
+    cors::CorsPolicy,
+    envelope::StorageEncryption,
+    environment::Environment,
+    notify::{Notifier, StatusEvent},
+    validation::PayloadValidation,
+    webhook::{RetryPolicy, Webhooks},
+    Config,
+};
+use world_id_bridge_client::{
+    crypto::Key, webhook, Backoff, BridgeClient, Error as ClientError, RequestPayload,
+    RequestStatus,
+};
 
 mod common;
 
@@ -359,6 +383,77 @@ async fn test_create_request_with_duplicate_id_returns_409() {
     assert_eq!(s2, 409, "second POST with same request_id must 409");
 }
 
+#[tokio::test]
+async fn test_create_standalone_response_with_client_supplied_id() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let body = json!({"request_id": id.to_uppercase(), "iv": "x", "payload": "y"});
+
+    let (s, b) = common::post(&app, "/response", &body).await;
+    assert_eq!(s, 201, "POST /response with custom id should succeed: {b}");
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["request_id"], id, "the supplied id is lowercased");
+
+    let (s, _) = common::post(&app, "/response", &body).await;
+    assert_eq!(s, 409, "second POST with same request_id must 409");
+
+    let (s, b) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["status"], "completed");
+    assert_eq!(v["response"]["payload"], "y");
+}
+
+#[tokio::test]
+async fn test_standalone_response_id_collides_with_request() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let body = json!({"request_id": id, "iv": "x", "payload": "y"});
+
+    let (s, _) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 200);
+    let (s, _) = common::post(&app, "/response", &body).await;
+    assert_eq!(s, 409, "requests and responses share the id space");
+
+    let (s, _) = common::post(
+        &app,
+        "/response",
+        &json!({"request_id": "a/b", "iv": "x", "payload": "y"}),
+    )
+    .await;
+    assert_eq!(s, 400);
+}
+
+#[tokio::test]
+async fn test_standalone_response_cannot_overwrite_an_answered_request() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+
+    let (s, _) = common::post(
+        &app,
+        "/request",
+        &json!({"request_id": id, "iv": "x", "payload": "y"}),
+    )
+    .await;
+    assert_eq!(s, 200);
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "a", "payload": "answer"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+
+    let body = json!({"request_id": id.to_uppercase(), "iv": "b", "payload": "forged"});
+    let (s, _) = common::post(&app, "/response", &body).await;
+    assert_eq!(s, 409, "the stored response must not be replaced");
+
+    let (s, b) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["response"]["payload"], "answer");
+}
+
 // ---------------------------------------------------------------------------
 // Server-driven URL overrides. The bridge takes no `app_id` input — it returns
 // the whole `app_overrides` map blindly and the SDK picks its own entry. The
@@ -590,6 +685,2550 @@ async fn test_case_insensitive_full_round_trip() {
     assert_eq!(rv["response"]["iv"], "rt-resp-iv");
 }
 
+// ---------------------------------------------------------------------------
+// Server-side envelope encryption: stored values are sealed under a server key
+// so a Redis leak reveals neither the client ciphertext nor its length.
+// ---------------------------------------------------------------------------
+
+fn encrypted_app_config(active: &str) -> Config {
+    let keys = r#"{
+        "k1": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+        "k2": "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="
+    }"#;
+    Config {
+        storage_encryption: Arc::new(
+            StorageEncryption::from_config(keys, Some(active)).expect("valid key config"),
+        ),
+        ..Config::default()
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_storage_round_trip() {
+    let app = common::test_app_with(encrypted_app_config("k1")).await;
+    let id = fresh_id();
+    let body = json!({"request_id": id, "iv": "enc-iv", "payload": "enc-payload"});
+
+    let (s, _) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 200);
+
+    // The raw Redis value is sealed under the active key and doesn't contain
+    // the client ciphertext.
+    let mut redis = common::redis_connection().await;
+    let stored: Vec<u8> = redis.get(format!("req:{id}")).await.unwrap();
+    assert!(stored.starts_with(b"enc1:k1:"));
+    assert!(!String::from_utf8_lossy(&stored).contains("enc-payload"));
+
+    let (gs, gb) = common::get(&app, &format!("/request/{id}")).await;
+    assert_eq!(gs, 200);
+    let gv: Value = serde_json::from_str(&gb).unwrap();
+    assert_eq!(gv["iv"], "enc-iv");
+    assert_eq!(gv["payload"], "enc-payload");
+
+    let res_body = json!({"iv": "enc-resp-iv", "payload": "enc-resp-payload"});
+    let (ps, _) = common::put(&app, &format!("/response/{id}"), &res_body).await;
+    assert_eq!(ps, 201);
+
+    // A rotated deployment still reads the value sealed under the old key.
+    let rotated = common::test_app_with(encrypted_app_config("k2")).await;
+    let (rs, rb) = common::get(&rotated, &format!("/response/{id}")).await;
+    assert_eq!(rs, 200);
+    let rv: Value = serde_json::from_str(&rb).unwrap();
+    assert_eq!(rv["response"]["payload"], "enc-resp-payload");
+}
+
+// ---------------------------------------------------------------------------
+// Opt-in payload validation: base64 IV of a standard nonce length, base64
+// payload, and a per-route decoded size limit. Failures are a structured 422.
+// ---------------------------------------------------------------------------
+
+fn validating_app_config() -> Config {
+    Config {
+        payload_validation: Arc::new(PayloadValidation {
+            enabled: true,
+            max_request_bytes: Some(16),
+            max_response_bytes: None,
+        }),
+        ..Config::default()
+    }
+}
+
+#[tokio::test]
+async fn test_payload_validation_accepts_well_formed_payload() {
+    let app = common::test_app_with(validating_app_config()).await;
+    // 12-byte IV, 12-byte payload.
+    let body = json!({"iv": "AAAAAAAAAAAAAAAA", "payload": "AAAAAAAAAAAAAAAA"});
+
+    let (s, b) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 200, "well-formed payload must be accepted: {b}");
+}
+
+#[tokio::test]
+async fn test_payload_validation_rejects_bad_iv_with_422() {
+    let app = common::test_app_with(validating_app_config()).await;
+    let body = json!({"iv": "test_iv", "payload": "AAAA"});
+
+    let (s, b) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 422);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["error"], "invalid_payload");
+    assert_eq!(v["field"], "iv");
+}
+
+#[tokio::test]
+async fn test_payload_validation_applies_per_route_limit() {
+    let app = common::test_app_with(validating_app_config()).await;
+    // 24 decoded bytes: over the request limit, but responses are unlimited.
+    let body = json!({"iv": "AAAAAAAAAAAAAAAA", "payload": "A".repeat(32)});
+
+    let (s, b) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 422);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["field"], "payload");
+
+    let (s, _) = common::post(&app, "/response", &body).await;
+    assert_eq!(s, 201);
+}
+
+// ---------------------------------------------------------------------------
+// Binary payloads: content negotiation between JSON (default), CBOR, and raw
+// application/octet-stream bodies with the IV in a header.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_octet_stream_request_round_trip() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let iv = "AAECAwQFBgcICQoL"; // 12 bytes
+    let payload: Vec<u8> = (0..=255).collect();
+
+    let (s, _, b) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/octet-stream", payload.clone())),
+        &[("bridge-iv", iv), ("bridge-request-id", &id)],
+    )
+    .await;
+    assert_eq!(s, 200, "octet-stream POST /request should succeed");
+    let v: Value = serde_json::from_slice(&b).unwrap();
+    assert_eq!(v["request_id"], id);
+
+    // Stored compactly as CBOR rather than base64-in-JSON.
+    let mut redis = common::redis_connection().await;
+    let stored: Vec<u8> = redis.get(format!("req:{id}")).await.unwrap();
+    assert_ne!(stored.first(), Some(&b'{'));
+    assert!(stored.len() < payload.len() + 32);
+
+    let (gs, headers, body) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{id}"),
+        None,
+        &[("Accept", "application/octet-stream")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert_eq!(headers["content-type"], "application/octet-stream");
+    assert_eq!(headers["bridge-iv"], iv);
+    assert_eq!(body, payload);
+}
+
+#[tokio::test]
+async fn test_json_and_binary_clients_interoperate() {
+    let app = common::test_app().await;
+    let (s, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "AAECAwQFBgcICQoL", "payload": "aGVsbG8="}),
+    )
+    .await;
+    assert_eq!(s, 200);
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // A CBOR client reads the JSON-submitted request as byte strings.
+    let (gs, headers, body) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{id}"),
+        None,
+        &[("Accept", "application/cbor")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert_eq!(headers["content-type"], "application/cbor");
+    let value: ciborium::Value = ciborium::from_reader(body.as_slice()).unwrap();
+    let map = value.as_map().expect("CBOR map");
+    let payload = map
+        .iter()
+        .find(|(k, _)| k.as_text() == Some("payload"))
+        .and_then(|(_, v)| v.as_bytes())
+        .expect("payload byte string");
+    assert_eq!(payload.as_slice(), b"hello");
+
+    // ...and answers in CBOR, which the JSON client reads back as base64.
+    let mut response = Vec::new();
+    ciborium::into_writer(
+        &ciborium::Value::Map(vec![
+            (
+                "iv".into(),
+                ciborium::Value::Bytes((0..12).collect::<Vec<u8>>()),
+            ),
+            ("payload".into(), ciborium::Value::Bytes(b"world".to_vec())),
+        ]),
+        &mut response,
+    )
+    .unwrap();
+    let (ps, _, _) = common::send_raw(
+        &app,
+        Method::PUT,
+        &format!("/response/{id}"),
+        Some(("application/cbor", response)),
+        &[],
+    )
+    .await;
+    assert_eq!(ps, 201);
+
+    let (rs, rb) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(rs, 200);
+    let rv: Value = serde_json::from_str(&rb).unwrap();
+    assert_eq!(rv["response"]["iv"], "AAECAwQFBgcICQoL");
+    assert_eq!(rv["response"]["payload"], "d29ybGQ=");
+}
+
+#[tokio::test]
+async fn test_octet_stream_response_status_header() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let body = json!({"request_id": id, "iv": "x", "payload": "y"});
+    let (s, _) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 200);
+
+    let (gs, headers, body) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/response/{id}"),
+        None,
+        &[("Accept", "application/octet-stream")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert_eq!(headers["bridge-status"], "initialized");
+    assert!(body.is_empty());
+
+    // Non-base64 strings have no raw byte form, and turning the read away
+    // leaves the payload for a client that asks for JSON.
+    let octet_stream = |route: String| {
+        let app = app.clone();
+        async move {
+            common::send_raw(
+                &app,
+                Method::GET,
+                &route,
+                None,
+                &[("Accept", "application/octet-stream")],
+            )
+            .await
+            .0
+        }
+    };
+    assert_eq!(octet_stream(format!("/request/{id}")).await, 406);
+    let (rs, rb) = common::get(&app, &format!("/request/{id}")).await;
+    assert_eq!(rs, 200);
+    assert_eq!(serde_json::from_str::<Value>(&rb).unwrap()["payload"], "y");
+
+    let (ps, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "x", "payload": "z"}),
+    )
+    .await;
+    assert_eq!(ps, 201);
+    assert_eq!(octet_stream(format!("/response/{id}")).await, 406);
+    let (rs, rb) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(rs, 200);
+    let rv: Value = serde_json::from_str(&rb).unwrap();
+    assert_eq!(rv["status"], "completed");
+    assert_eq!(rv["response"]["payload"], "z");
+}
+
+#[tokio::test]
+async fn test_octet_stream_requires_iv_header() {
+    let app = common::test_app().await;
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/response",
+        Some(("application/octet-stream", b"payload".to_vec())),
+        &[],
+    )
+    .await;
+    assert_eq!(s, 400);
+}
+
+// ---------------------------------------------------------------------------
+// Negotiated compression, enabled per route group. Compressed request bodies
+// are decompressed inside the body limit, so they can't inflate past it.
+// ---------------------------------------------------------------------------
+
+fn compressing_app_config() -> Config {
+    Config {
+        compression: Compression {
+            routes: HashSet::from([RouteGroup::Response]),
+            min_size: 64,
+        },
+        ..Config::default()
+    }
+}
+
+fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn test_response_group_compresses_and_accepts_gzip() {
+    let app = common::test_app_with(compressing_app_config()).await;
+    let payload = "A".repeat(4096);
+    let body = json!({"iv": "AAECAwQFBgcICQoL", "payload": payload}).to_string();
+
+    let (s, _, b) = common::send_raw(
+        &app,
+        Method::POST,
+        "/response",
+        Some(("application/json", gzip(body.as_bytes()))),
+        &[("Content-Encoding", "gzip")],
+    )
+    .await;
+    assert_eq!(s, 201, "gzip request body should be accepted");
+    let id = serde_json::from_slice::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (gs, headers, compressed) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/response/{id}"),
+        None,
+        &[("Accept-Encoding", "gzip")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert_eq!(headers["content-encoding"], "gzip");
+
+    let mut decoded = String::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    let v: Value = serde_json::from_str(&decoded).unwrap();
+    assert_eq!(v["response"]["payload"], payload);
+}
+
+#[tokio::test]
+async fn test_compression_is_per_route_group() {
+    let app = common::test_app_with(compressing_app_config()).await;
+    let body = json!({"iv": "x", "payload": "y".repeat(4096)});
+    let (s, b) = common::post(&app, "/request", &body).await;
+    assert_eq!(s, 200);
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (gs, headers, _) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{id}"),
+        None,
+        &[("Accept-Encoding", "gzip")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert!(headers.get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn test_decompressed_body_limit_rejects_zip_bomb() {
+    let app = common::test_app_with(compressing_app_config()).await;
+    // 6 MiB once inflated, a few KiB on the wire.
+    let compressed = gzip(&vec![0u8; 6 * 1024 * 1024]);
+    assert!(compressed.len() < 64 * 1024);
+
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/response",
+        Some(("application/octet-stream", compressed)),
+        &[
+            ("Content-Encoding", "gzip"),
+            ("bridge-iv", "AAECAwQFBgcICQoL"),
+        ],
+    )
+    .await;
+    assert_eq!(s, 413);
+}
+
+// ---------------------------------------------------------------------------
+// Chunked uploads: payloads past the body limit are sent in numbered chunks,
+// finalized into a request or response, and streamed back on retrieval.
+// ---------------------------------------------------------------------------
+
+const CHUNK: usize = 64 * 1024;
+
+async fn start_upload(app: &axum::Router, body: &Value) -> (String, String) {
+    let (s, b) = common::post(app, "/upload", body).await;
+    assert_eq!(s, 201, "POST /upload should succeed: {b}");
+    let v: Value = serde_json::from_str(&b).unwrap();
+    (
+        v["upload_id"].as_str().unwrap().to_string(),
+        v["request_id"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn put_chunk(app: &axum::Router, upload_id: &str, index: usize, chunk: &[u8]) -> u16 {
+    common::send_raw(
+        app,
+        Method::PUT,
+        &format!("/upload/{upload_id}/chunk/{index}"),
+        Some(("application/octet-stream", chunk.to_vec())),
+        &[],
+    )
+    .await
+    .0
+}
+
+#[tokio::test]
+async fn test_chunked_request_upload_streams_back_in_order() {
+    let app = common::test_app().await;
+    let payload: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8).collect();
+    let (upload_id, request_id) = start_upload(
+        &app,
+        &json!({"target": "request", "iv": "AAECAwQFBgcICQoL", "size": payload.len(), "chunk_size": CHUNK}),
+    )
+    .await;
+
+    // Chunks can arrive in any order and be retried.
+    let chunks: Vec<&[u8]> = payload.chunks(CHUNK).collect();
+    assert_eq!(chunks.len(), 3);
+    for index in [2, 0, 0] {
+        assert_eq!(put_chunk(&app, &upload_id, index, chunks[index]).await, 201);
+    }
+
+    let (s, b) = common::get(&app, &format!("/upload/{upload_id}")).await;
+    assert_eq!(s, 200);
+    let progress: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(progress, json!({"chunk_count": 3, "received": [0, 2]}));
+
+    assert_eq!(put_chunk(&app, &upload_id, 1, chunks[1]).await, 201);
+    let (fs, fb) = common::post(&app, &format!("/upload/{upload_id}/finalize"), &json!({})).await;
+    assert_eq!(fs, 201);
+    assert_eq!(
+        serde_json::from_str::<Value>(&fb).unwrap()["request_id"],
+        request_id
+    );
+
+    // A finalized upload can't be changed or finalized again.
+    let forged = vec![0xff; chunks[0].len()];
+    assert_eq!(put_chunk(&app, &upload_id, 0, &forged).await, 404);
+    let (fs, _) = common::post(&app, &format!("/upload/{upload_id}/finalize"), &json!({})).await;
+    assert_eq!(fs, 404);
+
+    let (gs, gb) = common::get(&app, &format!("/request/{request_id}")).await;
+    assert_eq!(gs, 200);
+    let v: Value = serde_json::from_str(&gb).expect("streamed body is valid JSON");
+    assert_eq!(v["iv"], "AAECAwQFBgcICQoL");
+    assert_eq!(v["payload"], STANDARD.encode(&payload));
+
+    // Still single-use: the manifest and its chunks are consumed.
+    let (again, _) = common::get(&app, &format!("/request/{request_id}")).await;
+    assert_eq!(again, 404);
+    let mut redis = common::redis_connection().await;
+    let leftover: bool = redis.exists(format!("chunk:{upload_id}:0")).await.unwrap();
+    assert!(!leftover);
+}
+
+#[tokio::test]
+async fn test_chunked_response_upload_streams_as_cbor() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let (s, _) = common::post(
+        &app,
+        "/request",
+        &json!({"request_id": id, "iv": "x", "payload": "y"}),
+    )
+    .await;
+    assert_eq!(s, 200);
+
+    let payload: Vec<u8> = (0..100_000u32).map(|i| (i % 7) as u8).collect();
+    let (upload_id, _) = start_upload(
+        &app,
+        &json!({"target": "response", "request_id": id, "iv": "AAECAwQFBgcICQoL", "size": payload.len(), "chunk_size": CHUNK}),
+    )
+    .await;
+    for (index, chunk) in payload.chunks(CHUNK).enumerate() {
+        assert_eq!(put_chunk(&app, &upload_id, index, chunk).await, 201);
+    }
+    let (fs, _) = common::post(&app, &format!("/upload/{upload_id}/finalize"), &json!({})).await;
+    assert_eq!(fs, 201);
+
+    let (gs, headers, body) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/response/{id}"),
+        None,
+        &[("Accept", "application/cbor")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert_eq!(headers["content-type"], "application/cbor");
+    let value: ciborium::Value = ciborium::from_reader(body.as_slice()).unwrap();
+    let map = value.as_map().expect("CBOR map");
+    assert_eq!(map[0].1.as_text(), Some("completed"));
+    let response = map[1].1.as_map().expect("response map");
+    assert_eq!(response[1].1.as_bytes().unwrap(), &payload);
+
+    // The status was cleared on finalize, like PUT /response.
+    let (hs, _, _) =
+        common::send_raw(&app, Method::HEAD, &format!("/response/{id}"), None, &[]).await;
+    assert_eq!(hs, 404);
+}
+
+#[tokio::test]
+async fn test_failed_finalize_deletes_the_chunks() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let (s, _) = common::post(
+        &app,
+        "/request",
+        &json!({"request_id": id, "iv": "x", "payload": "y"}),
+    )
+    .await;
+    assert_eq!(s, 200);
+
+    let payload = vec![3u8; 2 * CHUNK];
+    let mut uploads = Vec::new();
+    for body in [
+        json!({"target": "response", "request_id": id, "iv": "AAECAwQFBgcICQoL", "size": payload.len(), "chunk_size": CHUNK}),
+        json!({"target": "request", "request_id": id, "iv": "AAECAwQFBgcICQoL", "size": payload.len(), "chunk_size": CHUNK}),
+    ] {
+        let (upload_id, _) = start_upload(&app, &body).await;
+        for (index, chunk) in payload.chunks(CHUNK).enumerate() {
+            assert_eq!(put_chunk(&app, &upload_id, index, chunk).await, 201);
+        }
+        uploads.push(upload_id);
+    }
+
+    // The request is answered while the response upload is in flight, and its
+    // ID was taken before the request upload started.
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "a", "payload": "b"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+
+    let mut redis = common::redis_connection().await;
+    for (upload_id, expected) in uploads.iter().zip([400, 409]) {
+        let route = format!("/upload/{upload_id}/finalize");
+        let (s, _) = common::post(&app, &route, &json!({})).await;
+        assert_eq!(s, expected);
+
+        let chunks = [
+            format!("chunk:{upload_id}:0"),
+            format!("chunk:{upload_id}:1"),
+        ];
+        let mut left = 0;
+        for _ in 0..20 {
+            left = redis.exists::<_, u32>(&chunks).await.unwrap();
+            if left == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(left, 0, "chunks of a failed finalize are deleted");
+        let (s, _) = common::post(&app, &route, &json!({})).await;
+        assert_eq!(s, 404);
+    }
+}
+
+#[tokio::test]
+async fn test_chunked_upload_rejects_bad_chunks_and_incomplete_finalize() {
+    let app = common::test_app().await;
+    let size = CHUNK + 10;
+    let (upload_id, _) = start_upload(
+        &app,
+        &json!({"target": "request", "iv": "x", "size": size, "chunk_size": CHUNK}),
+    )
+    .await;
+
+    // Only the last chunk may be short, and only by the right amount.
+    assert_eq!(put_chunk(&app, &upload_id, 0, &[0; 10]).await, 400);
+    assert_eq!(put_chunk(&app, &upload_id, 1, &[0; 11]).await, 400);
+    assert_eq!(put_chunk(&app, &upload_id, 2, &[0; 10]).await, 400);
+    assert_eq!(put_chunk(&app, &upload_id, 1, &[0; 10]).await, 201);
+
+    let (fs, _) = common::post(&app, &format!("/upload/{upload_id}/finalize"), &json!({})).await;
+    assert_eq!(fs, 400, "finalize must wait for every chunk");
+
+    // Tiny chunks would fan out into too many keys; responses need a target.
+    let (s, _) = common::post(
+        &app,
+        "/upload",
+        &json!({"target": "request", "iv": "x", "size": size, "chunk_size": 1024}),
+    )
+    .await;
+    assert_eq!(s, 400);
+    let (s, _) = common::post(
+        &app,
+        "/upload",
+        &json!({"target": "response", "iv": "x", "size": 10, "chunk_size": 10}),
+    )
+    .await;
+    assert_eq!(s, 400);
+}
+
+// ---------------------------------------------------------------------------
+// Streaming passthrough: large octet-stream bodies are spooled into chunks as
+// they arrive rather than buffered whole, and streamed back out on read.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_large_octet_stream_body_is_spooled_into_chunks() {
+    let app = common::test_app().await;
+    let id = fresh_id();
+    let iv = "AAECAwQFBgcICQoL";
+    let payload: Vec<u8> = (0..600_000u32).map(|i| (i % 253) as u8).collect();
+
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/octet-stream", payload.clone())),
+        &[("bridge-iv", iv), ("bridge-request-id", &id)],
+    )
+    .await;
+    assert_eq!(s, 200);
+
+    // Only a small manifest sits under the request key.
+    let mut redis = common::redis_connection().await;
+    let stored: Vec<u8> = redis.get(format!("req:{id}")).await.unwrap();
+    assert!(stored.starts_with(b"chunks:"));
+    assert!(stored.len() < 256);
+
+    let (gs, headers, body) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{id}"),
+        None,
+        &[("Accept", "application/octet-stream")],
+    )
+    .await;
+    assert_eq!(gs, 200);
+    assert_eq!(headers["bridge-iv"], iv);
+    assert_eq!(body, payload);
+
+    // Responses stream the same way, and read back as JSON too.
+    let (ps, _, _) = common::send_raw(
+        &app,
+        Method::PUT,
+        &format!("/response/{id}"),
+        Some(("application/octet-stream", payload.clone())),
+        &[("bridge-iv", iv)],
+    )
+    .await;
+    assert_eq!(ps, 201);
+
+    let (rs, rb) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(rs, 200);
+    let rv: Value = serde_json::from_str(&rb).unwrap();
+    assert_eq!(rv["status"], "completed");
+    assert_eq!(rv["response"]["payload"], STANDARD.encode(&payload));
+}
+
+#[tokio::test]
+async fn test_spooled_body_respects_validation_limits() {
+    let app = common::test_app_with(Config {
+        payload_validation: Arc::new(PayloadValidation {
+            enabled: true,
+            max_request_bytes: Some(300_000),
+            max_response_bytes: None,
+        }),
+        ..Config::default()
+    })
+    .await;
+
+    let (s, _, body) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/octet-stream", vec![0; 600_000])),
+        &[("bridge-iv", "AAECAwQFBgcICQoL")],
+    )
+    .await;
+    assert_eq!(s, 422);
+    let v: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["field"], "payload");
+}
+
+/// A body made of `marker`, large enough to be spooled into several chunks.
+fn marked_body(marker: &str) -> Vec<u8> {
+    marker.bytes().cycle().take(600_000).collect()
+}
+
+/// How many spooled chunks hold a piece of a [`marked_body`], waiting a little
+/// for chunks deleted in the background to go.
+async fn chunks_containing(marker: &str) -> usize {
+    let mut redis = common::redis_connection().await;
+    let mut found = 0;
+    for _ in 0..20 {
+        let keys: Vec<String> = redis.keys("chunk:*").await.unwrap();
+        found = 0;
+        for key in keys.iter().filter(|key| key.starts_with("chunk:")) {
+            let value: Option<Vec<u8>> = redis.get(key).await.unwrap_or_default();
+            if value.is_some_and(|v| v.windows(marker.len()).any(|w| w == marker.as_bytes())) {
+                found += 1;
+            }
+        }
+        if found == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    found
+}
+
+#[tokio::test]
+async fn test_octet_stream_is_checked_before_spooling() {
+    let app = common::test_app().await;
+    let marker = fresh_id();
+
+    // No pending request to answer.
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::PUT,
+        &format!("/response/{}", fresh_id()),
+        Some(("application/octet-stream", marked_body(&marker))),
+        &[("bridge-iv", "AAECAwQFBgcICQoL")],
+    )
+    .await;
+    assert_eq!(s, 400);
+
+    // A malformed path or `bridge-request-id`.
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::PUT,
+        "/response/not:an:id",
+        Some(("application/octet-stream", marked_body(&marker))),
+        &[("bridge-iv", "AAECAwQFBgcICQoL")],
+    )
+    .await;
+    assert_eq!(s, 400);
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/octet-stream", marked_body(&marker))),
+        &[
+            ("bridge-iv", "AAECAwQFBgcICQoL"),
+            ("bridge-request-id", "a:b"),
+        ],
+    )
+    .await;
+    assert_eq!(s, 400);
+
+    assert_eq!(chunks_containing(&marker).await, 0);
+}
+
+#[tokio::test]
+async fn test_rejected_spooled_bodies_leave_no_chunks() {
+    let app = common::test_app_with(Config {
+        payload_validation: Arc::new(PayloadValidation {
+            enabled: true,
+            max_request_bytes: Some(300_000),
+            max_response_bytes: None,
+        }),
+        ..Config::default()
+    })
+    .await;
+    let marker = fresh_id();
+
+    // Cut off at the limit, after the first chunk was written.
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/octet-stream", marked_body(&marker))),
+        &[("bridge-iv", "AAECAwQFBgcICQoL")],
+    )
+    .await;
+    assert_eq!(s, 422);
+    assert_eq!(chunks_containing(&marker).await, 0);
+
+    // Rejected by the handler once fully spooled.
+    let id = fresh_id();
+    let (s, _) = common::post(
+        &app,
+        "/response",
+        &json!({"iv": "AAECAwQFBgcICQoL", "payload": "cGF5bG9hZA==", "request_id": id}),
+    )
+    .await;
+    assert_eq!(s, 201);
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/response",
+        Some(("application/octet-stream", marked_body(&marker))),
+        &[
+            ("bridge-iv", "AAECAwQFBgcICQoL"),
+            ("bridge-request-id", &id),
+        ],
+    )
+    .await;
+    assert_eq!(s, 409);
+    assert_eq!(chunks_containing(&marker).await, 0);
+
+    // Stored bodies keep theirs.
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/response",
+        Some(("application/octet-stream", marked_body(&marker))),
+        &[("bridge-iv", "AAECAwQFBgcICQoL")],
+    )
+    .await;
+    assert_eq!(s, 201);
+    assert_eq!(chunks_containing(&marker).await, 3);
+}
+
+// ---------------------------------------------------------------------------
+// The Rust client crate, run against a real listener.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_client_full_flow() {
+    let client = BridgeClient::new(common::serve(common::test_app().await).await);
+    let key = Key::generate().unwrap();
+    let request = key.encrypt(b"request").unwrap();
+
+    // With the HKDF-derived ID, the key alone is enough to find the request.
+    let request_id = client
+        .create_request(&request, Some(&key.request_id()))
+        .await
+        .unwrap();
+    assert_eq!(request_id, key.request_id());
+    let fetched = client.fetch_request(&request_id).await.unwrap();
+    assert_eq!(key.decrypt(&fetched).unwrap(), b"request");
+    assert!(matches!(
+        client.fetch_request(&request_id).await,
+        Err(ClientError::NotFound)
+    ));
+
+    let pending = client.response_status(&request_id).await.unwrap();
+    assert_eq!(pending.status, RequestStatus::Retrieved);
+
+    let response = key.encrypt(b"response").unwrap();
+    let waiter = {
+        let client = client.clone();
+        let request_id = request_id.clone();
+        tokio::spawn(async move { client.await_response(&request_id).await })
+    };
+    client
+        .submit_response(&request_id, &response)
+        .await
+        .unwrap();
+    let received = waiter.await.unwrap().unwrap();
+    assert_eq!(key.decrypt(&received).unwrap(), b"response");
+
+    // Once the response is consumed the request is gone.
+    assert!(matches!(
+        client.submit_response(&request_id, &response).await,
+        Err(ClientError::Status { status, .. }) if status == 400
+    ));
+}
+
+#[tokio::test]
+async fn test_client_await_response_times_out_and_reports_conflicts() {
+    let client =
+        BridgeClient::new(common::serve(common::test_app().await).await).with_backoff(Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+            timeout: Duration::from_millis(100),
+        });
+    let id = fresh_id();
+    let request = RequestPayload::new("x".to_string(), "y".to_string());
+
+    assert_eq!(
+        client.create_request(&request, Some(&id)).await.unwrap(),
+        id
+    );
+    assert!(matches!(
+        client.create_request(&request, Some(&id)).await,
+        Err(ClientError::Conflict)
+    ));
+    assert!(matches!(
+        client.await_response(&id).await,
+        Err(ClientError::Timeout)
+    ));
+}
+
+// ---------------------------------------------------------------------------
+// Versioned routes. `/v1` aliases the unversioned paths; `/v2` returns every
+// field unconditionally instead of gating them behind opt-in flags.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_v1_aliases_unversioned_routes() {
+    let app = common::test_app().await;
+    let body = json!({"iv": "x", "payload": "y"});
+
+    let (s, b) = common::post(&app, "/v1/request", &body).await;
+    assert_eq!(s, 200, "POST /v1/request should succeed: {b}");
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert!(v.get("app_overrides").is_none(), "v1 keeps the opt-in: {b}");
+    let id = v["request_id"].as_str().unwrap();
+
+    // Same storage behind both paths.
+    let (s, b) = common::get(&app, &format!("/request/{id}")).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["payload"], "y");
+    assert!(v.get("idkit_flow_id").is_none());
+
+    let (s, _) = common::put(&app, &format!("/v1/response/{id}"), &body).await;
+    assert_eq!(s, 201);
+    let (s, b) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(s, 200);
+    assert_eq!(
+        serde_json::from_str::<Value>(&b).unwrap()["status"],
+        "completed"
+    );
+}
+
+#[tokio::test]
+async fn test_v2_returns_fields_without_opt_in() {
+    let app = common::test_app().await;
+
+    let (s, b) = common::post(&app, "/v2/request", &json!({"iv": "x", "payload": "y"})).await;
+    assert_eq!(s, 200, "POST /v2/request should succeed: {b}");
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert!(
+        v["app_overrides"].get(common::FIXTURE_APP_ID).is_some(),
+        "v2 always returns the override map: {b}"
+    );
+    let id = v["request_id"].as_str().unwrap();
+
+    let (s, b) = common::get(&app, &format!("/v2/request/{id}")).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["iv"], "x");
+    assert!(v["idkit_flow_id"]
+        .as_str()
+        .is_some_and(|id| id.starts_with("idkitflow_")));
+
+    let (s, _) = common::put(
+        &app,
+        &format!("/v2/response/{id}"),
+        &json!({"iv": "a", "payload": "b"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+    let (s, b) = common::get(&app, &format!("/v2/response/{id}")).await;
+    assert_eq!(s, 200);
+    assert_eq!(
+        serde_json::from_str::<Value>(&b).unwrap()["response"]["payload"],
+        "b"
+    );
+}
+
+#[tokio::test]
+async fn test_openapi_documents_both_versions() {
+    let app = common::test_app().await;
+    let (_, body) = common::get(&app, "/openapi.json").await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+
+    let paths = json["paths"].as_object().unwrap();
+    for path in [
+        "/v1/request",
+        "/v2/request",
+        "/v1/response/{request_id}",
+        "/v2/response/{request_id}",
+    ] {
+        assert!(paths.contains_key(path), "missing {path}");
+    }
+    assert!(
+        !paths.contains_key("/request"),
+        "unversioned aliases are undocumented"
+    );
+
+    let schemas = json["components"]["schemas"].as_object().unwrap();
+    assert!(schemas.contains_key("CreateRequestBody"));
+    assert!(schemas.contains_key("CreateRequestBodyV2"));
+}
+
+// ---------------------------------------------------------------------------
+// Capability negotiation. `bridge-capabilities` replaces the per-feature
+// opt-ins, and the bridge echoes the capabilities it applied.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_capabilities_header_opts_in_and_is_echoed() {
+    let app = common::test_app().await;
+    let body = json!({"iv": "x", "payload": "y"}).to_string().into_bytes();
+
+    let (s, headers, b) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/json", body.clone())),
+        &[("bridge-capabilities", "app-overrides, some-future-feature")],
+    )
+    .await;
+    assert_eq!(s, 200);
+    assert_eq!(headers["bridge-capabilities"], "app-overrides");
+    let v: Value = serde_json::from_slice(&b).unwrap();
+    assert!(v["app_overrides"].get(common::FIXTURE_APP_ID).is_some());
+    let id = v["request_id"].as_str().unwrap();
+
+    let (s, headers, b) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{id}"),
+        None,
+        &[("bridge-capabilities", "IDKIT-FLOW-ID")],
+    )
+    .await;
+    assert_eq!(s, 200);
+    assert_eq!(headers["bridge-capabilities"], "idkit-flow-id");
+    let v: Value = serde_json::from_slice(&b).unwrap();
+    assert!(v["idkit_flow_id"].as_str().is_some());
+
+    // Without the header nothing is applied, and the echo says so.
+    let (s, headers, b) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/json", body)),
+        &[],
+    )
+    .await;
+    assert_eq!(s, 200);
+    assert_eq!(headers["bridge-capabilities"], "");
+    let v: Value = serde_json::from_slice(&b).unwrap();
+    assert!(v.get("app_overrides").is_none());
+}
+
+#[tokio::test]
+async fn test_legacy_opt_ins_are_echoed_as_capabilities() {
+    let app = common::test_app().await;
+    let body = json!({"iv": "x", "payload": "y", "supports_app_overrides": true});
+
+    let (s, headers, b) = common::send_raw(
+        &app,
+        Method::POST,
+        "/request",
+        Some(("application/json", body.to_string().into_bytes())),
+        &[],
+    )
+    .await;
+    assert_eq!(s, 200);
+    assert_eq!(headers["bridge-capabilities"], "app-overrides");
+    let id = serde_json::from_slice::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (s, headers, _) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{id}"),
+        None,
+        &[("accept-idkit-flow-id", "true")],
+    )
+    .await;
+    assert_eq!(s, 200);
+    assert_eq!(headers["bridge-capabilities"], "idkit-flow-id");
+}
+
+// ---------------------------------------------------------------------------
+// Fan-in: a request created with `max_responses` collects several responses,
+// and each poll drains whatever has arrived since the last one.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_fan_in_collects_and_drains_responses() {
+    let app = common::test_app().await;
+    let (s, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "x", "payload": "y", "max_responses": 3}),
+    )
+    .await;
+    assert_eq!(s, 200, "{b}");
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let route = format!("/response/{id}");
+
+    for n in ["1", "2"] {
+        let (s, _) = common::put(&app, &route, &json!({"iv": "a", "payload": n})).await;
+        assert_eq!(s, 201);
+    }
+    let (s, b) = common::get(&app, &route).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["status"], "initialized");
+    assert_eq!(
+        v["responses"],
+        json!([{"iv": "a", "payload": "1"}, {"iv": "a", "payload": "2"}])
+    );
+
+    // Nothing new since the last poll.
+    let (_, b) = common::get(&app, &route).await;
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["status"], "initialized");
+    assert!(v.get("responses").is_none());
+
+    let (s, _) = common::put(&app, &route, &json!({"iv": "a", "payload": "3"})).await;
+    assert_eq!(s, 201);
+    let (s, _) = common::put(&app, &route, &json!({"iv": "a", "payload": "4"})).await;
+    assert_eq!(s, 409, "responses past the quota are rejected");
+    let mut redis = common::redis_connection().await;
+    let count: u32 = redis.get(format!("res:count:{id}")).await.unwrap();
+    assert_eq!(count, 3, "rejected responses don't take a slot");
+
+    let (_, b) = common::get(&app, &route).await;
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["status"], "completed");
+    assert_eq!(v["responses"], json!([{"iv": "a", "payload": "3"}]));
+
+    let (s, _) = common::get(&app, &route).await;
+    assert_eq!(s, 404, "a drained, completed request is gone");
+}
+
+#[tokio::test]
+async fn test_fan_in_octet_stream_polls_keep_the_responses() {
+    let app = common::test_app().await;
+    let (s, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "x", "payload": "y", "max_responses": 2}),
+    )
+    .await;
+    assert_eq!(s, 200, "{b}");
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let route = format!("/response/{id}");
+
+    let (s, _) = common::put(&app, &route, &json!({"iv": "a", "payload": "1"})).await;
+    assert_eq!(s, 201);
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::GET,
+        &route,
+        None,
+        &[("Accept", "application/octet-stream")],
+    )
+    .await;
+    assert_eq!(s, 406);
+
+    let (s, b) = common::get(&app, &route).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["responses"], json!([{"iv": "a", "payload": "1"}]));
+}
+
+#[tokio::test]
+async fn test_fan_in_rejects_bad_quota_and_large_responses() {
+    let app = common::test_app().await;
+    for max in [0, 33] {
+        let body = json!({"iv": "x", "payload": "y", "max_responses": max});
+        let (s, _) = common::post(&app, "/request", &body).await;
+        assert_eq!(s, 400, "max_responses {max}");
+    }
+
+    let (_, b) = common::post(
+        &app,
+        "/v2/request",
+        &json!({"iv": "x", "payload": "y", "max_responses": 2}),
+    )
+    .await;
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (s, _) = common::post(
+        &app,
+        "/upload",
+        &json!({"target": "response", "request_id": id, "iv": "x", "size": 10, "chunk_size": 10}),
+    )
+    .await;
+    assert_eq!(s, 413, "fan-in responses must be inline");
+}
+
+// ---------------------------------------------------------------------------
+// Fan-out: a request created with `max_reads` can be read that many times,
+// after which reads get 410 and the read count shows up on the response.
+// ---------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_fan_out_allows_bounded_reads() {
+    let app = common::test_app().await;
+    let (s, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "x", "payload": "y", "max_reads": 2}),
+    )
+    .await;
+    assert_eq!(s, 200, "{b}");
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let route = format!("/request/{id}");
+
+    for _ in 0..2 {
+        let (s, b) = common::get(&app, &route).await;
+        assert_eq!(s, 200);
+        assert_eq!(serde_json::from_str::<Value>(&b).unwrap()["payload"], "y");
+    }
+    let (s, _) = common::get(&app, &route).await;
+    assert_eq!(
+        s, 410,
+        "reads past the limit are distinguishable from a missing request"
+    );
+    let (s, _) = common::get(&app, &route).await;
+    assert_eq!(s, 410);
+
+    // Reads of a request that is gone aren't counted.
+    let (_, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "x", "payload": "y", "max_reads": 2}),
+    )
+    .await;
+    let gone = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let mut redis = common::redis_connection().await;
+    let _: () = redis.del(format!("req:{gone}")).await.unwrap();
+    assert_eq!(common::get(&app, &format!("/request/{gone}")).await.0, 404);
+    let reads: Option<u32> = redis.get(format!("req:reads:{gone}")).await.unwrap();
+    assert_eq!(reads, Some(0));
+
+    let (s, b) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(s, 200);
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["status"], "retrieved");
+    assert_eq!(v["reads"], 2, "rejected reads aren't counted: {b}");
+
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "a", "payload": "b"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+    let (_, b) = common::get(&app, &format!("/response/{id}")).await;
+    let v: Value = serde_json::from_str(&b).unwrap();
+    assert_eq!(v["status"], "completed");
+    assert_eq!(v["reads"], 2);
+}
+
+#[tokio::test]
+async fn test_fan_out_rejects_bad_limits() {
+    let app = common::test_app().await;
+    for max in [0, 17] {
+        let body = json!({"iv": "x", "payload": "y", "max_reads": max});
+        let (s, _) = common::post(&app, "/v2/request", &body).await;
+        assert_eq!(s, 400, "max_reads {max}");
+    }
+
+    // Regular requests keep their single-read semantics and omit `reads`.
+    let (_, b) = common::post(&app, "/request", &json!({"iv": "x", "payload": "y"})).await;
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(common::get(&app, &format!("/request/{id}")).await.0, 200);
+    assert_eq!(common::get(&app, &format!("/request/{id}")).await.0, 404);
+    let (_, b) = common::get(&app, &format!("/response/{id}")).await;
+    assert!(serde_json::from_str::<Value>(&b)
+        .unwrap()
+        .get("reads")
+        .is_none());
+}
+
+// ---------------------------------------------------------------------------
+// Two-phase retrieval. With the `lease` capability, `GET /request/:id` leases
+// the payload instead of deleting it, and `POST /request/:id/ack` deletes it.
+// ---------------------------------------------------------------------------
+
+async fn leased_get(app: &axum::Router, route: &str) -> (u16, Value) {
+    let (s, headers, b) = common::send_raw(
+        app,
+        Method::GET,
+        route,
+        None,
+        &[("bridge-capabilities", "lease")],
+    )
+    .await;
+    if s == 200 {
+        let applied = headers["bridge-capabilities"].to_str().unwrap();
+        assert!(applied.split(", ").any(|c| c == "lease"), "{applied}");
+    }
+    (s, serde_json::from_slice(&b).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_lease_and_ack_request() {
+    let app = common::test_app().await;
+    let (_, b) = common::post(&app, "/request", &json!({"iv": "x", "payload": "y"})).await;
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let route = format!("/request/{id}");
+
+    let (s, v) = leased_get(&app, &route).await;
+    assert_eq!(s, 200);
+    assert_eq!(v["payload"], "y");
+    let token = v["lease_token"].as_str().unwrap().to_string();
+
+    let (s, _) = leased_get(&app, &route).await;
+    assert_eq!(s, 409, "the payload is hidden while leased");
+
+    let (s, _) = common::post(
+        &app,
+        &format!("{route}/ack"),
+        &json!({"lease_token": "nope"}),
+    )
+    .await;
+    assert_eq!(s, 409);
+    let (s, _) = common::post(
+        &app,
+        &format!("{route}/ack"),
+        &json!({"lease_token": token}),
+    )
+    .await;
+    assert_eq!(s, 200);
+
+    assert_eq!(
+        common::get(&app, &route).await.0,
+        404,
+        "acknowledged requests are gone"
+    );
+    let (s, _) = common::post(
+        &app,
+        &format!("{route}/ack"),
+        &json!({"lease_token": token}),
+    )
+    .await;
+    assert_eq!(s, 409);
+}
+
+#[tokio::test]
+async fn test_unacknowledged_lease_expires() {
+    let app = common::test_app().await;
+    let (_, b) = common::post(&app, "/v2/request", &json!({"iv": "x", "payload": "y"})).await;
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let route = format!("/v2/request/{id}");
+
+    let (s, first) = leased_get(&app, &route).await;
+    assert_eq!(s, 200);
+    assert!(first["idkit_flow_id"].is_string());
+
+    // Simulate the lease running out without an ack.
+    let mut redis = common::redis_connection().await;
+    let _: () = redis.del(format!("req:lease:{id}")).await.unwrap();
+
+    let (s, second) = leased_get(&app, &route).await;
+    assert_eq!(s, 200, "the payload is fetchable again");
+    assert_eq!(second["payload"], "y");
+    assert_ne!(first["lease_token"], second["lease_token"]);
+
+    let (s, _) = common::post(
+        &app,
+        &format!("{route}/ack"),
+        &json!({"lease_token": first["lease_token"]}),
+    )
+    .await;
+    assert_eq!(s, 409, "a stale token can't acknowledge");
+    let (s, _) = common::post(
+        &app,
+        &format!("{route}/ack"),
+        &json!({"lease_token": second["lease_token"]}),
+    )
+    .await;
+    assert_eq!(s, 200);
+}
+
+#[tokio::test]
+async fn test_leases_only_hold_off_leased_reads() {
+    let notifications = Arc::new(Notifier::local());
+    let mut events = notifications.subscribe();
+    let app = common::test_app_with(Config {
+        notifications,
+        ..Config::default()
+    })
+    .await;
+    let id = create_request(&app).await;
+    let route = format!("/request/{id}");
+
+    let (s, first) = leased_get(&app, &route).await;
+    assert_eq!(s, 200);
+    let mut redis = common::redis_connection().await;
+    let _: () = redis.del(format!("req:lease:{id}")).await.unwrap();
+    let (s, second) = leased_get(&app, &route).await;
+    assert_eq!(s, 200);
+
+    // Clients that don't lease consume the request as before.
+    let (s, b) = common::get(&app, &route).await;
+    assert_eq!(s, 200);
+    assert_eq!(serde_json::from_str::<Value>(&b).unwrap()["payload"], "b");
+    let (s, _) = common::post(
+        &app,
+        &format!("{route}/ack"),
+        &json!({"lease_token": second["lease_token"]}),
+    )
+    .await;
+    assert_eq!(s, 404);
+    assert_ne!(first["lease_token"], second["lease_token"]);
+
+    // Retrieved once, however many times the request was leased or read.
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "a", "payload": "b"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+    assert_eq!(
+        next_status(&mut events, &id).await,
+        RequestStatus::Initialized
+    );
+    assert_eq!(
+        next_status(&mut events, &id).await,
+        RequestStatus::Retrieved
+    );
+    assert_eq!(
+        next_status(&mut events, &id).await,
+        RequestStatus::Completed
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Idempotency keys. A retry of `POST /request` or `POST /response` under the
+// same `Idempotency-Key` replays the first result; a different body gets 422.
+// ---------------------------------------------------------------------------
+
+async fn post_idempotent(app: &axum::Router, route: &str, key: &str, body: &Value) -> (u16, Value) {
+    let (s, _, b) = common::send_raw(
+        app,
+        Method::POST,
+        route,
+        Some(("application/json", serde_json::to_vec(body).unwrap())),
+        &[("idempotency-key", key)],
+    )
+    .await;
+    (s, serde_json::from_slice(&b).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_idempotent_create_request() {
+    let app = common::test_app().await;
+    let key = Uuid::new_v4().to_string();
+    let body = json!({"iv": "x", "payload": "y", "max_reads": 2});
+
+    let (s, first) = post_idempotent(&app, "/request", &key, &body).await;
+    assert_eq!(s, 200);
+    let (s, retry) = post_idempotent(&app, "/v1/request", &key, &body).await;
+    assert_eq!(s, 200);
+    assert_eq!(first, retry, "the retry replays the first result");
+
+    let (s, _) = post_idempotent(&app, "/request", &key, &json!({"iv": "x", "payload": "z"})).await;
+    assert_eq!(s, 422, "a different body under the same key");
+
+    let (s, v2) = post_idempotent(&app, "/v2/request", &key, &body).await;
+    assert_eq!(s, 200);
+    assert_ne!(v2["request_id"], first["request_id"], "keys are per route");
+
+    // Only one request was created, and it is readable as usual.
+    let route = format!("/request/{}", first["request_id"].as_str().unwrap());
+    assert_eq!(common::get(&app, &route).await.0, 200);
+    assert_eq!(common::get(&app, &route).await.0, 200);
+    assert_eq!(common::get(&app, &route).await.0, 410);
+}
+
+#[tokio::test]
+async fn test_idempotent_retry_after_failure() {
+    let app = common::test_app().await;
+    let key = Uuid::new_v4().to_string();
+
+    let (s, _) = post_idempotent(
+        &app,
+        "/request",
+        &key,
+        &json!({"iv": "x", "payload": "y", "max_reads": 0}),
+    )
+    .await;
+    assert_eq!(s, 400);
+    let (s, created) =
+        post_idempotent(&app, "/request", &key, &json!({"iv": "x", "payload": "y"})).await;
+    assert_eq!(s, 200, "a failed attempt releases the key");
+    assert!(created["request_id"].is_string());
+
+    let (s, _) = post_idempotent(
+        &app,
+        "/request",
+        "has space",
+        &json!({"iv": "x", "payload": "y"}),
+    )
+    .await;
+    assert_eq!(s, 400);
+}
+
+#[tokio::test]
+async fn test_idempotent_standalone_response() {
+    let app = common::test_app().await;
+    let key = Uuid::new_v4().to_string();
+    let body = json!({"iv": "x", "payload": "y"});
+
+    let (s, first) = post_idempotent(&app, "/response", &key, &body).await;
+    assert_eq!(s, 201);
+    let (s, retry) = post_idempotent(&app, "/response", &key, &body).await;
+    assert_eq!(s, 201);
+    assert_eq!(first, retry);
+
+    let (s, _) =
+        post_idempotent(&app, "/response", &key, &json!({"iv": "x", "payload": "z"})).await;
+    assert_eq!(s, 422);
+
+    let (s, b) = common::get(
+        &app,
+        &format!("/response/{}", first["request_id"].as_str().unwrap()),
+    )
+    .await;
+    assert_eq!(s, 200);
+    assert_eq!(
+        serde_json::from_str::<Value>(&b).unwrap()["response"]["payload"],
+        "y"
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Webhook delivery. A request created with an allowlisted `callback_url` has
+// its response POSTed there, signed and retried, instead of waiting for a poll.
+// A local receiver stands in for the RP backend.
+// ---------------------------------------------------------------------------
+
+const WEBHOOK_APP_ID: &str = "app_webhook_fixture";
+const WEBHOOK_SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+/// Serve a receiver that fails the first `failures` deliveries with a 500,
+/// and return its callback URL along with every delivery it receives.
+async fn webhook_receiver(
+    failures: u32,
+) -> (
+    String,
+    tokio::sync::mpsc::UnboundedReceiver<(axum::http::HeaderMap, axum::body::Bytes)>,
+) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let failures = Arc::new(std::sync::atomic::AtomicU32::new(failures));
+    let receiver = axum::Router::new().route(
+        "/hook",
+        axum::routing::post(
+            move |headers: axum::http::HeaderMap, body: axum::body::Bytes| async move {
+                tx.send((headers, body)).unwrap();
+                let failed = failures
+                    .fetch_update(
+                        std::sync::atomic::Ordering::SeqCst,
+                        std::sync::atomic::Ordering::SeqCst,
+                        |n| n.checked_sub(1),
+                    )
+                    .is_ok();
+                if failed {
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    axum::http::StatusCode::NO_CONTENT
+                }
+            },
+        ),
+    );
+
+    (format!("{}/hook", common::serve(receiver).await), rx)
+}
+
+/// Webhooks for [`WEBHOOK_APP_ID`], allowed to call `callback_url`.
+fn fixture_webhooks(callback_url: &str) -> Arc<Webhooks> {
+    // The receiver is plain `http`, which only non-production profiles accept.
+    let mut webhooks = Webhooks::from_config(
+        &json!({
+            WEBHOOK_APP_ID: {
+                "secrets": [STANDARD.encode(WEBHOOK_SECRET)],
+                "callback_urls": [callback_url],
+            }
+        })
+        .to_string(),
+        Environment::Development,
+    )
+    .unwrap();
+    webhooks.retry = RetryPolicy {
+        attempts: 3,
+        initial_backoff: Duration::from_millis(20),
+    };
+    Arc::new(webhooks)
+}
+
+async fn webhook_app(callback_url: &str) -> axum::Router {
+    common::test_app_with(Config {
+        webhooks: fixture_webhooks(callback_url),
+        ..Config::default()
+    })
+    .await
+}
+
+async fn next_delivery(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<(axum::http::HeaderMap, axum::body::Bytes)>,
+) -> (axum::http::HeaderMap, axum::body::Bytes) {
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("webhook was not delivered in time")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_webhook_delivers_signed_response() {
+    let (callback_url, mut rx) = webhook_receiver(0).await;
+    let app = webhook_app(&callback_url).await;
+
+    let (s, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "x", "payload": "y", "callback_url": callback_url, "app_id": WEBHOOK_APP_ID}),
+    )
+    .await;
+    assert_eq!(s, 200, "{b}");
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(common::get(&app, &format!("/request/{id}")).await.0, 200);
+
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "ri", "payload": "rp"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+
+    let (headers, body) = next_delivery(&mut rx).await;
+    assert_eq!(headers["bridge-request-id"], id.as_str());
+    let delivered: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(delivered, json!({"iv": "ri", "payload": "rp"}));
+
+    let signature = headers[webhook::SIGNATURE_HEADER].to_str().unwrap();
+    assert_eq!(
+        webhook::verify(
+            signature,
+            &id,
+            &body,
+            &[WEBHOOK_SECRET],
+            webhook::DEFAULT_TOLERANCE
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        webhook::verify(
+            signature,
+            &id,
+            &body,
+            &[b"another secret"],
+            webhook::DEFAULT_TOLERANCE
+        ),
+        Err(webhook::WebhookError::Mismatch)
+    );
+
+    // A delivered response is consumed, as if it had been polled.
+    let route = format!("/response/{id}");
+    for _ in 0..50 {
+        if common::get(&app, &route).await.0 == 404 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the delivered response was not consumed");
+}
+
+#[tokio::test]
+async fn test_webhook_retries_with_backoff() {
+    let (callback_url, mut rx) = webhook_receiver(2).await;
+    let app = webhook_app(&callback_url).await;
+
+    let id = fresh_id();
+    let (s, _) = common::post(
+        &app,
+        "/v2/request",
+        &json!({"request_id": id, "iv": "x", "payload": "y", "callback_url": callback_url, "app_id": WEBHOOK_APP_ID}),
+    )
+    .await;
+    assert_eq!(s, 200);
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "ri", "payload": "rp"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+
+    let mut bodies = Vec::new();
+    for _ in 0..3 {
+        bodies.push(next_delivery(&mut rx).await.1);
+    }
+    assert!(
+        bodies.windows(2).all(|w| w[0] == w[1]),
+        "retries resend the same body"
+    );
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), rx.recv())
+            .await
+            .is_err(),
+        "no retries after a 2xx"
+    );
+}
+
+#[tokio::test]
+async fn test_webhook_does_not_follow_redirects() {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let receiver = axum::Router::new()
+        .route(
+            "/hook",
+            axum::routing::post(|| async { axum::response::Redirect::temporary("/elsewhere") }),
+        )
+        .route(
+            "/elsewhere",
+            axum::routing::post(move || async move {
+                tx.send(()).unwrap();
+                axum::http::StatusCode::NO_CONTENT
+            }),
+        );
+    let callback_url = format!("{}/hook", common::serve(receiver).await);
+    let app = webhook_app(&callback_url).await;
+
+    let (s, b) = common::post(
+        &app,
+        "/request",
+        &json!({"iv": "x", "payload": "y", "callback_url": callback_url, "app_id": WEBHOOK_APP_ID}),
+    )
+    .await;
+    assert_eq!(s, 200, "{b}");
+    let id = serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (s, _) = common::put(
+        &app,
+        &format!("/response/{id}"),
+        &json!({"iv": "ri", "payload": "rp"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), rx.recv())
+            .await
+            .is_err(),
+        "the redirect was followed"
+    );
+    // Every attempt failed, so the response is still there to poll.
+    let (s, _) = common::get(&app, &format!("/response/{id}")).await;
+    assert_eq!(s, 200);
+}
+
+#[tokio::test]
+async fn test_callback_url_must_be_allowlisted() {
+    let (callback_url, _rx) = webhook_receiver(0).await;
+    let app = webhook_app(&callback_url).await;
+
+    let create = |body: Value| {
+        let app = app.clone();
+        async move { common::post(&app, "/request", &body).await.0 }
+    };
+    let base = json!({"iv": "x", "payload": "y"});
+    let with = |extra: Value| {
+        let mut body = base.clone();
+        body.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        body
+    };
+
+    assert_eq!(
+        create(with(json!({"callback_url": callback_url}))).await,
+        400,
+        "app_id is required"
+    );
+    assert_eq!(
+        create(with(
+            json!({"callback_url": format!("{callback_url}/other"), "app_id": WEBHOOK_APP_ID})
+        ))
+        .await,
+        400,
+        "the URL must be on the app's allowlist"
+    );
+    assert_eq!(
+        create(with(
+            json!({"callback_url": callback_url, "app_id": "app_unknown"})
+        ))
+        .await,
+        400
+    );
+    assert_eq!(
+        create(with(
+            json!({"callback_url": callback_url, "app_id": WEBHOOK_APP_ID, "max_responses": 2})
+        ))
+        .await,
+        400,
+        "webhooks deliver a single response"
+    );
+    assert_eq!(
+        create(with(
+            json!({"callback_url": callback_url, "app_id": WEBHOOK_APP_ID})
+        ))
+        .await,
+        200
+    );
+}
+
+// ---------------------------------------------------------------------------
+// Status notifications. Every status change is published on the notifier bus;
+// relayed through Redis, a change on one replica reaches subscribers on all.
+// ---------------------------------------------------------------------------
+
+/// The next status published for `request_id`, skipping other requests.
+async fn next_status(
+    rx: &mut tokio::sync::broadcast::Receiver<StatusEvent>,
+    request_id: &str,
+) -> RequestStatus {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let event = rx.recv().await.expect("notifier closed");
+            if event.request_id == request_id {
+                return event.status;
+            }
+        }
+    })
+    .await
+    .expect("status change was not published in time")
+}
+
+/// Create `request_id`, retrieve it, and respond to it.
+async fn run_request_flow(app: &axum::Router, request_id: &str) {
+    let body = json!({"iv": "x", "payload": "y", "request_id": request_id});
+    let (s, b) = common::post(app, "/request", &body).await;
+    assert_eq!(s, 200, "{b}");
+    let (s, _) = common::get(app, &format!("/request/{request_id}")).await;
+    assert_eq!(s, 200);
+    let (s, _) = common::put(
+        app,
+        &format!("/response/{request_id}"),
+        &json!({"iv": "r", "payload": "z"}),
+    )
+    .await;
+    assert_eq!(s, 201);
+}
+
+#[tokio::test]
+async fn test_status_changes_are_published() {
+    let notifier = Arc::new(Notifier::local());
+    let mut rx = notifier.subscribe();
+    let app = common::test_app_with(Config {
+        notifications: notifier,
+        ..Config::default()
+    })
+    .await;
+
+    let id = Uuid::new_v4().to_string();
+    run_request_flow(&app, &id).await;
+
+    assert_eq!(next_status(&mut rx, &id).await, RequestStatus::Initialized);
+    assert_eq!(next_status(&mut rx, &id).await, RequestStatus::Retrieved);
+    assert_eq!(next_status(&mut rx, &id).await, RequestStatus::Completed);
+}
+
+#[tokio::test]
+async fn test_status_changes_reach_other_replicas() {
+    let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+    let replica = || async {
+        Arc::new(
+            Notifier::redis(redis::Client::open(url.as_str()).unwrap())
+                .await
+                .unwrap(),
+        )
+    };
+    let (publisher, listener) = (replica().await, replica().await);
+    let mut rx = listener.subscribe();
+    let app = common::test_app_with(Config {
+        notifications: publisher,
+        ..Config::default()
+    })
+    .await;
+
+    let id = Uuid::new_v4().to_string();
+    run_request_flow(&app, &id).await;
+
+    assert_eq!(next_status(&mut rx, &id).await, RequestStatus::Initialized);
+    assert_eq!(next_status(&mut rx, &id).await, RequestStatus::Retrieved);
+    assert_eq!(next_status(&mut rx, &id).await, RequestStatus::Completed);
+}
+
+// ---------------------------------------------------------------------------
+// Audit events. Every transition is written to the audit sink with a hashed
+// request ID, the route and the payload size, but never the payload itself.
+// ---------------------------------------------------------------------------
+
+const AUDIT_HASH_KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+#[tokio::test]
+async fn test_audit_events_are_written_to_a_file() {
+    let id = Uuid::new_v4().to_string();
+    let path = std::env::temp_dir().join(format!("bridge-audit-{}.jsonl", Uuid::new_v4()));
+    let audit =
+        Audit::from_config(&format!("file:{}", path.display()), Some(AUDIT_HASH_KEY)).unwrap();
+    let request_hash = audit.request_hash(&id);
+    let app = common::test_app_with(Config {
+        audit: Arc::new(audit),
+        ..Config::default()
+    })
+    .await;
+
+    run_request_flow(&app, &id).await;
+
+    // Lines are written in the background.
+    let mut written = String::new();
+    for _ in 0..50 {
+        written = std::fs::read_to_string(&path).unwrap();
+        if written.lines().count() >= 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let events: Vec<AuditEvent> = written
+        .lines()
+        .map(|line| {
+            assert!(!line.contains(&id) && !line.contains("\"z\""), "{line}");
+            serde_json::from_str(line).unwrap()
+        })
+        .collect();
+    std::fs::remove_file(&path).unwrap();
+
+    let transitions: Vec<_> = events
+        .iter()
+        .map(|e| (e.from, e.to, e.route.as_str(), e.payload_bytes))
+        .collect();
+    assert_eq!(
+        transitions,
+        [
+            (None, RequestStatus::Initialized, "POST /request", Some(1)),
+            (
+                Some(RequestStatus::Initialized),
+                RequestStatus::Retrieved,
+                "GET /request/:request_id",
+                Some(1)
+            ),
+            (
+                Some(RequestStatus::Retrieved),
+                RequestStatus::Completed,
+                "PUT /response/:request_id",
+                Some(1)
+            ),
+        ]
+    );
+    for event in &events {
+        assert_eq!(event.request_hash, request_hash);
+        assert_eq!(event.client_ip, IpClass::Unknown);
+    }
+}
+
+#[tokio::test]
+async fn test_audit_events_are_added_to_a_redis_stream() {
+    let stream = format!("audit:{}", Uuid::new_v4());
+    let audit = Audit::from_config(&format!("redis:{stream}"), Some(AUDIT_HASH_KEY)).unwrap();
+    let app = common::test_app_with(Config {
+        audit: Arc::new(audit),
+        ..Config::default()
+    })
+    .await;
+
+    let id = Uuid::new_v4().to_string();
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::POST,
+        "/v2/request",
+        Some((
+            "application/json",
+            json!({"iv": "x", "payload": "eQ==", "request_id": id})
+                .to_string()
+                .into_bytes(),
+        )),
+        &[("x-forwarded-for", "203.0.113.7, 10.0.0.1")],
+    )
+    .await;
+    assert_eq!(s, 200);
+
+    let mut redis = common::redis_connection().await;
+    let entries: Vec<(String, Vec<(String, String)>)> = redis::cmd("XRANGE")
+        .arg(&stream)
+        .arg("-")
+        .arg("+")
+        .query_async(&mut redis)
+        .await
+        .unwrap();
+    let _: () = redis.del(&stream).await.unwrap();
+
+    assert_eq!(entries.len(), 1);
+    let (field, event) = &entries[0].1[0];
+    assert_eq!(field, "event");
+    let event: AuditEvent = serde_json::from_str(event).unwrap();
+    assert_eq!(event.route, "POST /v2/request");
+    assert_eq!(event.to, RequestStatus::Initialized);
+    assert_eq!(event.payload_bytes, Some(1));
+    assert_eq!(event.client_ip, IpClass::Public);
+}
+
+// ---------------------------------------------------------------------------
+// CORS policy. Origins, per-group methods, max-age and exposed headers come
+// from config; the default allows any origin.
+// ---------------------------------------------------------------------------
+
+async fn preflight(
+    app: &axum::Router,
+    route: &str,
+    origin: &str,
+    method: &str,
+) -> axum::http::HeaderMap {
+    let (s, headers, _) = common::send_raw(
+        app,
+        Method::OPTIONS,
+        route,
+        None,
+        &[
+            ("origin", origin),
+            ("access-control-request-method", method),
+        ],
+    )
+    .await;
+    assert_eq!(s, 200);
+    headers
+}
+
+#[tokio::test]
+async fn test_cors_allows_any_origin_by_default() {
+    let app = common::test_app().await;
+
+    let headers = preflight(&app, "/response/abc", "https://anywhere.example", "POST").await;
+    assert_eq!(headers["access-control-allow-origin"], "*");
+    assert!(headers["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("POST"));
+}
+
+#[tokio::test]
+async fn test_cors_exposes_payload_headers() {
+    let app = common::test_app().await;
+    let exposed = |headers: &axum::http::HeaderMap| -> HashSet<String> {
+        headers["access-control-expose-headers"]
+            .to_str()
+            .unwrap()
+            .split(',')
+            .map(|h| h.trim().to_string())
+            .collect()
+    };
+    let origin = [("origin", "https://anywhere.example")];
+
+    let (_, headers, _) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/request/{}", fresh_id()),
+        None,
+        &origin,
+    )
+    .await;
+    let request = exposed(&headers);
+    for header in [
+        "bridge-iv",
+        "idkit-flow-id",
+        "bridge-lease-token",
+        "bridge-capabilities",
+    ] {
+        assert!(
+            request.contains(header),
+            "{header} missing from {request:?}"
+        );
+    }
+
+    let (_, headers, _) = common::send_raw(
+        &app,
+        Method::GET,
+        &format!("/response/{}", fresh_id()),
+        None,
+        &origin,
+    )
+    .await;
+    let response = exposed(&headers);
+    for header in ["bridge-iv", "bridge-status"] {
+        assert!(
+            response.contains(header),
+            "{header} missing from {response:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_cors_policy_from_config() {
+    let cors = CorsPolicy::from_config(
+        Some("https://world.org, https://*.world.org"),
+        Some("response:GET,POST"),
+        Some("600"),
+        Some("x-trace-id"),
+    )
+    .unwrap();
+    let app = common::test_app_with(Config {
+        cors,
+        ..Config::default()
+    })
+    .await;
+
+    let headers = preflight(&app, "/v2/response/abc", "https://app.world.org", "GET").await;
+    assert_eq!(
+        headers["access-control-allow-origin"],
+        "https://app.world.org"
+    );
+    assert_eq!(headers["access-control-allow-methods"], "GET,POST");
+    assert_eq!(headers["access-control-max-age"], "600");
+
+    let headers = preflight(&app, "/request/abc", "https://evil.org", "GET").await;
+    assert!(!headers.contains_key("access-control-allow-origin"));
+
+    let (_, headers, _) = common::send_raw(
+        &app,
+        Method::GET,
+        "/request/abcdefghijklmnopq",
+        None,
+        &[("origin", "https://world.org")],
+    )
+    .await;
+    assert_eq!(headers["access-control-allow-origin"], "https://world.org");
+    let exposed = headers["access-control-expose-headers"].to_str().unwrap();
+    for header in ["bridge-capabilities", "bridge-lease-token", "x-trace-id"] {
+        assert!(exposed.contains(header), "{exposed}");
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Environment profiles
+//
+// The profile decides which environment-specific routes and methods exist,
+// and `/` reports it with its features.
+// ----------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_production_enables_no_features() {
+    let app = common::test_app().await;
+
+    let (_, body) = common::get(&app, "/").await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["environment"], "production");
+    assert_eq!(json["features"], json!([]));
+
+    let body = json!({"iv": "a", "payload": "b"});
+    let (s, _) = common::put(&app, &format!("/request/{}", Uuid::new_v4()), &body).await;
+    assert_eq!(s, 405);
+    let (s, _) = common::put(&app, &format!("/v2/request/{}", Uuid::new_v4()), &body).await;
+    assert_eq!(s, 405);
+
+    let headers = preflight(&app, "/response/abc", "https://anywhere.example", "PUT").await;
+    assert!(!headers["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("PUT"));
+}
+
+#[tokio::test]
+async fn test_staging_enables_its_features() {
+    let app = common::test_app_with(Config {
+        environment: Environment::Staging,
+        ..Config::default()
+    })
+    .await;
+
+    let (_, body) = common::get(&app, "/").await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["environment"], "staging");
+    assert_eq!(json["features"], json!(["staging-upsert"]));
+
+    let body = json!({"iv": "a", "payload": "b"});
+    let (s, _) = common::put(&app, &format!("/request/{}", Uuid::new_v4()), &body).await;
+    assert_eq!(s, 201);
+    let (s, _) = common::put(&app, &format!("/v2/request/{}", Uuid::new_v4()), &body).await;
+    assert_eq!(s, 201);
+
+    // Debug features stay off until opted into.
+    let headers = preflight(&app, "/response/abc", "https://anywhere.example", "PUT").await;
+    assert!(!headers["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("PUT"));
+}
+
+#[tokio::test]
+async fn test_debug_features_need_an_opt_in() {
+    let app = common::test_app_with(Config {
+        environment: Environment::Staging,
+        debug_features: true,
+        ..Config::default()
+    })
+    .await;
+
+    let (_, body) = common::get(&app, "/").await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        json["features"],
+        json!(["staging-upsert", "simulator-put", "debug-endpoints"])
+    );
+
+    let headers = preflight(&app, "/response/abc", "https://anywhere.example", "PUT").await;
+    assert!(headers["access-control-allow-methods"]
+        .to_str()
+        .unwrap()
+        .contains("PUT"));
+
+    // Production allows none, opt-in or not.
+    let app = common::test_app_with(Config {
+        debug_features: true,
+        simulator_token: Some(SIMULATOR_TOKEN.to_string()),
+        ..Config::default()
+    })
+    .await;
+    let (_, body) = common::get(&app, "/").await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["features"], json!([]));
+    let (s, _) = simulate(
+        &app,
+        Method::PUT,
+        &format!("/simulator/request/{}/status", fresh_id()),
+        &json!({"status": "retrieved"}),
+    )
+    .await;
+    assert_eq!(s, 404);
+}
+
+// ----------------------------------------------------------------------------
+// Simulator
+//
+// Token-protected controls over the store, only mounted in non-production
+// profiles: force a status, inject a response, fast-forward TTLs.
+// ----------------------------------------------------------------------------
+
+const SIMULATOR_TOKEN: &str = "simulator-token-of-at-least-32-chars";
+
+async fn simulator_app() -> axum::Router {
+    common::test_app_with(Config {
+        environment: Environment::Staging,
+        debug_features: true,
+        simulator_token: Some(SIMULATOR_TOKEN.to_string()),
+        ..Config::default()
+    })
+    .await
+}
+
+async fn simulate(app: &axum::Router, method: Method, route: &str, body: &Value) -> (u16, Value) {
+    let authorization = format!("Bearer {SIMULATOR_TOKEN}");
+    let (s, _, bytes) = common::send_raw(
+        app,
+        method,
+        route,
+        Some(("application/json", serde_json::to_vec(body).unwrap())),
+        &[("authorization", &authorization)],
+    )
+    .await;
+    (s, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+}
+
+async fn create_request(app: &axum::Router) -> String {
+    let (s, body) = common::post(app, "/request", &json!({"iv": "a", "payload": "b"})).await;
+    assert_eq!(s, 200);
+    let json: Value = serde_json::from_str(&body).unwrap();
+    json["request_id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_simulator_requires_profile_and_token() {
+    let route = format!("/simulator/request/{}/status", Uuid::new_v4());
+    let body = json!({"status": "retrieved"});
+
+    let production = common::test_app_with(Config {
+        simulator_token: Some(SIMULATOR_TOKEN.to_string()),
+        ..Config::default()
+    })
+    .await;
+    let (s, _) = simulate(&production, Method::PUT, &route, &body).await;
+    assert_eq!(s, 404);
+
+    let app = simulator_app().await;
+    let (s, _) = common::put(&app, &route, &body).await;
+    assert_eq!(s, 401);
+    let (s, _, _) = common::send_raw(
+        &app,
+        Method::PUT,
+        &route,
+        Some(("application/json", serde_json::to_vec(&body).unwrap())),
+        &[(
+            "authorization",
+            "Bearer simulator-token-of-at-least-32-chart",
+        )],
+    )
+    .await;
+    assert_eq!(s, 401);
+
+    let (s, _) = simulate(&app, Method::PUT, &route, &body).await;
+    assert_eq!(s, 204);
+}
+
+#[tokio::test]
+async fn test_simulator_forces_status_and_injects_response() {
+    let notifications = Arc::new(Notifier::local());
+    let mut events = notifications.subscribe();
+    let app = common::test_app_with(Config {
+        environment: Environment::Staging,
+        debug_features: true,
+        simulator_token: Some(SIMULATOR_TOKEN.to_string()),
+        notifications,
+        ..Config::default()
+    })
+    .await;
+    let request_id = create_request(&app).await;
+    assert_eq!(
+        next_status(&mut events, &request_id).await,
+        RequestStatus::Initialized
+    );
+
+    let (s, _) = simulate(
+        &app,
+        Method::PUT,
+        &format!("/simulator/request/{request_id}/status"),
+        &json!({"status": "retrieved"}),
+    )
+    .await;
+    assert_eq!(s, 204);
+    assert_eq!(
+        next_status(&mut events, &request_id).await,
+        RequestStatus::Retrieved
+    );
+    let (_, body) = common::get(&app, &format!("/response/{request_id}")).await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["status"], "retrieved");
+    assert!(json["response"].is_null());
+
+    // Works for IDs the bridge has never seen, too.
+    for id in [request_id.clone(), Uuid::new_v4().to_string()] {
+        let (s, _) = simulate(
+            &app,
+            Method::PUT,
+            &format!("/simulator/response/{id}"),
+            &json!({"iv": "c", "payload": "d"}),
+        )
+        .await;
+        assert_eq!(s, 201);
+        assert_eq!(
+            next_status(&mut events, &id).await,
+            RequestStatus::Completed
+        );
+
+        let (_, body) = common::get(&app, &format!("/response/{id}")).await;
+        let json: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["status"], "completed");
+        assert_eq!(json["response"], json!({"iv": "c", "payload": "d"}));
+    }
+}
+
+#[tokio::test]
+async fn test_simulator_fast_forwards_ttls() {
+    let app = simulator_app().await;
+    let request_id = create_request(&app).await;
+    let route = format!("/simulator/request/{request_id}/fast-forward");
+
+    let (s, json) = simulate(&app, Method::POST, &route, &json!({"seconds": 60})).await;
+    assert_eq!(s, 200);
+    assert_eq!(json, json!({"expired": 0, "remaining": 2}));
+
+    let mut redis = common::redis_connection().await;
+    let ttl: i64 = redis.ttl(format!("req:{request_id}")).await.unwrap();
+    assert!(ttl <= 840, "{ttl}");
+
+    let (s, json) = simulate(&app, Method::POST, &route, &json!({"seconds": 900})).await;
+    assert_eq!(s, 200);
+    assert_eq!(json, json!({"expired": 2, "remaining": 0}));
+
+    let (s, _) = common::get(&app, &format!("/request/{request_id}")).await;
+    assert_eq!(s, 404);
+}
+
+#[tokio::test]
+async fn test_simulator_fast_forward_expires_chunks() {
+    let app = simulator_app().await;
+    let payload = vec![7u8; 2 * CHUNK];
+    let (upload_id, request_id) = start_upload(
+        &app,
+        &json!({"target": "request", "iv": "AAECAwQFBgcICQoL", "size": payload.len(), "chunk_size": CHUNK}),
+    )
+    .await;
+    for (index, chunk) in payload.chunks(CHUNK).enumerate() {
+        assert_eq!(put_chunk(&app, &upload_id, index, chunk).await, 201);
+    }
+    let (s, _) = common::post(&app, &format!("/upload/{upload_id}/finalize"), &json!({})).await;
+    assert_eq!(s, 201);
+
+    let mut redis = common::redis_connection().await;
+    let chunks = [
+        format!("chunk:{upload_id}:0"),
+        format!("chunk:{upload_id}:1"),
+    ];
+    let exists: u32 = redis.exists(&chunks).await.unwrap();
+    assert_eq!(exists, 2);
+
+    let route = format!("/simulator/request/{request_id}/fast-forward");
+    let (s, _) = simulate(&app, Method::POST, &route, &json!({"seconds": 900})).await;
+    assert_eq!(s, 200);
+
+    let exists: u32 = redis.exists(&chunks).await.unwrap();
+    assert_eq!(exists, 0);
+}
+
+#[tokio::test]
+async fn test_simulator_injects_like_a_real_response() {
+    let (callback_url, mut rx) = webhook_receiver(0).await;
+    let app = common::test_app_with(Config {
+        environment: Environment::Staging,
+        debug_features: true,
+        simulator_token: Some(SIMULATOR_TOKEN.to_string()),
+        webhooks: fixture_webhooks(&callback_url),
+        ..Config::default()
+    })
+    .await;
+    let inject = |id: String| {
+        let app = app.clone();
+        async move {
+            let route = format!("/simulator/response/{id}");
+            simulate(
+                &app,
+                Method::PUT,
+                &route,
+                &json!({"iv": "c", "payload": "d"}),
+            )
+            .await
+        }
+    };
+    let create = |body: Value| {
+        let app = app.clone();
+        async move {
+            let (s, b) = common::post(&app, "/request", &body).await;
+            assert_eq!(s, 200, "{b}");
+            serde_json::from_str::<Value>(&b).unwrap()["request_id"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        }
+    };
+
+    // Fan-in requests collect injected responses until the quota is met.
+    let id = create(json!({"iv": "x", "payload": "y", "max_responses": 2})).await;
+    assert_eq!(inject(id.clone()).await.0, 201);
+    assert_eq!(inject(id.clone()).await.0, 201);
+    assert_eq!(inject(id.clone()).await.0, 409);
+    let (_, body) = common::get(&app, &format!("/response/{id}")).await;
+    let json: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["status"], "completed");
+    assert_eq!(json["responses"].as_array().unwrap().len(), 2);
+
+    // Registered callbacks get injected responses delivered.
+    let id = create(
+        json!({"iv": "x", "payload": "y", "callback_url": callback_url, "app_id": WEBHOOK_APP_ID}),
+    )
+    .await;
+    assert_eq!(inject(id.clone()).await.0, 201);
+    let (headers, body) = next_delivery(&mut rx).await;
+    assert_eq!(headers["bridge-request-id"], id.as_str());
+    let delivered: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(delivered, json!({"iv": "c", "payload": "d"}));
+}
+
+// ----------------------------------------------------------------------------
+// Request metadata
+//
+// An optional plaintext `metadata` object on `POST /request`, stored next to
+// the status for per-app reporting and policies.
+// ----------------------------------------------------------------------------
+
+#[tokio::test]
+async fn test_request_metadata_is_stored_with_the_status() {
+    let app = common::test_app().await;
+    let mut redis = common::redis_connection().await;
+    let action = format!("0x{}", "0f".repeat(32));
+
+    for route in ["/request", "/v2/request"] {
+        let metadata =
+            json!({"app_id": "app_staging_1234abcd", "action": action, "client": "idkit_js"});
+        let body = json!({"iv": "a", "payload": "b", "metadata": metadata});
+        let (s, body) = common::post(&app, route, &body).await;
+        assert_eq!(s, 200, "{body}");
+        let request_id = serde_json::from_str::<Value>(&body).unwrap()["request_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let stored: String = redis.get(format!("req:meta:{request_id}")).await.unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&stored).unwrap(), metadata);
+        let ttl: i64 = redis.ttl(format!("req:meta:{request_id}")).await.unwrap();
+        assert!(ttl > 0);
+    }
+
+    // Without metadata, nothing is stored.
+    let request_id = create_request(&app).await;
+    let stored: Option<String> = redis.get(format!("req:meta:{request_id}")).await.unwrap();
+    assert_eq!(stored, None);
+}
+
+#[tokio::test]
+async fn test_request_metadata_schema_is_strict() {
+    let app = common::test_app().await;
+
+    for metadata in [
+        json!({"app_id": "app_1", "client": "rust", "signal": "x"}),
+        json!({"app_id": "app_1", "client": "browser"}),
+        json!({"app_id": "1234", "client": "rust"}),
+        json!({"app_id": "app_1", "action": "verify", "client": "rust"}),
+    ] {
+        let body = json!({"iv": "a", "payload": "b", "metadata": metadata});
+        let (s, _) = common::post(&app, "/v2/request", &body).await;
+        assert_eq!(s, 400, "{metadata}");
+    }
+}
+
 /// Test OpenAPI documentation endpoint exists
 #[tokio::test]
 async fn test_openapi_endpoint() {